@@ -0,0 +1,200 @@
+// async_io.rs
+//! `AsyncRead` から読みながら照合・置換する（feature `tokio`）。
+//!
+//! 読んだ分を `match_sink` と同じ `Scanner` に足していくので、入力全体を集めない。
+//! 一致の選び方も `match_sink` と同じで、`match_kind` によらず最も左で最長のもの。
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::Regex;
+use crate::span::Span;
+use crate::stream::Scanner;
+
+// 1回の read で受け取る大きさ
+const CHUNK: usize = 8 * 1024;
+
+impl Regex {
+    /// `reader` を終わりまで読み、一致が確定するたびに `on_match(位置, 一致したバイト列)` を呼ぶ。
+    /// 位置は読んだバイトの通算オフセット。
+    ///
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let mut found = Vec::new();
+    /// re.scan_async(&b"id=42; n=7"[..], |span, _: &[u8]| found.push(span.range()))
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(found, vec![3..5, 9..10]);
+    /// # });
+    /// ```
+    ///
+    /// # Errors
+    /// 読み込みの失敗と、スレッド数が `thread_limit` を超えた場合（`io::ErrorKind::Other`）。
+    pub async fn scan_async<R, F>(&self, mut reader: R, mut on_match: F) -> io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(Span, &[u8]),
+    {
+        let mut scan = Scanner::default();
+        let mut chunk = vec![0; CHUNK];
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            scan.push(&chunk[..n]);
+            for m in scan.advance(self, n == 0).map_err(io::Error::other)? {
+                on_match(m, scan.bytes(m));
+            }
+            scan.trim();
+            if n == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// `reader` を読みながら、一致した部分を `rep` に置き換えて `writer` に書く。置き換えた数を返す。
+    /// `rep` はそのままのバイト列で、`$1` などは展開しない（キャプチャは追わない）。
+    /// 一致しかけている部分は確定するまで書き出さない。最後に `writer` を flush する。
+    ///
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// use my_regex::Regex;
+    /// let re = Regex::new("secret-[0-9]+").unwrap();
+    /// let mut out = Vec::new();
+    /// let n = re
+    ///     .replace_async(&b"key=secret-123, next=secret-9"[..], &mut out, b"***")
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!((n, out.as_slice()), (2, &b"key=***, next=***"[..]));
+    /// # });
+    /// ```
+    ///
+    /// # Errors
+    /// 読み書きの失敗と、スレッド数が `thread_limit` を超えた場合（`io::ErrorKind::Other`）。
+    pub async fn replace_async<R, W>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+        rep: &[u8],
+    ) -> io::Result<usize>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut scan = Scanner::default();
+        let mut chunk = vec![0; CHUNK];
+        let mut out = Vec::new();
+        // ここまでは書き出し済み（入力全体でのオフセット）
+        let mut written = 0;
+        let mut count = 0;
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            scan.push(&chunk[..n]);
+            for m in scan.advance(self, n == 0).map_err(io::Error::other)? {
+                out.extend_from_slice(scan.bytes(Span::new(written, m.start)));
+                out.extend_from_slice(rep);
+                written = m.end;
+                count += 1;
+            }
+            // 一致が始まりうる位置より前は確定しているので書き出してよい
+            let safe = if n == 0 { scan.end() } else { scan.settled() };
+            if safe > written {
+                out.extend_from_slice(scan.bytes(Span::new(written, safe)));
+                written = safe;
+            }
+            scan.trim();
+            writer.write_all(&out).await?;
+            out.clear();
+            if n == 0 {
+                writer.flush().await?;
+                return Ok(count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod async_io_tests {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    use crate::{Regex, RegexBuilder, Span};
+
+    // 1回の read で `step` バイトずつしか返さない reader（チャンクの切れ目を試す）
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let n = self.step.min(self.data.len()).min(buf.remaining());
+            buf.put_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn block_on<T>(f: impl Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn scan_agrees_with_find_iter() {
+        let re = Regex::new("a+|b").unwrap();
+        let hay = "xaaybaa";
+        let want: Vec<Span> = re.find_iter(hay).map(|m| m.span()).collect();
+        for step in [1, 2, 100] {
+            let mut got = Vec::new();
+            let reader = Trickle {
+                data: hay.as_bytes(),
+                step,
+            };
+            block_on(re.scan_async(reader, |s, _: &[u8]| got.push(s))).unwrap();
+            assert_eq!(got, want, "by {step}");
+        }
+    }
+
+    #[test]
+    fn replace_across_chunk_boundaries() {
+        let re = Regex::new("ab+").unwrap();
+        let hay = b"abbxabyab";
+        for step in [1, 2, 3, 100] {
+            let mut out = Vec::new();
+            let reader = Trickle { data: hay, step };
+            let n = block_on(re.replace_async(reader, &mut out, b"-")).unwrap();
+            assert_eq!((n, out.as_slice()), (3, &b"-x-y-"[..]), "by {step}");
+        }
+        // 一致が無ければそのまま写す
+        let mut out = Vec::new();
+        let n = block_on(re.replace_async(&b"xyz"[..], &mut out, b"-")).unwrap();
+        assert_eq!((n, out.as_slice()), (0, &b"xyz"[..]));
+    }
+
+    #[test]
+    fn replace_empty_matches() {
+        let re = Regex::new("x*").unwrap();
+        let mut out = Vec::new();
+        let n = block_on(re.replace_async(&b"axxb"[..], &mut out, b"-")).unwrap();
+        assert_eq!((n, out.as_slice()), (4, &b"-a--b-"[..]));
+    }
+
+    #[test]
+    fn thread_limit_is_an_io_error() {
+        let re = RegexBuilder::new("(a|aa)*b")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        let err = block_on(re.scan_async(&b"aaaa"[..], |_, _: &[u8]| {})).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+}
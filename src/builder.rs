@@ -0,0 +1,242 @@
+// builder.rs
+use std::collections::HashMap;
+
+use crate::Regex;
+use crate::class::{ClassSet, Equivalences};
+use crate::error::Error;
+
+/// 複数の一致候補があるときにどれを報告するか。
+/// 全エンジン共通の約束で、既定は `LeftmostLongest`。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MatchKind {
+    /// Perl 系と同じ優先順位: `|` は左の枝、量指定子は貪欲側を優先し、
+    /// 最初に受理した候補を採る。
+    LeftmostFirst,
+    /// 最長の一致を採る。同じ長さならグループの開始が遅い（前段が貪欲な）方、
+    /// 次に終端が遅い方、最後に番号の小さいグループが参加している方。
+    #[default]
+    LeftmostLongest,
+}
+
+/// コンパイル時オプション
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    pub captures: bool,
+    pub literal: bool,
+    pub whole_word: bool,
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub dot_matches_new_line: bool,
+    pub equivalences: Equivalences,
+    pub thread_limit: Option<usize>,
+    pub max_haystack_len: Option<usize>,
+    pub repetition_limit: usize,
+    // \p{name} で参照できる利用者定義の文字集合
+    pub classes: HashMap<String, ClassSet>,
+    pub match_kind: MatchKind,
+    // 計数を全体の登録簿に載せるときの名前
+    #[cfg(feature = "metrics")]
+    pub metrics_name: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            captures: true,
+            literal: false,
+            whole_word: false,
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            equivalences: Equivalences::default(),
+            thread_limit: None,
+            max_haystack_len: None,
+            repetition_limit: 1000,
+            classes: HashMap::new(),
+            match_kind: MatchKind::default(),
+            #[cfg(feature = "metrics")]
+            metrics_name: None,
+        }
+    }
+}
+
+impl Config {
+    /// 利用者の同一視に、`case_insensitive` なら ASCII の大文字・小文字の組を足したもの
+    pub(crate) fn folded_equivalences(&self) -> Equivalences {
+        self.equivalences_for(self.case_insensitive)
+    }
+
+    /// `equivalent_bytes` の表に、`case_insensitive` なら ASCII の大文字・小文字を足したもの
+    pub(crate) fn equivalences_for(&self, case_insensitive: bool) -> Equivalences {
+        let mut eq = self.equivalences.clone();
+        if case_insensitive {
+            for b in b'a'..=b'z' {
+                let upper = b.to_ascii_uppercase() as u32;
+                eq.add(&ClassSet::from_ranges([
+                    (b as u32, b as u32),
+                    (upper, upper),
+                ]));
+            }
+        }
+        eq
+    }
+}
+
+/// オプション付きで `Regex` を組み立てる。
+///
+/// ```
+/// use my_regex::RegexBuilder;
+/// let re = RegexBuilder::new(r"(\w+)@(\w+)").captures(false).build().unwrap();
+/// assert!(re.is_match("me@host"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RegexBuilder {
+    pattern: String,
+    config: Config,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            config: Config::default(),
+        }
+    }
+
+    /// false にするとキャプチャ用の遷移を一切持たないプログラムを作る。
+    /// `is_match` だけ使う場合、グループの多いパターンでもグループ無しと同じコストになる。
+    /// このとき `captures` は全体一致（`[0]`）のみを返す。
+    pub fn captures(&mut self, yes: bool) -> &mut Self {
+        self.config.captures = yes;
+        self
+    }
+
+    /// true にするとパターン全体を固定文字列として扱う（メタ文字も解釈しない）。
+    /// エスケープしてからコンパイルするのと同じ意味だが、照合はバイト比較で済ませる。
+    pub fn literal(&mut self, yes: bool) -> &mut Self {
+        self.config.literal = yes;
+        self
+    }
+
+    /// true にするとパターン全体の前後に単語境界を要求する（grep の `-w` 相当）。
+    /// パターン文字列を書き換えるのではなく、コンパイル時に境界判定を前後へ連接する。
+    pub fn whole_word(&mut self, yes: bool) -> &mut Self {
+        self.config.whole_word = yes;
+        self
+    }
+
+    /// 互いに同一視するバイトの組を追加する（リテラルと文字クラスの両方に効く）。
+    /// 何度でも呼べ、共通のバイトを持つ組はひとつにまとめられる。
+    /// エンジンはバイト単位なので、対象は1バイト文字に限られる。
+    ///
+    /// ```
+    /// use my_regex::RegexBuilder;
+    /// let re = RegexBuilder::new("colou?r")
+    ///     .equivalent_bytes(b"oO0")
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(re.is_match("c0lOur"));
+    /// ```
+    pub fn equivalent_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        let set = ClassSet::from_ranges(bytes.iter().map(|&b| (b as u32, b as u32)));
+        self.config.equivalences.add(&set);
+        self
+    }
+
+    /// true にすると ASCII の英字の大文字・小文字を同一視する。
+    /// `equivalent_bytes` で各組を足すのと同じで、それ以外の文字は区別したまま。
+    /// パターン先頭の `(?i)` でも立つ。
+    ///
+    /// ```
+    /// use my_regex::RegexBuilder;
+    /// let re = RegexBuilder::new("GET /[a-z]+")
+    ///     .case_insensitive(true)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(re.is_match("get /Index"));
+    /// ```
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.config.case_insensitive = yes;
+        self
+    }
+
+    /// true にすると `^` / `$` が各行の先頭・末尾（`\n` の直後・直前）でも成り立つ。
+    /// false（既定）なら入力の先頭・末尾だけで、`\A` / `\z` と同じ。パターン先頭の `(?m)` でも立つ。
+    ///
+    /// ```
+    /// use my_regex::RegexBuilder;
+    /// let re = RegexBuilder::new("^[a-z]+$").multi_line(true).build().unwrap();
+    /// let words: Vec<&str> = re.find_iter("foo\nbar baz\nqux").map(|m| m.as_str()).collect();
+    /// assert_eq!(words, vec!["foo", "qux"]);
+    /// ```
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.config.multi_line = yes;
+        self
+    }
+
+    /// true にすると `.` が `\n` にも一致する（任意の1バイト）。
+    /// false（既定）なら `\n` 以外の1バイトで、`[^\n]` と同じ。パターン先頭の `(?s)` でも立つ。
+    ///
+    /// ```
+    /// use my_regex::RegexBuilder;
+    /// assert!(!RegexBuilder::new("a.b").build().unwrap().is_match("a\nb"));
+    /// let re = RegexBuilder::new("a.b").dot_matches_new_line(true).build().unwrap();
+    /// assert!(re.is_match("a\nb"));
+    /// ```
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut Self {
+        self.config.dot_matches_new_line = yes;
+        self
+    }
+
+    /// NFA シミュレーションで同時に持てるスレッド数の上限。
+    /// 超えると `try_*` 系は `MatchError::TooManyThreads` を返す
+    /// （失敗を返さない `is_match` 等はパニックする）。
+    /// 病的なパターンと入力の組からサービスを守るためのもの。
+    pub fn thread_limit(&mut self, limit: usize) -> &mut Self {
+        self.config.thread_limit = Some(limit);
+        self
+    }
+
+    /// 受け付ける入力の最大バイト長。超えた入力は読まずに
+    /// `MatchError::HaystackTooLong` で断る（失敗を返さない API はパニックする）。
+    /// 入力サイズの制限を呼び出し側ごとではなくライブラリの入口で守るためのもの。
+    pub fn max_haystack_len(&mut self, limit: usize) -> &mut Self {
+        self.config.max_haystack_len = Some(limit);
+        self
+    }
+
+    /// `{m,n}` に書ける回数の上限（既定 1000）。`{m,}` は `m` を見る。
+    /// 入れ子の `{m,n}` は外側と内側の回数の積で見る（`(a{100}){20}` は 2000）。
+    /// 超えると `ErrorKind::RepetitionTooLarge`。回数分の状態を作るので、
+    /// 信頼できないパターンでメモリを使い切られないようにするためのもの。
+    pub fn repetition_limit(&mut self, limit: usize) -> &mut Self {
+        self.config.repetition_limit = limit;
+        self
+    }
+
+    /// 文字集合に名前を付け、パターンから `\p{name}`（補集合は `\P{name}`）で使えるようにする。
+    /// 名前は英数字と `_`。同じ名前をもう一度定義すると上書きする。
+    /// 未定義の名前は `Feature::UnicodeClass` の未対応エラーになる。
+    pub fn define_class(&mut self, name: &str, set: ClassSet) -> &mut Self {
+        self.config.classes.insert(name.to_string(), set);
+        self
+    }
+
+    /// 一致の選び方（`MatchKind`）を指定する
+    pub fn match_kind(&mut self, kind: MatchKind) -> &mut Self {
+        self.config.match_kind = kind;
+        self
+    }
+
+    /// 計数に名前を付けて `my_regex::metrics` の登録簿に載せる（feature `metrics`）。
+    /// 名前が無くても `Regex::stats` では読める。
+    #[cfg(feature = "metrics")]
+    pub fn metrics_name(&mut self, name: &str) -> &mut Self {
+        self.config.metrics_name = Some(name.to_string());
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, Error> {
+        Regex::with_config(&self.pattern, &self.config)
+    }
+}
@@ -0,0 +1,379 @@
+// captures.rs
+//! `Regex::captures` の結果。グループごとの位置を持ち、必要なときに切り出す。
+use std::collections::HashMap;
+use std::iter::FusedIterator;
+use std::ops::Index;
+use std::sync::Arc;
+
+use crate::search::Cursor;
+use crate::span::Span;
+use crate::{GroupInfo, Match, Regex, search_failed};
+
+/// 一致したときの各グループの位置。`[0]` は一致全体、`[g]` はグループ g。
+///
+/// ```
+/// use my_regex::Regex;
+/// let re = Regex::new(r"(\w+)=(\d+)?").unwrap();
+/// let caps = re.captures("key=").unwrap();
+/// assert_eq!(caps.len(), 3);
+/// assert_eq!(&caps[1], "key");
+/// assert_eq!(caps.get(1).unwrap().range(), 0..3);
+/// assert!(caps.get(2).is_none());
+///
+/// let re = Regex::new(r"(?<key>\w+)=(?<value>\w+)").unwrap();
+/// let caps = re.captures("a=1").unwrap();
+/// assert_eq!(&caps["value"], "1");
+/// assert_eq!(caps.name("key").unwrap().start(), 0);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Captures<'h> {
+    hay: &'h str,
+    // [g] = グループ g の (開始, 終端)。参加しなかったグループは None
+    slots: Vec<Option<(usize, usize)>>,
+    // 名前からグループ番号を引くため（`Regex` と共有）
+    group_info: Arc<GroupInfo>,
+}
+
+impl<'h> Captures<'h> {
+    pub(crate) fn new(
+        hay: &'h str,
+        slots: Vec<Option<(usize, usize)>>,
+        group_info: Arc<GroupInfo>,
+    ) -> Self {
+        Self {
+            hay,
+            slots,
+            group_info,
+        }
+    }
+
+    /// グループ `i` の一致。一致に参加しなかったグループや範囲外は None
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        let (start, end) = (*self.slots.get(i)?)?;
+        Some(Match::new(self.hay, start, end))
+    }
+
+    /// 名前付きグループの一致。そういう名前が無いか、参加しなかったなら None
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        self.get(self.group_info.to_index(name)?)
+    }
+
+    /// 参加したグループの文字列を、名前付きグループは名前、それ以外は番号の文字列をキーにして並べる。
+    /// グループ 0（一致全体）は `"0"`。参加しなかったグループは入らない。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"(?<k>\w+)=(\d+)(;)?").unwrap();
+    /// let map = re.captures("a=1").unwrap().to_map();
+    /// assert_eq!(map["k"], "a");
+    /// assert_eq!(map["2"], "1");
+    /// assert_eq!(map.len(), 3);
+    /// ```
+    pub fn to_map(&self) -> HashMap<String, &'h str> {
+        self.iter()
+            .enumerate()
+            .filter_map(|(g, m)| {
+                let key = match self.group_info.name(g) {
+                    Some(name) => name.to_string(),
+                    None => g.to_string(),
+                };
+                Some((key, m?.as_str()))
+            })
+            .collect()
+    }
+
+    /// 参加したグループをビット列にしたもの。グループ g は `[g / 64]` の `1 << (g % 64)` で、
+    /// 参加していれば立つ。長さは `len()` を 64 で割って切り上げた数。
+    /// 多くの省略可能なグループのどれが揃ったかを、`get` を1つずつ見ずにまとめて比べるため。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"(a)?(b)?(c)?").unwrap();
+    /// let bits = re.captures("ac").unwrap().participation();
+    /// assert_eq!(bits, vec![0b1011]);
+    /// ```
+    pub fn participation(&self) -> Vec<u64> {
+        let mut bits = vec![0u64; self.slots.len().div_ceil(64)];
+        for (g, slot) in self.slots.iter().enumerate() {
+            if slot.is_some() {
+                bits[g / 64] |= 1 << (g % 64);
+            }
+        }
+        bits
+    }
+
+    // 一致全体（グループ 0 は常にある）の範囲
+    pub(crate) fn whole(&self) -> Span {
+        let (start, end) = self.slots[0].unwrap_or_default();
+        Span::new(start, end)
+    }
+
+    /// グループ 0 を含むグループ数（参加しなかったものも数える）
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// 常に false（グループ 0 は必ずある）
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// グループ 0 から順に `get` を並べたもの
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// 置換文字列 `template` の `$1` / `${1}` / `${name}` をグループの中身に、`$$` を `$` にして
+    /// `dst` に足す（`Regex::replace_all` と同じ展開）。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"(?<k>\w+)=(\w+)").unwrap();
+    /// let mut out = String::new();
+    /// re.captures("a=1").unwrap().expand("${k} is $2", &mut out);
+    /// assert_eq!(out, "a is 1");
+    /// ```
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        crate::replace::expand(self, template, dst);
+    }
+}
+
+/// `caps[i]` はグループ `i` の文字列。参加しなかったグループや範囲外はパニックする
+/// （あるか分からないときは `get` を使う）。
+impl Index<usize> for Captures<'_> {
+    type Output = str;
+
+    fn index(&self, i: usize) -> &str {
+        match self.slots.get(i) {
+            Some(Some((start, end))) => &self.hay[*start..*end],
+            Some(None) => panic!("group {i} did not participate in the match"),
+            None => panic!("no group {i} (this pattern has {} groups)", self.len()),
+        }
+    }
+}
+
+/// `caps["name"]` は名前付きグループの文字列。無い名前や参加しなかったグループはパニックする。
+impl Index<&str> for Captures<'_> {
+    type Output = str;
+
+    fn index(&self, name: &str) -> &str {
+        match self.group_info.to_index(name) {
+            Some(i) => &self[i],
+            None => {
+                let names: Vec<&str> = self.group_info.names().collect();
+                if names.is_empty() {
+                    panic!("no group named {name:?} (this pattern has no named groups)")
+                }
+                panic!("no group named {name:?} (groups: {})", names.join(", "))
+            }
+        }
+    }
+}
+
+/// `Regex::captures_all_groups_iter` が返すイテレータ
+#[derive(Debug)]
+pub struct AllGroupMatches<'r, 'h> {
+    re: &'r Regex,
+    hay: &'h str,
+    cursor: Cursor,
+    // 次の一致の番号
+    next_index: usize,
+    // 読んでいる途中の一致: (一致の番号, キャプチャ, 次に見るグループ)
+    current: Option<(usize, Captures<'h>, usize)>,
+}
+
+impl<'h> Iterator for AllGroupMatches<'_, 'h> {
+    type Item = (usize, usize, Match<'h>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((index, caps, g)) = &mut self.current {
+                while *g < caps.len() {
+                    let group = *g;
+                    *g += 1;
+                    if let Some(m) = caps.get(group) {
+                        return Some((*index, group, m));
+                    }
+                }
+                self.current = None;
+            }
+            // 一致の並びは find_iter と同じ
+            let (re, hay) = (self.re, self.hay);
+            let caps = self
+                .cursor
+                .try_next(hay, |opts| {
+                    Ok(re.try_search_captures(hay, opts)?.map(|c| (c.whole(), c)))
+                })
+                .unwrap_or_else(|e| search_failed(e))?;
+            self.current = Some((self.next_index, caps, 0));
+            self.next_index += 1;
+        }
+    }
+}
+
+// 入力を読み切った後は位置が範囲を超えたままなので、None を返し続ける
+impl FusedIterator for AllGroupMatches<'_, '_> {}
+
+impl Regex {
+    /// 入力中の一致（`find_iter` と同じもの）について、参加したグループを
+    /// `(一致の番号, グループ番号, 一致)` の形で一致の順・グループ番号の順に平らに並べて返す。
+    /// グループ 0（一致全体）も含み、参加しなかったグループは飛ばす。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"(\w+)=(\d+)?").unwrap();
+    /// let rows: Vec<(usize, usize, &str)> = re
+    ///     .captures_all_groups_iter("a=1 b=")
+    ///     .map(|(i, g, m)| (i, g, m.as_str()))
+    ///     .collect();
+    /// assert_eq!(
+    ///     rows,
+    ///     vec![(0, 0, "a=1"), (0, 1, "a"), (0, 2, "1"), (1, 0, "b="), (1, 1, "b")]
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    /// `find_iter` と同じく、探索が `MatchError` で失敗した場合。
+    pub fn captures_all_groups_iter<'r, 'h>(&'r self, hay: &'h str) -> AllGroupMatches<'r, 'h> {
+        AllGroupMatches {
+            re: self,
+            hay,
+            cursor: Cursor::new(Span::new(0, hay.len())),
+            next_index: 0,
+            current: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod captures_tests {
+    use crate::{Regex, RegexBuilder};
+
+    #[test]
+    fn get_and_iter_report_positions() {
+        let re = Regex::new(r"(a)|(b)").unwrap();
+        let caps = re.captures("b").unwrap();
+        assert_eq!(caps.len(), 3);
+        assert!(caps.get(1).is_none());
+        assert_eq!(caps.get(2).unwrap().span(), (0..1).into());
+        assert!(caps.get(3).is_none());
+        let got: Vec<Option<&str>> = caps.iter().map(|m| m.map(|m| m.as_str())).collect();
+        assert_eq!(got, vec![Some("b"), None, Some("b")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "group 1 did not participate")]
+    fn index_panics_on_missing_group() {
+        let re = Regex::new(r"(a)?b").unwrap();
+        let _ = &re.captures("b").unwrap()[1];
+    }
+
+    #[test]
+    fn names_and_numbers_address_the_same_groups() {
+        let re = Regex::new(r"(?P<y>\d+)-(\d+)-(?<d>\d+)").unwrap();
+        let caps = re.captures("2024-1-31").unwrap();
+        assert_eq!((&caps["y"], &caps[1]), ("2024", "2024"));
+        assert_eq!(&caps[2], "1");
+        assert_eq!(caps.name("d").unwrap().range(), 7..9);
+        assert!(caps.name("m").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "no group named \"m\" (groups: y, d)")]
+    fn index_panics_on_unknown_name() {
+        let re = Regex::new(r"(?<y>a)(b)(?<d>c)").unwrap();
+        let _ = &re.captures("abc").unwrap()["m"];
+    }
+
+    #[test]
+    #[should_panic(expected = "no group named \"m\" (this pattern has no named groups)")]
+    fn index_panics_without_named_groups() {
+        let re = Regex::new(r"(a)").unwrap();
+        let _ = &re.captures("a").unwrap()["m"];
+    }
+
+    #[test]
+    fn to_map_keys_by_name_or_number() {
+        let re = Regex::new(r"(?<y>\d+)-(\d+)(?:-(?<d>\d+))?(x)?").unwrap();
+        let map = re.captures("2024-5").unwrap().to_map();
+        let mut got: Vec<(&str, &str)> = map.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        got.sort();
+        // d と 4 は参加していない
+        assert_eq!(got, vec![("0", "2024-5"), ("2", "5"), ("y", "2024")]);
+
+        let map = re.captures("2024-5-31x").unwrap().to_map();
+        assert_eq!((map["d"], map["4"], map.len()), ("31", "x", 5));
+        // キャプチャを取らなければグループ 0 だけ
+        let re = RegexBuilder::new("(a)").captures(false).build().unwrap();
+        assert_eq!(re.captures("a").unwrap().to_map().len(), 1);
+    }
+
+    #[test]
+    fn participation_spans_several_words() {
+        // 70 個の省略可能なグループのうち、1・65・70 番だけが参加する
+        let pat: String = (1..=70)
+            .map(|g| match g {
+                1 | 65 | 70 => "(x)?",
+                _ => "(y)?",
+            })
+            .collect();
+        let re = Regex::new(&pat).unwrap();
+        let caps = re.captures("xxx").unwrap();
+        let bits = caps.participation();
+        assert_eq!(bits, vec![0b11, 1 << 1 | 1 << 6]);
+        for (g, m) in caps.iter().enumerate() {
+            assert_eq!(bits[g / 64] >> (g % 64) & 1 == 1, m.is_some());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "no group 5")]
+    fn index_panics_out_of_range() {
+        let re = Regex::new(r"(a)").unwrap();
+        let _ = &re.captures("a").unwrap()[5];
+    }
+
+    #[test]
+    fn all_groups_follow_find_iter() {
+        let re = Regex::new(r"(a)|(b)(c)?").unwrap();
+        let hay = "xabcb";
+        let rows: Vec<(usize, usize, usize, usize)> = re
+            .captures_all_groups_iter(hay)
+            .map(|(i, g, m)| (i, g, m.start(), m.end()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (0, 0, 1, 2),
+                (0, 1, 1, 2),
+                (1, 0, 2, 4),
+                (1, 2, 2, 3),
+                (1, 3, 3, 4),
+                (2, 0, 4, 5),
+                (2, 2, 4, 5),
+            ]
+        );
+        let wholes: Vec<_> = re
+            .captures_all_groups_iter(hay)
+            .filter(|&(_, g, _)| g == 0)
+            .map(|(_, _, m)| m.span())
+            .collect();
+        let found: Vec<_> = re.find_iter(hay).map(|m| m.span()).collect();
+        assert_eq!(wholes, found);
+    }
+
+    #[test]
+    fn all_groups_with_empty_matches_and_no_captures() {
+        let re = Regex::new("(x)*").unwrap();
+        let got: Vec<_> = re
+            .captures_all_groups_iter("axb")
+            .map(|(i, g, m)| (i, g, m.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![(0, 0, ""), (1, 0, "x"), (1, 1, "x"), (2, 0, ""), (3, 0, "")]
+        );
+        let re = RegexBuilder::new("(a)").captures(false).build().unwrap();
+        assert_eq!(re.captures_all_groups_iter("aa").count(), 2);
+    }
+}
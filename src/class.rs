@@ -0,0 +1,495 @@
+// class.rs
+
+/// コードポイントの上限（Unicode スカラ値の最大）
+pub(crate) const MAX_CODEPOINT: u32 = 0x10FFFF;
+
+/// コードポイント区間の集合。
+/// 常に「昇順・重複なし・隣接なし」に正規化して持ち、所属判定は二分探索。
+/// パーサ（文字クラス）とケース畳み込みの共通表現として使う。
+///
+/// ロケールデータなどから計算した集合は `RegexBuilder::define_class` で名前を付けて
+/// パターンから `\p{name}` / `\P{name}` で参照できる（1文字 = UTF-8 の1〜4バイトに一致）。
+///
+/// ```
+/// use my_regex::{ClassSet, RegexBuilder};
+/// let kana = ClassSet::from_ranges([(0x3041, 0x3096)]);
+/// let vowels = ClassSet::from_ranges("あいうえお".chars().map(|c| (c as u32, c as u32)));
+/// let re = RegexBuilder::new(r"\p{cons}+")
+///     .define_class("cons", kana.intersect(&vowels.negate()))
+///     .build()
+///     .unwrap();
+/// assert!(re.is_match("かきく"));
+/// assert!(!re.is_match("かあ"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ClassSet {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl ClassSet {
+    pub fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    /// 任意順・重複ありの区間列から正規化して作る（lo > hi の区間は捨てる）
+    pub fn from_ranges<I: IntoIterator<Item = (u32, u32)>>(it: I) -> Self {
+        let mut ranges: Vec<(u32, u32)> = it.into_iter().filter(|&(lo, hi)| lo <= hi).collect();
+        ranges.sort_unstable();
+        let mut out: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+        for (lo, hi) in ranges {
+            match out.last_mut() {
+                // 重なり or 隣接ならまとめる
+                Some(last) if lo <= last.1.saturating_add(1) => last.1 = last.1.max(hi),
+                _ => out.push((lo, hi)),
+            }
+        }
+        Self { ranges: out }
+    }
+
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, cp: u32) -> bool {
+        self.ranges
+            .binary_search_by(|&(lo, hi)| {
+                if hi < cp {
+                    std::cmp::Ordering::Less
+                } else if lo > cp {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_ranges(self.ranges.iter().chain(other.ranges.iter()).copied())
+    }
+
+    pub fn intersect(&self, other: &Self) -> Self {
+        // 両方とも正規化済みなのでマージ走査で済む
+        let (a, b) = (&self.ranges, &other.ranges);
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        while i < a.len() && j < b.len() {
+            let lo = a[i].0.max(b[j].0);
+            let hi = a[i].1.min(b[j].1);
+            if lo <= hi {
+                out.push((lo, hi));
+            }
+            if a[i].1 < b[j].1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self { ranges: out }
+    }
+
+    /// 0..=0x10FFFF に対する補集合
+    pub fn negate(&self) -> Self {
+        let mut out = Vec::with_capacity(self.ranges.len() + 1);
+        let mut next = 0u32;
+        for &(lo, hi) in &self.ranges {
+            if lo > next {
+                out.push((next, lo - 1));
+            }
+            next = hi.saturating_add(1);
+        }
+        if next <= MAX_CODEPOINT && self.ranges.last().is_none_or(|&(_, hi)| hi < MAX_CODEPOINT) {
+            out.push((next, MAX_CODEPOINT));
+        }
+        Self { ranges: out }
+    }
+}
+
+impl ClassSet {
+    /// 集合の各文字の UTF-8 表現を、バイト範囲の列の集まりで表す。
+    /// 1つの列 `[(lo1, hi1), (lo2, hi2), ..]` は「1バイト目が lo1..=hi1、2バイト目が …」。
+    /// サロゲート（U+D800..U+DFFF）は UTF-8 にならないので除く。
+    pub(crate) fn utf8_sequences(&self) -> Vec<Vec<(u8, u8)>> {
+        let surrogates = ClassSet::from_ranges([(0xD800, 0xDFFF)]);
+        let mut out = Vec::new();
+        for &(lo, hi) in self.intersect(&surrogates.negate()).ranges() {
+            let mut stack = vec![(lo, hi)];
+            'split: while let Some((lo, hi)) = stack.pop() {
+                // 符号化の長さが変わる境目で分ける
+                for max in [0x7F, 0x7FF, 0xFFFF] {
+                    if lo <= max && max < hi {
+                        stack.push((max + 1, hi));
+                        stack.push((lo, max));
+                        continue 'split;
+                    }
+                }
+                if hi <= 0x7F {
+                    out.push(vec![(lo as u8, hi as u8)]);
+                    continue;
+                }
+                // 後続バイトが 0x80..=0xBF を全部使える形になるまで分ける
+                for k in 1..4 {
+                    let m: u32 = (1 << (6 * k)) - 1;
+                    if lo & !m != hi & !m {
+                        if lo & m != 0 {
+                            stack.push(((lo | m) + 1, hi));
+                            stack.push((lo, lo | m));
+                            continue 'split;
+                        }
+                        if hi & m != m {
+                            stack.push((hi & !m, hi));
+                            stack.push((lo, (hi & !m) - 1));
+                            continue 'split;
+                        }
+                    }
+                }
+                let (mut a, mut b) = ([0u8; 4], [0u8; 4]);
+                let a = encode(lo, &mut a);
+                let b = encode(hi, &mut b);
+                out.push(a.iter().zip(b).map(|(&x, &y)| (x, y)).collect());
+            }
+        }
+        out
+    }
+}
+
+fn encode(cp: u32, buf: &mut [u8; 4]) -> &[u8] {
+    // サロゲートは呼び出し側で除いてある
+    let ch = char::from_u32(cp).unwrap_or('\u{FFFD}');
+    ch.encode_utf8(buf).as_bytes()
+}
+
+/// ユーザー指定の「同一視する文字」のグループ群（ケース同一視テーブル等）。
+/// 共通要素を持つグループは追加時にまとめるので、常に互いに素。
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Equivalences {
+    groups: Vec<ClassSet>,
+}
+
+impl Equivalences {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub(crate) fn groups(&self) -> &[ClassSet] {
+        &self.groups
+    }
+
+    pub(crate) fn add(&mut self, members: &ClassSet) {
+        let mut merged = members.clone();
+        self.groups.retain(|g| {
+            if g.intersect(&merged).is_empty() {
+                true
+            } else {
+                merged = merged.union(g);
+                false
+            }
+        });
+        if !merged.is_empty() {
+            self.groups.push(merged);
+        }
+    }
+
+    /// `set` に含まれる文字と同一視される文字をすべて足した集合
+    pub(crate) fn close(&self, set: &ClassSet) -> ClassSet {
+        self.groups
+            .iter()
+            .filter(|g| !g.intersect(set).is_empty())
+            .fold(set.clone(), |acc, g| acc.union(g))
+    }
+}
+
+/// 256bit のビットマップで表したバイト集合。
+/// 所属判定は O(1)（ワード選択 + ビットテスト）。
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) struct ByteSet([u64; 4]);
+
+impl ByteSet {
+    pub(crate) const fn empty() -> Self {
+        Self([0; 4])
+    }
+
+    /// トークナイザの (lo, hi) 列から作る。`neg` なら補集合。
+    /// 正規化・否定は ClassSet 側で行い、最後にバイト範囲へ切り詰める。
+    pub(crate) fn from_ranges(ranges: &[(u8, u8)], neg: bool) -> Self {
+        let set = ClassSet::from_ranges(ranges.iter().map(|&(lo, hi)| (lo as u32, hi as u32)));
+        let set = if neg { set.negate() } else { set };
+        Self::from(&set)
+    }
+
+    pub(crate) fn insert(&mut self, b: u8) {
+        self.0[(b >> 6) as usize] |= 1u64 << (b & 63);
+    }
+
+    /// lo > hi の場合は何もしない（空範囲）
+    pub(crate) fn insert_range(&mut self, lo: u8, hi: u8) {
+        if lo > hi {
+            return;
+        }
+        for b in lo..=hi {
+            self.insert(b);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn contains(&self, b: u8) -> bool {
+        self.0[(b >> 6) as usize] & (1u64 << (b & 63)) != 0
+    }
+
+    pub(crate) const fn full() -> Self {
+        Self([u64::MAX; 4])
+    }
+
+    pub(crate) fn singleton(b: u8) -> Self {
+        let mut s = Self::empty();
+        s.insert(b);
+        s
+    }
+
+    /// `hay` の先頭から、この集合に含まれるバイトが何個続くか。
+    /// 補集合が3バイト以下（`.` や `[^X]` 等）なら memchr で次の「止まる」バイトへ飛ぶ。
+    pub(crate) fn prefix_len(&self, hay: &[u8]) -> usize {
+        let stops: Vec<u8> = (0..=255u8).filter(|&b| !self.contains(b)).collect();
+        let found = match stops.as_slice() {
+            [] => None,
+            [a] => memchr::memchr(*a, hay),
+            [a, b] => memchr::memchr2(*a, *b, hay),
+            [a, b, c] => memchr::memchr3(*a, *b, *c, hay),
+            _ => hay.iter().position(|&b| !self.contains(b)),
+        };
+        found.unwrap_or(hay.len())
+    }
+
+    /// 含まれるバイトの個数
+    pub(crate) fn count(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// 小さい方から数えて `k` 番目（0 始まり）のバイト
+    pub(crate) fn nth(&self, k: usize) -> Option<u8> {
+        (0..=255u8).filter(|&b| self.contains(b)).nth(k)
+    }
+
+    /// 連続するビットをまとめて (lo, hi) 列に戻す（表示・検査用）
+    pub(crate) fn ranges(&self) -> Vec<(u8, u8)> {
+        let mut out = Vec::new();
+        let mut lo: Option<u8> = None;
+        for b in 0..=255u8 {
+            match (self.contains(b), lo) {
+                (true, None) => lo = Some(b),
+                (false, Some(l)) => {
+                    out.push((l, b - 1));
+                    lo = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(l) = lo {
+            out.push((l, 255));
+        }
+        out
+    }
+}
+
+impl From<&ClassSet> for ByteSet {
+    /// 0..=255 に収まる部分だけを取り出す
+    fn from(set: &ClassSet) -> Self {
+        let mut out = Self::empty();
+        for &(lo, hi) in set.ranges() {
+            if lo > 0xFF {
+                break;
+            }
+            out.insert_range(lo as u8, hi.min(0xFF) as u8);
+        }
+        out
+    }
+}
+
+impl std::fmt::Debug for ByteSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.ranges().iter().map(|&(lo, hi)| lo..=hi))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod class_tests {
+    use super::*;
+
+    #[test]
+    fn from_ranges_and_contains() {
+        let s = ByteSet::from_ranges(&[(b'a', b'c'), (b'x', b'x')], false);
+        assert!(s.contains(b'a'));
+        assert!(s.contains(b'c'));
+        assert!(s.contains(b'x'));
+        assert!(!s.contains(b'd'));
+        assert!(!s.contains(0xFF));
+    }
+
+    #[test]
+    fn negated_covers_high_bytes() {
+        let s = ByteSet::from_ranges(&[(b'0', b'9')], true);
+        assert!(!s.contains(b'5'));
+        assert!(s.contains(b'a'));
+        assert!(s.contains(0x00));
+        assert!(s.contains(0xFF));
+    }
+
+    #[test]
+    fn reversed_range_is_empty() {
+        let s = ByteSet::from_ranges(&[(b'z', b'a')], false);
+        assert_eq!(s, ByteSet::empty());
+    }
+
+    // 列の集まりが表すバイト列に、s の UTF-8 がちょうど一致するか
+    fn seq_matches(seqs: &[Vec<(u8, u8)>], s: &str) -> bool {
+        let b = s.as_bytes();
+        seqs.iter().any(|seq| {
+            seq.len() == b.len() && seq.iter().zip(b).all(|(&(lo, hi), &x)| lo <= x && x <= hi)
+        })
+    }
+
+    #[test]
+    fn utf8_sequences_cover_exactly_the_set() {
+        let set = ClassSet::from_ranges([(0x41, 0x5A), (0xE0, 0x3042), (0x1F600, 0x1F64F)]);
+        let seqs = set.utf8_sequences();
+        for cp in (0..0x20000u32)
+            .step_by(7)
+            .chain([0x41, 0x5A, 0xE0, 0x3042, 0x3043, 0x1F600])
+        {
+            let Some(ch) = char::from_u32(cp) else {
+                continue;
+            };
+            assert_eq!(
+                seq_matches(&seqs, ch.encode_utf8(&mut [0; 4])),
+                set.contains(cp),
+                "U+{cp:04X}"
+            );
+        }
+        // 各列の各位置は空でない範囲
+        assert!(seqs.iter().flatten().all(|&(lo, hi)| lo <= hi));
+    }
+
+    #[test]
+    fn utf8_sequences_of_everything() {
+        let seqs = ClassSet::from_ranges([(0, MAX_CODEPOINT)]).utf8_sequences();
+        for s in [
+            "\0",
+            "a",
+            "\u{7F}",
+            "\u{80}",
+            "é",
+            "\u{7FF}",
+            "\u{800}",
+            "\u{D7FF}",
+            "\u{E000}",
+            "\u{FFFF}",
+            "\u{10000}",
+            "\u{10FFFF}",
+        ] {
+            assert!(seq_matches(&seqs, s), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn ranges_roundtrip() {
+        let s = ByteSet::from_ranges(&[(b'x', b'z'), (b'a', b'c'), (b'b', b'd')], false);
+        assert_eq!(s.ranges(), vec![(b'a', b'd'), (b'x', b'z')]);
+
+        let all = ByteSet::from_ranges(&[], true);
+        assert_eq!(all.ranges(), vec![(0, 255)]);
+    }
+
+    #[test]
+    fn prefix_len_stops_at_first_non_member() {
+        let not_nl = ByteSet::from_ranges(&[(b'\n', b'\n')], true);
+        assert_eq!(not_nl.prefix_len(b"abc\ndef"), 3);
+        assert_eq!(not_nl.prefix_len(b"abc"), 3);
+        assert_eq!(ByteSet::full().prefix_len(b"a\nb"), 3);
+
+        let not_two = ByteSet::from_ranges(&[(b',', b','), (b';', b';')], true);
+        assert_eq!(not_two.prefix_len(b"ab;c,d"), 2);
+
+        let digits = ByteSet::from_ranges(&[(b'0', b'9')], false);
+        assert_eq!(digits.prefix_len(b"0123x5"), 4);
+        assert_eq!(ByteSet::singleton(b'a').prefix_len(b"aab"), 2);
+    }
+
+    // ---- ClassSet ----
+
+    fn cs(rs: &[(u32, u32)]) -> ClassSet {
+        ClassSet::from_ranges(rs.iter().copied())
+    }
+
+    #[test]
+    fn class_set_normalizes() {
+        // 重なり・隣接はまとめ、逆転区間は捨てる
+        let s = cs(&[(10, 20), (0, 5), (6, 8), (15, 30), (50, 40)]);
+        assert_eq!(s.ranges(), &[(0, 8), (10, 30)]);
+    }
+
+    #[test]
+    fn class_set_contains_binary_search() {
+        let s = cs(&[(0x30, 0x39), (0x3040, 0x309F), (0x1F600, 0x1F64F)]);
+        assert!(s.contains(0x35));
+        assert!(s.contains(0x3042)); // あ
+        assert!(s.contains(0x1F600));
+        assert!(!s.contains(0x40));
+        assert!(!s.contains(0x30A2)); // ア
+    }
+
+    #[test]
+    fn class_set_union_intersect() {
+        let a = cs(&[(0, 10), (20, 30)]);
+        let b = cs(&[(5, 25)]);
+        assert_eq!(a.union(&b).ranges(), &[(0, 30)]);
+        assert_eq!(a.intersect(&b).ranges(), &[(5, 10), (20, 25)]);
+        assert!(a.intersect(&cs(&[(11, 19)])).is_empty());
+    }
+
+    #[test]
+    fn class_set_negate() {
+        let s = cs(&[(0, 9), (20, 20)]);
+        assert_eq!(s.negate().ranges(), &[(10, 19), (21, MAX_CODEPOINT)]);
+        assert_eq!(s.negate().negate(), s);
+        assert_eq!(ClassSet::new().negate().ranges(), &[(0, MAX_CODEPOINT)]);
+        assert!(cs(&[(0, MAX_CODEPOINT)]).negate().is_empty());
+    }
+
+    #[test]
+    fn equivalences_merge_and_close() {
+        let mut eq = Equivalences::default();
+        eq.add(&cs(&[
+            (b'a' as u32, b'a' as u32),
+            (b'A' as u32, b'A' as u32),
+        ]));
+        eq.add(&cs(&[(b'x' as u32, b'x' as u32)]));
+        // 'A' を共有するのでまとめられる
+        eq.add(&cs(&[
+            (b'A' as u32, b'A' as u32),
+            (b'@' as u32, b'@' as u32),
+        ]));
+
+        let got = eq.close(&cs(&[(b'a' as u32, b'a' as u32)]));
+        assert_eq!(
+            got.ranges(),
+            &[(b'@' as u32, b'A' as u32), (b'a' as u32, b'a' as u32)]
+        );
+
+        // どのグループにも属さない文字はそのまま
+        let got = eq.close(&cs(&[(b'z' as u32, b'z' as u32)]));
+        assert_eq!(got.ranges(), &[(b'z' as u32, b'z' as u32)]);
+    }
+
+    #[test]
+    fn class_set_to_byte_set_clips() {
+        let s = cs(&[(b'a' as u32, b'c' as u32), (0xF0, 0x3042)]);
+        let b = ByteSet::from(&s);
+        assert_eq!(b.ranges(), vec![(b'a', b'c'), (0xF0, 0xFF)]);
+    }
+}
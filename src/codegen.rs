@@ -0,0 +1,108 @@
+// codegen.rs
+//! コンパイル済みの `Regex` から、依存なしで動く Rust の判定関数を書き出す。
+//!
+//! ビルドスクリプトで生成したコードを `include!` すれば、実行時にこのクレートは要らない。
+//!
+//! ```
+//! use my_regex::{Regex, codegen};
+//! let re = Regex::new("[0-9]+").unwrap();
+//! let src = codegen::rust_source(&re).unwrap();
+//! assert!(src.contains("pub fn matches(input: &[u8]) -> bool"));
+//! ```
+use std::fmt::Write;
+
+use crate::Regex;
+use crate::dfa::Dfa;
+pub use crate::dfa::DfaError;
+
+/// 完全一致を判定する `pub fn matches(input: &[u8]) -> bool` の Rust ソースを返す。
+/// 状態遷移は `match (state, byte)` で表し、遷移の無い組は即 `false`。
+/// 単語境界を含むパターンや、DFA の状態数が上限を超えるパターンは書き出せない。
+pub fn rust_source(re: &Regex) -> Result<String, DfaError> {
+    let dfa = Dfa::from_regex(re)?;
+    let mut out = String::new();
+    // String への書き込みは失敗しない
+    let _ = write_source(&mut out, &dfa);
+    Ok(out)
+}
+
+fn write_source(out: &mut String, dfa: &Dfa) -> std::fmt::Result {
+    writeln!(out, "// generated by my_regex::codegen; do not edit")?;
+    writeln!(out, "#[allow(clippy::all)]")?;
+    writeln!(out, "pub fn matches(input: &[u8]) -> bool {{")?;
+    writeln!(out, "    let mut state: usize = 0;")?;
+    writeln!(out, "    for &b in input {{")?;
+    writeln!(out, "        state = match (state, b) {{")?;
+    for (s, row) in dfa.next.iter().enumerate() {
+        for (lo, hi, t) in byte_runs(row) {
+            if lo == hi {
+                writeln!(out, "            ({s}, {lo}) => {t},")?;
+            } else {
+                writeln!(out, "            ({s}, {lo}..={hi}) => {t},")?;
+            }
+        }
+    }
+    writeln!(out, "            _ => return false,")?;
+    writeln!(out, "        }};")?;
+    writeln!(out, "    }}")?;
+    let accepting: Vec<String> = (0..dfa.accept.len())
+        .filter(|&s| dfa.accept[s])
+        .map(|s| s.to_string())
+        .collect();
+    if accepting.is_empty() {
+        writeln!(out, "    let _ = state;")?;
+        writeln!(out, "    false")?;
+    } else {
+        writeln!(out, "    matches!(state, {})", accepting.join(" | "))?;
+    }
+    writeln!(out, "}}")
+}
+
+// 同じ遷移先が続くバイトをまとめる: (lo, hi, 遷移先)
+fn byte_runs(row: &[Option<usize>; 256]) -> Vec<(u8, u8, usize)> {
+    let mut runs: Vec<(u8, u8, usize)> = Vec::new();
+    for b in 0..=255u8 {
+        let Some(t) = row[b as usize] else { continue };
+        match runs.last_mut() {
+            Some((_, hi, last)) if *last == t && *hi as usize + 1 == b as usize => *hi = b,
+            _ => runs.push((b, b, t)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod codegen_tests {
+    use super::{byte_runs, rust_source};
+    use crate::Regex;
+
+    #[test]
+    fn source_shape() {
+        let re = Regex::new("a[0-9]*").unwrap();
+        let src = rust_source(&re).unwrap();
+        assert!(src.contains("(0, 97) => 1,"), "{src}");
+        assert!(src.contains("(1, 48..=57) => 1,"), "{src}");
+        assert!(src.contains("matches!(state, 1)"), "{src}");
+
+        let re = Regex::new("ab|b").unwrap();
+        let src = rust_source(&re).unwrap();
+        assert!(src.contains("(0, 97) => 1,"), "{src}");
+        assert!(src.contains("(0, 98) => 2,"), "{src}");
+        assert!(src.contains("(1, 98) => 2,"), "{src}");
+        assert!(src.contains("matches!(state, 2)"), "{src}");
+    }
+
+    #[test]
+    fn runs_merge_adjacent_bytes() {
+        let mut row = [None; 256];
+        for b in b'a'..=b'c' {
+            row[b as usize] = Some(1);
+        }
+        row[b'd' as usize] = Some(2);
+        row[b'f' as usize] = Some(2);
+        assert_eq!(
+            byte_runs(&row),
+            vec![(b'a', b'c', 1), (b'd', b'd', 2), (b'f', b'f', 2)]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// conformance.rs
+//! 一致とキャプチャの選び方の約束（`MatchKind` の各値）を固定する表テスト。
+//!
+//! 同じパターン・入力に対して、完全一致（`captures` / `validate`）と探索（`try_search_captures`
+//! の anchored / unanchored、`find`）がどれも同じ答えを返すことを確かめる。
+//! 最長一致でのキャプチャの選び方は `better_choice` のコメントにある順の規則で、全順序になっている。
+use crate::{Captures, MatchKind, Regex, RegexBuilder, SearchOptions, Span};
+
+// グループ 0.. の中身。参加しなかったグループは None
+type Groups<'a> = Vec<Option<&'a str>>;
+
+fn groups<'h>(caps: &Captures<'h>) -> Groups<'h> {
+    caps.iter().map(|m| m.map(|m| m.as_str())).collect()
+}
+
+fn build(pat: &str, kind: MatchKind) -> Regex {
+    RegexBuilder::new(pat).match_kind(kind).build().unwrap()
+}
+
+// 入力全体が一致する場合: 完全一致と先頭固定の探索、先頭にゴミを付けた探索が同じグループを返す
+fn check_full(kind: MatchKind, pat: &str, hay: &str, want: &[Option<&str>]) {
+    let re = build(pat, kind);
+    let ctx = format!("{kind:?} {pat} on {hay:?}");
+
+    let full = re
+        .captures(hay)
+        .unwrap_or_else(|| panic!("{ctx}: no full match"));
+    assert_eq!(groups(&full), want, "{ctx}: captures");
+    assert_eq!(groups(&re.validate(hay).unwrap()), want, "{ctx}: validate");
+
+    let anchored = re
+        .try_search_captures(hay, &SearchOptions::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!(groups(&anchored), want, "{ctx}: anchored search");
+
+    let opts = SearchOptions {
+        anchored: false,
+        ..Default::default()
+    };
+    let unanchored = re.try_search_captures(hay, &opts).unwrap().unwrap();
+    assert_eq!(groups(&unanchored), want, "{ctx}: unanchored search");
+    assert_eq!(re.find(hay).map(|m| m.as_str()), want[0], "{ctx}: find");
+
+    // 範囲の前にバイトがあっても、位置がずれるだけで選び方は変わらない
+    let padded = format!("-{hay}");
+    let opts = SearchOptions {
+        span: Some(Span::new(1, padded.len())),
+        ..Default::default()
+    };
+    let shifted = re.try_search_captures(&padded, &opts).unwrap().unwrap();
+    assert_eq!(groups(&shifted), want, "{ctx}: search in span");
+}
+
+#[test]
+fn leftmost_longest_prefers_longest_overall() {
+    let k = MatchKind::LeftmostLongest;
+    check_full(
+        k,
+        "(a|ab)(c|bcd)",
+        "abcd",
+        &[Some("abcd"), Some("a"), Some("bcd")],
+    );
+    let re = build("a|ab|abc", k);
+    assert_eq!(re.find("xabcd").unwrap().as_str(), "abc");
+}
+
+#[test]
+fn leftmost_longest_later_group_start_wins() {
+    let k = MatchKind::LeftmostLongest;
+    // 規則2: 同じ終端なら、番号の小さいグループから見て開始が遅い方（前段が貪欲）
+    check_full(k, "(a*)(a*)", "aa", &[Some("aa"), Some("aa"), Some("")]);
+    check_full(k, "(a|ab)(b*)", "ab", &[Some("ab"), Some("ab"), Some("")]);
+    check_full(k, "a*(a*)", "aa", &[Some("aa"), Some("")]);
+}
+
+#[test]
+fn leftmost_longest_later_group_end_wins() {
+    let k = MatchKind::LeftmostLongest;
+    // 規則3: 開始が同じなら、終端が遅い方
+    check_full(k, "(a*)b*", "aab", &[Some("aab"), Some("aa")]);
+    check_full(
+        k,
+        "((a)|ab)(b?)",
+        "ab",
+        &[Some("ab"), Some("ab"), None, Some("")],
+    );
+}
+
+#[test]
+fn leftmost_longest_participating_group_wins() {
+    let k = MatchKind::LeftmostLongest;
+    // 規則4: 位置で決まらなければ、番号の小さいグループが参加している方
+    check_full(k, "(a)?(a)?", "a", &[Some("a"), Some("a"), None]);
+    check_full(k, "(a)|(a)", "a", &[Some("a"), Some("a"), None]);
+    check_full(k, "((a)|a)b", "ab", &[Some("ab"), Some("a"), Some("a")]);
+}
+
+#[test]
+fn leftmost_first_follows_pattern_order() {
+    let k = MatchKind::LeftmostFirst;
+    check_full(
+        k,
+        "(a|ab)(c|bcd)",
+        "abcd",
+        &[Some("abcd"), Some("a"), Some("bcd")],
+    );
+    check_full(k, "(a*)(a*)", "aa", &[Some("aa"), Some("aa"), Some("")]);
+    check_full(k, "(a)?(a)?", "a", &[Some("a"), Some("a"), None]);
+    check_full(k, "(a)|(a)", "a", &[Some("a"), Some("a"), None]);
+    // 探索では先に書いた枝が短くても採る
+    let re = build("a|ab|abc", k);
+    assert_eq!(re.find("xabcd").unwrap().as_str(), "a");
+}
+
+#[test]
+fn tie_breaking_is_independent_of_thread_order() {
+    // 枝の並びを入れ替えても、最長一致での選び方は変わらない
+    let k = MatchKind::LeftmostLongest;
+    check_full(
+        k,
+        "(a|(a))(b?)",
+        "ab",
+        &[Some("ab"), Some("a"), Some("a"), Some("b")],
+    );
+    check_full(
+        k,
+        "((a)|a)(b?)",
+        "ab",
+        &[Some("ab"), Some("a"), Some("a"), Some("b")],
+    );
+}
@@ -0,0 +1,249 @@
+// context.rs
+//! 一致ごとに前後の行を添えて返すイテレータ（grep の `-B` / `-A` 相当）。
+//!
+//! 前後の行は一致の位置から改行を memchr で数えて切り出すだけで、入力を読み直さない。
+//! 一致を行単位に広げる `Match` の補助と、強調表示用の範囲を返す補助もここに置く。
+use std::iter::FusedIterator;
+
+use memchr::{memchr, memchr_iter, memrchr, memrchr_iter};
+
+use crate::search::FindMatches;
+use crate::{Match, Regex, Span};
+
+impl<'h> Match<'h> {
+    /// 一致を含む行全体に広げる。最後の行の改行は含む（入力の終わりで切れていればそこまで）。
+    /// 一致が改行で終わっていれば、その改行の先の行には広げない。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("ERR").unwrap();
+    /// let m = re.find("ok\nan ERR here\nok").unwrap();
+    /// assert_eq!(m.extend_to_line_bounds().as_str(), "an ERR here\n");
+    /// assert_eq!(m.extend_to_line_bounds().trim_trailing_newline().as_str(), "an ERR here");
+    /// ```
+    pub fn extend_to_line_bounds(&self) -> Match<'h> {
+        let bytes = self.hay.as_bytes();
+        let start = memrchr(b'\n', &bytes[..self.start]).map_or(0, |i| i + 1);
+        let end = if self.end > self.start && bytes[self.end - 1] == b'\n' {
+            self.end
+        } else {
+            memchr(b'\n', &bytes[self.end..]).map_or(bytes.len(), |i| self.end + i + 1)
+        };
+        Match::new(self.hay, start, end)
+    }
+
+    /// 末尾の改行（`\n` か `\r\n`）を1つ除く。無ければそのまま
+    pub fn trim_trailing_newline(&self) -> Match<'h> {
+        let s = self.as_str();
+        let trimmed = s
+            .strip_suffix("\r\n")
+            .or_else(|| s.strip_suffix('\n'))
+            .unwrap_or(s);
+        Match::new(self.hay, self.start, self.start + trimmed.len())
+    }
+}
+
+/// 一致と、その前後の文脈
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContextMatch<'h> {
+    m: Match<'h>,
+    before: &'h str,
+    after: &'h str,
+}
+
+impl<'h> ContextMatch<'h> {
+    pub fn as_match(&self) -> Match<'h> {
+        self.m
+    }
+
+    /// 一致の前の `before` 行と、一致を含む行のうち一致より前の部分
+    pub fn before(&self) -> &'h str {
+        self.before
+    }
+
+    /// 一致を含む行のうち一致より後の部分と、その後の `after` 行（最後の改行は含まない）
+    pub fn after(&self) -> &'h str {
+        self.after
+    }
+}
+
+/// `Regex::find_iter_with_context` が返すイテレータ
+#[derive(Debug)]
+pub struct ContextMatches<'r, 'h> {
+    matches: FindMatches<'r, 'h>,
+    hay: &'h str,
+    before: usize,
+    after: usize,
+}
+
+impl<'h> ContextMatches<'_, 'h> {
+    fn with_context(&self, m: Match<'h>) -> ContextMatch<'h> {
+        let bytes = self.hay.as_bytes();
+        // 一致を含む行の先頭から、さらに before 行さかのぼる
+        let from = memrchr_iter(b'\n', &bytes[..m.start])
+            .nth(self.before)
+            .map_or(0, |i| i + 1);
+        // 一致を含む行の末尾から、さらに after 行進む
+        let to = memchr_iter(b'\n', &bytes[m.end..])
+            .nth(self.after)
+            .map_or(bytes.len(), |i| m.end + i);
+        ContextMatch {
+            m,
+            before: &self.hay[from..m.start],
+            after: &self.hay[m.end..to],
+        }
+    }
+}
+
+impl<'h> Iterator for ContextMatches<'_, 'h> {
+    type Item = ContextMatch<'h>;
+
+    fn next(&mut self) -> Option<ContextMatch<'h>> {
+        let m = self.matches.next()?;
+        Some(self.with_context(m))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.matches.size_hint()
+    }
+}
+
+impl FusedIterator for ContextMatches<'_, '_> {}
+
+impl Regex {
+    /// `find_iter` の各一致に、前 `before` 行・後 `after` 行を添えて返す。
+    /// 一致自体が改行をまたぐ場合、行は一致の先頭・末尾から数える。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("ERROR").unwrap();
+    /// let log = "a\nb\nx ERROR y\nc\nd";
+    /// let cm = re.find_iter_with_context(log, 1, 1).next().unwrap();
+    /// assert_eq!(cm.before(), "b\nx ");
+    /// assert_eq!(cm.after(), " y\nc");
+    /// ```
+    pub fn find_iter_with_context<'r, 'h>(
+        &'r self,
+        hay: &'h str,
+        before: usize,
+        after: usize,
+    ) -> ContextMatches<'r, 'h> {
+        ContextMatches {
+            matches: self.find_iter(hay),
+            hay,
+            before,
+            after,
+        }
+    }
+
+    /// 強調表示にそのまま渡せる一致範囲を、左から順に重ならない形で返す。
+    /// 空一致は色を付けようがないので含めない。`merge_adjacent` なら、
+    /// 接する範囲（前の終わりが次の始まり）を 1 つにまとめる。
+    ///
+    /// ```
+    /// use my_regex::{Regex, Span};
+    /// let re = Regex::new("a|b").unwrap();
+    /// assert_eq!(re.highlight_spans("ab c a", true), vec![Span::new(0, 2), Span::new(5, 6)]);
+    /// assert_eq!(re.highlight_spans("ab", false), vec![Span::new(0, 1), Span::new(1, 2)]);
+    /// ```
+    pub fn highlight_spans(&self, hay: &str, merge_adjacent: bool) -> Vec<Span> {
+        let mut spans: Vec<Span> = Vec::new();
+        for m in self.find_iter(hay).filter(|m| !m.span().is_empty()) {
+            match spans.last_mut() {
+                Some(last) if merge_adjacent && last.end >= m.start() => {
+                    last.end = last.end.max(m.end());
+                }
+                _ => spans.push(m.span()),
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod context_tests {
+    use crate::{Regex, SearchOptions, Span};
+
+    #[test]
+    fn context_is_clamped_to_the_haystack() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let hay = "a1\nb\nc22\nd";
+        let got: Vec<(&str, &str, &str)> = re
+            .find_iter_with_context(hay, 5, 0)
+            .map(|c| (c.before(), c.as_match().as_str(), c.after()))
+            .collect();
+        assert_eq!(got, vec![("a", "1", ""), ("a1\nb\nc", "22", "")]);
+
+        let last = re.find_iter_with_context(hay, 0, 9).last().unwrap();
+        assert_eq!((last.before(), last.after()), ("c", "\nd"));
+        let all: Vec<_> = re.find_iter_with_context(hay, 0, 9).collect();
+        assert_eq!(all.last(), Some(&last));
+    }
+
+    #[test]
+    fn multiline_match_and_empty_matches() {
+        let re = Regex::new("b\nc").unwrap();
+        let cm = re
+            .find_iter_with_context("a\nb\nc\nd", 0, 0)
+            .next()
+            .unwrap();
+        assert_eq!(cm.as_match().span(), (2..5).into());
+        assert_eq!((cm.before(), cm.after()), ("", ""));
+
+        // 空一致は1文字ずつ進む（マルチバイト文字の途中には止まらない）
+        let re = Regex::new("x*").unwrap();
+        let starts: Vec<usize> = re
+            .find_iter_with_context("éx", 0, 0)
+            .map(|c| c.as_match().start())
+            .collect();
+        assert_eq!(starts, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn line_bounds_of_a_match() {
+        let hay = "a1\nb22c\r\n\nd3";
+        let re = Regex::new("[0-9]+").unwrap();
+        let lines: Vec<(&str, &str)> = re
+            .find_iter(hay)
+            .map(|m| {
+                let line = m.extend_to_line_bounds();
+                (line.as_str(), line.trim_trailing_newline().as_str())
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![("a1\n", "a1"), ("b22c\r\n", "b22c"), ("d3", "d3")]
+        );
+        // 改行をまたぐ一致は両端の行まで、改行で終わる一致はその行まで
+        let m = Regex::new("(?s)1.b").unwrap().find("xa1\nb2\n").unwrap();
+        assert_eq!(m.extend_to_line_bounds().span(), Span::new(0, 7));
+        let m = Regex::new("c\r\n").unwrap().find(hay).unwrap();
+        assert_eq!(m.extend_to_line_bounds().as_str(), "b22c\r\n");
+        // 空行の上の空一致
+        let opts = SearchOptions {
+            anchored: false,
+            span: Some(Span::at(9)),
+            ..Default::default()
+        };
+        let m = Regex::new("x*").unwrap().search(hay, &opts).unwrap();
+        assert_eq!(m.extend_to_line_bounds().span(), Span::new(9, 10));
+        assert_eq!(m.trim_trailing_newline().span(), Span::at(9));
+    }
+
+    #[test]
+    fn highlight_spans_skip_empty_and_merge_on_request() {
+        let re = Regex::new("[0-9]*").unwrap();
+        // 空一致は落とし、数字の並びだけが残る
+        assert_eq!(
+            re.highlight_spans("a12b3", false),
+            vec![Span::new(1, 3), Span::new(4, 5)]
+        );
+        let re = Regex::new("ab|cd").unwrap();
+        assert_eq!(re.highlight_spans("abcd ab", false).len(), 3);
+        assert_eq!(
+            re.highlight_spans("abcd ab", true),
+            vec![Span::new(0, 4), Span::new(5, 7)]
+        );
+        assert!(re.highlight_spans("", true).is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+// dfa.rs
+//! 部分集合構成による DFA（キャプチャなし）。
+//!
+//! コード生成などの書き出しと、キャプチャの要らない照合（`strategy` が振り分ける）に使う。
+//! 状態数は NFA に対して指数的に増えうるので上限を設ける。
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::Regex;
+use crate::error::MatchError;
+use crate::nfa::Label;
+use crate::search::Run;
+
+/// DFA を作れなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DfaError {
+    /// 単語境界などのゼロ幅アサーションは、位置の前後を見る必要があるので表せない
+    LookAround,
+    /// 状態数が上限を超えた
+    TooManyStates { limit: usize },
+}
+
+impl fmt::Display for DfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DfaError::LookAround => write!(f, "zero-width assertions cannot be compiled to a DFA"),
+            DfaError::TooManyStates { limit } => {
+                write!(f, "DFA would need more than {limit} states")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DfaError {}
+
+/// 状態 0 が開始。`next[s][b]` が `None` なら行き止まり（以後受理しない）。
+#[derive(Clone, Debug)]
+pub(crate) struct Dfa {
+    pub next: Vec<[Option<usize>; 256]>,
+    pub accept: Vec<bool>,
+}
+
+impl Dfa {
+    pub(crate) const MAX_STATES: usize = 10_000;
+
+    pub(crate) fn from_regex(re: &Regex) -> Result<Self, DfaError> {
+        Self::with_limit(re, Self::MAX_STATES)
+    }
+
+    pub(crate) fn with_limit(re: &Regex, limit: usize) -> Result<Self, DfaError> {
+        let start = closure(re, vec![re.start])?;
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::from([(start.clone(), 0)]);
+        let mut sets = vec![start];
+        let mut dfa = Dfa {
+            next: Vec::new(),
+            accept: Vec::new(),
+        };
+
+        let mut k = 0;
+        while k < sets.len() {
+            let set = sets[k].clone();
+            dfa.accept.push(set.contains(&re.accept));
+            let mut row = [None; 256];
+            for b in 0..=255u8 {
+                let moved: Vec<usize> = set
+                    .iter()
+                    .flat_map(|&s| &re.states[s].edges)
+                    .filter(|(lbl, _)| lbl.byte_set().is_some_and(|bs| bs.contains(b)))
+                    .map(|(_, t)| *t)
+                    .collect();
+                if moved.is_empty() {
+                    continue;
+                }
+                let target = closure(re, moved)?;
+                let id = match ids.get(&target) {
+                    Some(&id) => id,
+                    None => {
+                        if sets.len() == limit {
+                            return Err(DfaError::TooManyStates { limit });
+                        }
+                        ids.insert(target.clone(), sets.len());
+                        sets.push(target);
+                        sets.len() - 1
+                    }
+                };
+                row[b as usize] = Some(id);
+            }
+            dfa.next.push(row);
+            k += 1;
+        }
+        Ok(dfa)
+    }
+
+    /// 位置 `at` から読んで受理した終端。`run.need_end` なら入力末尾での受理だけ、
+    /// `run.earliest` なら最初の受理、それ以外は最後（最長）の受理
+    pub(crate) fn try_run(
+        &self,
+        bytes: &[u8],
+        at: usize,
+        run: &mut Run,
+    ) -> Result<Option<usize>, MatchError> {
+        let mut s = 0;
+        let mut last = None;
+        let mut i = at;
+        loop {
+            if self.accept[s] && (!run.need_end || i == bytes.len()) {
+                last = Some(i);
+                if run.earliest {
+                    break;
+                }
+            }
+            if i == bytes.len() {
+                break;
+            }
+            run.step()?;
+            match self.next[s][bytes[i] as usize] {
+                Some(t) => s = t,
+                None => break,
+            }
+            i += 1;
+        }
+        Ok(last)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_match(&self, bytes: &[u8]) -> bool {
+        let mut s = 0;
+        for &b in bytes {
+            match self.next[s][b as usize] {
+                Some(t) => s = t,
+                None => return false,
+            }
+        }
+        self.accept[s]
+    }
+}
+
+// ε・Cap を辿って閉じた状態集合（整列済み、重複なし）。Look があれば DFA にできない
+fn closure(re: &Regex, mut stack: Vec<usize>) -> Result<Vec<usize>, DfaError> {
+    let mut seen = vec![false; re.states.len()];
+    let mut out = Vec::new();
+    while let Some(s) = stack.pop() {
+        if std::mem::replace(&mut seen[s], true) {
+            continue;
+        }
+        out.push(s);
+        for (lbl, t) in &re.states[s].edges {
+            match lbl {
+                Label::Eps | Label::CapBegin(_) | Label::CapEnd(_) => stack.push(*t),
+                Label::Look(_) => return Err(DfaError::LookAround),
+                Label::Byte(_) | Label::Any | Label::Class(_) => {}
+            }
+        }
+    }
+    out.sort_unstable();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod dfa_tests {
+    use super::{Dfa, DfaError};
+    use crate::{Regex, RegexBuilder};
+
+    #[test]
+    fn agrees_with_nfa() {
+        let pats = [r"(a|ab)(c|bcd)(d*)", r"[^0-9]+\d?", r"a*a*a*b", r"(x|y)*z?"];
+        let hays = [
+            "", "abcd", "abcddd", "x", "xy", "xyz", "aaab", "q1", "q12", "z", "b",
+        ];
+        for p in pats {
+            let re = Regex::new(p).unwrap();
+            let dfa = Dfa::from_regex(&re).unwrap();
+            for h in hays {
+                assert_eq!(dfa.is_match(h.as_bytes()), re.is_match(h), "{p} / {h}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_look_and_blowup() {
+        let re = RegexBuilder::new("a").whole_word(true).build().unwrap();
+        assert_eq!(Dfa::from_regex(&re).unwrap_err(), DfaError::LookAround);
+
+        // (a|b)*a(a|b)^n は 2^(n+1) 状態が要る
+        let p = format!("(a|b)*a{}", "(a|b)".repeat(6));
+        let re = Regex::new(&p).unwrap();
+        assert_eq!(
+            Dfa::with_limit(&re, 100).unwrap_err(),
+            DfaError::TooManyStates { limit: 100 }
+        );
+        assert!(Dfa::with_limit(&re, 200).is_ok());
+    }
+}
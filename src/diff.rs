@@ -0,0 +1,233 @@
+// diff.rs
+//! 2つのパターンの振る舞いの違いを、片方だけが完全一致する具体的な入力で示す
+//! （規則を書き換える前に、何が変わるかを確かめる用）。
+//!
+//! 2つの NFA を同時に進める積オートマトンを、短い入力から順に幅優先で調べる。
+//! Look は前後1バイトしか見ないので、直前のバイトはその性質（単語バイトか・改行か）だけを覚える。
+use std::collections::{HashSet, VecDeque};
+
+use crate::token::is_word_byte;
+use crate::{Regex, Thread};
+
+// 調べる積状態の数の上限（状態の組は指数的に増えうる）
+const MAX_STATES: usize = 10_000;
+
+/// `Regex::diff_examples` の結果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternDiff {
+    left_only: Option<Vec<u8>>,
+    right_only: Option<Vec<u8>>,
+    exhausted: bool,
+}
+
+impl PatternDiff {
+    /// `self` だけが完全一致する最短の入力
+    pub fn left_only(&self) -> Option<&[u8]> {
+        self.left_only.as_deref()
+    }
+
+    /// `other` だけが完全一致する最短の入力
+    pub fn right_only(&self) -> Option<&[u8]> {
+        self.right_only.as_deref()
+    }
+
+    /// 例が1つも見つからなかったか
+    pub fn is_empty(&self) -> bool {
+        self.left_only.is_none() && self.right_only.is_none()
+    }
+
+    /// 状態数の上限で打ち切らずに、長さ `max_len` 以下の入力をすべて調べ終えたか。
+    /// true で `is_empty` なら、2つのパターンは長さ `max_len` 以下では同じ入力を受け付ける
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+// 積状態: 各 NFA の（ε閉包を取る前の）状態集合と、直前のバイトの代表
+type Node = (Vec<usize>, Vec<usize>, Option<u8>);
+
+impl Regex {
+    /// `self` と `other` のどちらか一方だけが完全一致する、長さ `max_len` 以下の最短の入力を探す。
+    /// 同じ長さなら表示できる ASCII を優先する。入力は UTF-8 とは限らない。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let old = Regex::new("[a-z]+[0-9]*").unwrap();
+    /// let new = Regex::new("[a-z]+[0-9]+").unwrap();
+    /// let d = old.diff_examples(&new, 8);
+    /// assert_eq!(d.left_only(), Some(&b"a"[..]));
+    /// assert_eq!(d.right_only(), None);
+    /// assert!(d.exhausted());
+    /// ```
+    pub fn diff_examples(&self, other: &Regex, max_len: usize) -> PatternDiff {
+        let mut diff = PatternDiff {
+            left_only: None,
+            right_only: None,
+            exhausted: true,
+        };
+        let start: Node = (vec![self.start], vec![other.start], None);
+        let mut seen: HashSet<Node> = HashSet::from([start.clone()]);
+        let mut queue: VecDeque<(Node, Vec<u8>)> = VecDeque::from([(start, Vec::new())]);
+
+        while let Some(((a, b, prev), path)) = queue.pop_front() {
+            // ここで入力が終わった場合
+            let left = accepts_at_end(self, &a, prev);
+            let right = accepts_at_end(other, &b, prev);
+            if left && !right && diff.left_only.is_none() {
+                diff.left_only = Some(path.clone());
+            }
+            if right && !left && diff.right_only.is_none() {
+                diff.right_only = Some(path.clone());
+            }
+            if diff.left_only.is_some() && diff.right_only.is_some() {
+                return diff;
+            }
+            if path.len() == max_len {
+                continue;
+            }
+            for byte in byte_order() {
+                let na = step(self, &a, prev, byte);
+                let nb = step(other, &b, prev, byte);
+                // どちらも死んだら、この先で差は出ない
+                if na.is_empty() && nb.is_empty() {
+                    continue;
+                }
+                let node = (na, nb, Some(representative(byte)));
+                if seen.contains(&node) {
+                    continue;
+                }
+                if seen.len() >= MAX_STATES {
+                    diff.exhausted = false;
+                    continue;
+                }
+                seen.insert(node.clone());
+                let mut next = path.clone();
+                next.push(byte);
+                queue.push_back((node, next));
+            }
+        }
+        diff
+    }
+}
+
+// 表示できる ASCII を先に、残りを後に
+fn byte_order() -> impl Iterator<Item = u8> {
+    (0x20..=0x7E).chain(0x00..0x20).chain(0x7F..=0xFF)
+}
+
+// Look が見る性質（単語バイトか・改行か）が同じバイトの代表
+fn representative(b: u8) -> u8 {
+    if is_word_byte(b) {
+        b'a'
+    } else if b == b'\n' {
+        b'\n'
+    } else {
+        b' '
+    }
+}
+
+// 前後1バイトの窓と、その間の位置
+fn window(prev: Option<u8>, next: Option<u8>) -> ([u8; 2], usize, usize) {
+    let mut w = [0u8; 2];
+    let mut n = 0;
+    if let Some(p) = prev {
+        w[0] = p;
+        n = 1;
+    }
+    let pos = n;
+    if let Some(b) = next {
+        w[n] = b;
+        n += 1;
+    }
+    (w, pos, n)
+}
+
+fn closure(re: &Regex, states: &[usize], prev: Option<u8>, next: Option<u8>) -> Vec<Thread> {
+    let mut set: Vec<Thread> = states
+        .iter()
+        .map(|&s| Thread {
+            s,
+            caps: Vec::new(),
+        })
+        .collect();
+    let (w, pos, n) = window(prev, next);
+    re.eps_closure(&mut set, &w[..n], pos);
+    set
+}
+
+fn accepts_at_end(re: &Regex, states: &[usize], prev: Option<u8>) -> bool {
+    closure(re, states, prev, None)
+        .iter()
+        .any(|t| t.s == re.accept)
+}
+
+fn step(re: &Regex, states: &[usize], prev: Option<u8>, byte: u8) -> Vec<usize> {
+    let set = closure(re, states, prev, Some(byte));
+    let mut next: Vec<usize> = re.step_byte(&set, byte).iter().map(|t| t.s).collect();
+    next.sort_unstable();
+    next.dedup();
+    next
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use crate::{Regex, RegexBuilder};
+
+    fn diff(a: &str, b: &str, max_len: usize) -> (Option<Vec<u8>>, Option<Vec<u8>>, bool) {
+        let d = Regex::new(a)
+            .unwrap()
+            .diff_examples(&Regex::new(b).unwrap(), max_len);
+        (
+            d.left_only().map(<[u8]>::to_vec),
+            d.right_only().map(<[u8]>::to_vec),
+            d.exhausted(),
+        )
+    }
+
+    #[test]
+    fn finds_shortest_examples_both_ways() {
+        let (l, r, _) = diff("a+|c", "a|b+", 5);
+        assert_eq!(l.as_deref(), Some(&b"c"[..]));
+        assert_eq!(r.as_deref(), Some(&b"b"[..]));
+        let (l, r, _) = diff("[0-9]{2,4}", "[0-9]{3,5}", 6);
+        assert_eq!(l.as_deref(), Some(&b"00"[..]));
+        assert_eq!(r.as_deref(), Some(&b"00000"[..]));
+    }
+
+    #[test]
+    fn equivalent_patterns_have_no_examples() {
+        let d = Regex::new("(a|b)*")
+            .unwrap()
+            .diff_examples(&Regex::new("(a*b*)*").unwrap(), 10);
+        assert!(d.is_empty() && d.exhausted());
+        // 大文字・小文字の違いだけ
+        let ci = Regex::new("(?i)ab").unwrap();
+        let d = ci.diff_examples(&Regex::new("[aA][bB]").unwrap(), 4);
+        assert!(d.is_empty() && d.exhausted());
+    }
+
+    #[test]
+    fn examples_are_bounded_by_length() {
+        // 違いは長さ 3 で初めて出る
+        let (l, r, exhausted) = diff("a*", "a?a?", 2);
+        assert_eq!((l, r, exhausted), (None, None, true));
+        let (l, _, exhausted) = diff("a*", "a?a?", 3);
+        assert_eq!((l.as_deref(), exhausted), (Some(&b"aaa"[..]), true));
+    }
+
+    #[test]
+    fn anchors_see_neighbouring_bytes() {
+        // 複数行モードの ^ は改行の直後でだけ成り立つ
+        let multi = RegexBuilder::new("a\n^b|a b")
+            .multi_line(true)
+            .build()
+            .unwrap();
+        let d = multi.diff_examples(&Regex::new("a\nb").unwrap(), 4);
+        assert_eq!(d.left_only(), Some(&b"a b"[..]));
+        assert_eq!(d.right_only(), None);
+        // 単語境界の有無
+        let word = RegexBuilder::new("a.*").whole_word(true).build().unwrap();
+        let d = word.diff_examples(&Regex::new("a.*").unwrap(), 3);
+        assert_eq!(d.right_only(), Some(&b"a "[..]));
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::span::Span;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     UnexpectedEof,
@@ -7,14 +11,104 @@ pub enum ErrorKind {
     EmptyClass,
     BadRange(char, char),
     DanglingQuantifier,
+    UnsupportedFeature(Feature), // 構文としては認識したが、このエンジンでは未対応
+    UnboundPlaceholder,          // PatternTemplate の差し込み口に値が無い
+    QuantifiedPlaceholder,       // PatternTemplate の差し込み口の直後に量指定子
+    BadRepetition,               // {m,n} で m > n
+    RepetitionTooLarge { limit: usize }, // {m,n} の回数（入れ子なら積）が RegexBuilder::repetition_limit を超える
+    BadGroupName,                        // (?<name>...) の名前が空・不正な文字・`>` が無い
+    DuplicateGroupName,                  // 同じ名前のグループが2つ以上
+}
+
+/// 構文機能の種類。`Regex::supports` で対応状況を問い合わせられる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    CaptureGroup,      // (...)
+    NonCapturingGroup, // (?:...)
+    NamedGroup,        // (?P<name>...) / (?<name>...)
+    InlineFlags,       // (?i) / (?i-s:...) など（i / m / s。(?i) の形はパターン先頭だけ）
+    Lookaround,        // (?=...) (?!...) (?<=...) (?<!...)
+    Backreference,     // \1 など
+    CountedRepetition, // {m,n}
+    WordBoundary,      // \b \B
+    TextAnchor,        // \A \z
+    UnicodeClass,      // \p{...} \P{...}
+}
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::CaptureGroup => "capture group",
+            Feature::NonCapturingGroup => "non-capturing group",
+            Feature::NamedGroup => "named group",
+            Feature::InlineFlags => "inline flags",
+            Feature::Lookaround => "lookaround",
+            Feature::Backreference => "backreference",
+            Feature::CountedRepetition => "counted repetition",
+            Feature::WordBoundary => "word boundary",
+            Feature::TextAnchor => "text anchor",
+            Feature::UnicodeClass => "Unicode class",
+        }
+    }
+}
+
+/// 探索（照合）時の失敗。コンパイル時の `Error` とは別物。
+/// `try_*` 系の API だけが返し、対応する非 `try` 版はこれをパニックにする。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MatchError {
+    /// 同時に生きているスレッド数が `RegexBuilder::thread_limit` を超えた
+    TooManyThreads { limit: usize },
+    /// `SearchOptions::budget` で許したバイト数を読み切った
+    BudgetExceeded { budget: usize },
+    /// 入力が `RegexBuilder::max_haystack_len` より長い
+    HaystackTooLong { len: usize, limit: usize },
+    /// 指定された範囲が入力の外にあるか、UTF-8 の文字境界に乗っていない
+    InvalidSpan { span: Span },
+    /// 一致やグループの区間が文字境界に乗っていない（feature `boundary-audit` で検出）
+    MisalignedSpan { span: Span },
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchError::TooManyThreads { limit } => {
+                write!(f, "more than {limit} NFA threads were alive at once")
+            }
+            MatchError::BudgetExceeded { budget } => {
+                write!(f, "search budget of {budget} bytes exhausted")
+            }
+            MatchError::HaystackTooLong { len, limit } => {
+                write!(f, "haystack of {len} bytes exceeds the limit of {limit}")
+            }
+            MatchError::InvalidSpan { span } => {
+                write!(
+                    f,
+                    "span {span:?} is out of bounds or not on a char boundary"
+                )
+            }
+            MatchError::MisalignedSpan { span } => {
+                write!(f, "reported span {span:?} splits a UTF-8 character")
+            }
+        }
+    }
 }
 
+impl std::error::Error for MatchError {}
+
 #[derive(Debug)]
 pub struct Error {
     pub kind: ErrorKind,
     pub pos: usize,
 }
 
+impl Error {
+    /// 検出位置を空区間で返す（一致やトークンと同じ `Span` で扱えるように）
+    pub fn span(&self) -> Span {
+        Span::at(self.pos)
+    }
+}
+
 pub(crate) fn err<T>(kind: ErrorKind, pos: usize) -> Result<T, Error> {
     Err(Error { kind, pos })
 }
@@ -0,0 +1,225 @@
+// estimate.rs
+//! 一致の数の見積もり（ダッシュボードの集計向け）。
+//!
+//! 長い入力は等間隔に置いた窓の中だけを数え、長さの比で引き伸ばす。
+//! 数え方は `find_iter` と同じ（重ならず左から）で、キャプチャは追わない。
+//! feature `dfa` があれば、一致の開始位置は DFA で探す。
+#[cfg(feature = "dfa")]
+use crate::builder::MatchKind;
+#[cfg(feature = "dfa")]
+use crate::dfa::Dfa;
+use crate::error::MatchError;
+use crate::search::Cursor;
+#[cfg(feature = "dfa")]
+use crate::search::{Run, SearchOptions};
+use crate::span::Span;
+#[cfg(feature = "dfa")]
+use crate::strategy::Engine;
+use crate::{Regex, search_failed};
+
+// 長い入力で数える窓の数。入力の偏りをならすため、1か所にまとめず散らす
+const WINDOWS: usize = 16;
+
+impl Regex {
+    /// 入力中の一致の数の見積もり。
+    ///
+    /// 入力が `sample` バイト以下か、パターンが `\A` で始まる（一致は高々1つ）なら
+    /// `find_iter(hay).count()` と同じ正確な数。
+    /// 長ければ入力に等間隔に置いた窓（合計およそ `sample` バイト）の中の一致だけを数え、
+    /// 入力全体の長さに比例させた数を返す。窓の終端は入力の終わりとして扱うので、
+    /// `$` や `\z` を含むパターン、窓より長い一致では誤差が大きくなる。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let hay = "id=42; ".repeat(10_000);
+    /// assert_eq!(re.approximate_match_count(&hay[..70], 1024), 10);
+    /// let n = re.approximate_match_count(&hay, 4096);
+    /// assert!((9_500..=10_500).contains(&n));
+    /// ```
+    ///
+    /// # Panics
+    /// `find_iter` と同じく、探索が `MatchError` で失敗した場合。
+    pub fn approximate_match_count(&self, hay: &str, sample: usize) -> usize {
+        self.try_approximate_match_count(hay, sample)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `approximate_match_count` の失敗を返す版
+    pub fn try_approximate_match_count(
+        &self,
+        hay: &str,
+        sample: usize,
+    ) -> Result<usize, MatchError> {
+        let len = hay.len();
+        if len <= sample || self.strategy.text_anchored {
+            return self.count_in(hay, Span::new(0, len));
+        }
+        let width = (sample / WINDOWS).max(1);
+        let stride = len / WINDOWS;
+        let (mut found, mut read) = (0, 0);
+        for k in 0..WINDOWS {
+            let lo = char_floor(hay, k * stride);
+            let hi = char_floor(hay, (lo + width).min(len));
+            if hi > lo {
+                found += self.count_in(hay, Span::new(lo, hi))?;
+                read += hi - lo;
+            }
+        }
+        if read == 0 {
+            return Ok(0);
+        }
+        // found * len / read を四捨五入
+        Ok(((found as u128 * len as u128 + read as u128 / 2) / read as u128) as usize)
+    }
+
+    // span の中の一致を find_iter と同じ進め方で数える。span より前のバイトは境界の判定に使う
+    fn count_in(&self, hay: &str, span: Span) -> Result<usize, MatchError> {
+        // 固定文字列は memmem の方が速い。上限を付けたときは照合と同じく NFA で数える
+        #[cfg(feature = "dfa")]
+        if self.strategy.engine == Engine::Nfa
+            && self.thread_limit.is_none()
+            && let Some(dfa) = self.matching_dfa()
+        {
+            return self.count_with_dfa(dfa, hay, span);
+        }
+        let mut cursor = Cursor::new(span);
+        let mut n = 0;
+        while cursor
+            .try_next(hay, |opts| {
+                Ok(self.try_search(hay, opts)?.map(|m| (m.span(), ())))
+            })?
+            .is_some()
+        {
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    // `count_in` の DFA 版。最も左の開始位置は、各位置から DFA を走らせて最初に受理したところ。
+    // DFA の終端は最長一致なので、優先順位つきの一致ではその位置から NFA で終端を決め直す
+    #[cfg(feature = "dfa")]
+    fn count_with_dfa(&self, dfa: &Dfa, hay: &str, span: Span) -> Result<usize, MatchError> {
+        self.check_haystack_len(hay.as_bytes())?;
+        let bytes = &hay.as_bytes()[..span.end];
+        let mut cursor = Cursor::new(span);
+        let mut n = 0;
+        while cursor
+            .try_next(hay, |opts| {
+                let from = opts.span.map_or(span.start, |s| s.start);
+                for start in (from..=span.end).filter(|&k| hay.is_char_boundary(k)) {
+                    let Some(end) = dfa.try_run(bytes, start, &mut Run::new(false, false))? else {
+                        continue;
+                    };
+                    let end = if self.match_kind == MatchKind::LeftmostFirst {
+                        let opts = SearchOptions {
+                            span: Some(Span::new(start, span.end)),
+                            ..Default::default()
+                        };
+                        self.try_search(hay, &opts)?.map_or(end, |m| m.end())
+                    } else {
+                        end
+                    };
+                    return Ok(Some((Span::new(start, end), ())));
+                }
+                Ok(None)
+            })?
+            .is_some()
+        {
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+// i 以下で最も近い文字境界
+fn char_floor(hay: &str, mut i: usize) -> usize {
+    while !hay.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod estimate_tests {
+    use crate::{MatchError, Regex, RegexBuilder};
+
+    #[test]
+    fn short_inputs_are_counted_exactly() {
+        for (pat, hay) in [("[0-9]+", "a1b22c333"), ("x*", "axxb"), ("é", "éaé")] {
+            let re = Regex::new(pat).unwrap();
+            let exact = re.find_iter(hay).count();
+            assert_eq!(re.approximate_match_count(hay, hay.len()), exact, "{pat}");
+        }
+    }
+
+    #[test]
+    fn long_inputs_are_extrapolated() {
+        let re = Regex::new("ab").unwrap();
+        let hay = "ab-".repeat(30_000);
+        let n = re.approximate_match_count(&hay, 3 * 1024);
+        assert!((29_000..=31_000).contains(&n), "{n}");
+        // 窓が文字の途中に落ちても壊れない
+        let hay = "éab".repeat(20_000);
+        let n = re.approximate_match_count(&hay, 1000);
+        assert!((19_000..=21_000).contains(&n), "{n}");
+        assert_eq!(re.approximate_match_count(&"x".repeat(10_000), 100), 0);
+    }
+
+    #[test]
+    fn text_anchored_patterns_are_counted_exactly() {
+        // \A で始まれば一致は入力の先頭の高々1つなので、引き伸ばさない
+        let re = Regex::new(r"\Aa").unwrap();
+        let hay = "a".repeat(10_000);
+        assert_eq!(re.approximate_match_count(&hay, 160), 1);
+        assert_eq!(
+            re.approximate_match_count(&hay[1..].replace('a', "b"), 160),
+            0
+        );
+        let re = Regex::new(r"\Ax*").unwrap();
+        assert_eq!(re.approximate_match_count(&"y".repeat(10_000), 160), 1);
+    }
+
+    #[test]
+    fn windows_see_the_bytes_before_them() {
+        // (?m)^ は窓の先頭ではなく、入力の先頭か改行の直後でだけ成り立つ。
+        // 窓は 625 バイトおきなので行の途中（"ab\n" の前）からも始まるが、そこでは数えない
+        let re = Regex::new(r"(?m)^ab").unwrap();
+        let hay = "aab\n".repeat(2_500);
+        assert_eq!(re.approximate_match_count(&hay, 16 * 3), 0);
+    }
+
+    #[cfg(feature = "dfa")]
+    #[test]
+    fn dfa_counts_agree_with_find_iter() {
+        use crate::MatchKind;
+        let hays = ["abab", "aaa", "xaabbx", "", "ba", "abcabcab"];
+        for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+            for pat in ["a|aa", "a|ab", "(a|b)*c", "b*", "[a-c]+b"] {
+                let re = RegexBuilder::new(pat).match_kind(kind).build().unwrap();
+                assert!(re.strategy.dfa.get().is_none());
+                for hay in hays {
+                    let exact = re.find_iter(hay).count();
+                    let got = re.approximate_match_count(hay, hay.len());
+                    assert_eq!(got, exact, "{pat} {kind:?} {hay}");
+                }
+                assert!(re.strategy.dfa.get().is_some_and(Option::is_some), "{pat}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let re = RegexBuilder::new("a+")
+            .max_haystack_len(10)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.try_approximate_match_count(&"a".repeat(100), 16),
+            Err(MatchError::HaystackTooLong {
+                len: 100,
+                limit: 10
+            })
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+// fingerprint.rs
+//! `Regex::pattern_hash` の中身。プロセスや版をまたいで同じ値になるよう、
+//! 標準の `Hash`（実装も種も保証されない）は使わず、入力の並べ方とハッシュ関数をここで固定する。
+//!
+//! 形式（v1）: FNV-1a 64bit に次の順で流し込む。整数は u64 の little endian、
+//! 可変長のものは長さを先に、`Option` は 0（None）か 1 の後に値。
+//!
+//! 1. `b"my_regex/v1"`
+//! 2. 先頭の `(?ims)` を取り除いたパターン
+//! 3. literal, captures, whole_word, case_insensitive, multi_line, dot_matches_new_line（各1バイト）
+//! 4. match_kind（LeftmostFirst=1, LeftmostLongest=2。0 は欠番）
+//! 5. thread_limit, max_haystack_len
+//! 6. equivalent_bytes のグループ（区間列の小さい順）
+//! 7. define_class の集合（名前順に、名前と区間列）
+//!
+//! 形式を変えるときは版の文字列を上げ、古い値と混ざらないようにする。
+use crate::builder::{Config, MatchKind};
+use crate::class::ClassSet;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn int(&mut self, n: u64) {
+        self.bytes(&n.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.int(s.len() as u64);
+        self.bytes(s.as_bytes());
+    }
+
+    fn flag(&mut self, yes: bool) {
+        self.bytes(&[yes as u8]);
+    }
+
+    fn opt(&mut self, n: Option<usize>) {
+        match n {
+            None => self.bytes(&[0]),
+            Some(n) => {
+                self.bytes(&[1]);
+                self.int(n as u64);
+            }
+        }
+    }
+
+    fn set(&mut self, set: &ClassSet) {
+        self.int(set.ranges().len() as u64);
+        for &(lo, hi) in set.ranges() {
+            self.int(lo as u64);
+            self.int(hi as u64);
+        }
+    }
+}
+
+/// 先頭のフラグを `config` に移した後のパターン `pat` と設定のハッシュ
+pub(crate) fn pattern_hash(pat: &str, config: &Config) -> u64 {
+    let mut h = Fnv(FNV_OFFSET);
+    h.bytes(b"my_regex/v1");
+    h.str(pat);
+    for yes in [
+        config.literal,
+        config.captures,
+        config.whole_word,
+        config.case_insensitive,
+        config.multi_line,
+        config.dot_matches_new_line,
+    ] {
+        h.flag(yes);
+    }
+    h.int(match config.match_kind {
+        MatchKind::LeftmostFirst => 1,
+        MatchKind::LeftmostLongest => 2,
+    });
+    h.opt(config.thread_limit);
+    h.opt(config.max_haystack_len);
+    // グループの並びは追加の順に依存するので並べ直す
+    let mut groups: Vec<&ClassSet> = config.equivalences.groups().iter().collect();
+    groups.sort_by(|a, b| a.ranges().cmp(b.ranges()));
+    h.int(groups.len() as u64);
+    for g in groups {
+        h.set(g);
+    }
+    let mut classes: Vec<(&String, &ClassSet)> = config.classes.iter().collect();
+    classes.sort_by(|a, b| a.0.cmp(b.0));
+    h.int(classes.len() as u64);
+    for (name, set) in classes {
+        h.str(name);
+        h.set(set);
+    }
+    h.0
+}
+
+#[cfg(test)]
+mod fingerprint_tests {
+    use crate::{ClassSet, MatchKind, Regex, RegexBuilder};
+
+    fn hash(b: &mut RegexBuilder) -> u64 {
+        b.build().unwrap().pattern_hash()
+    }
+
+    #[test]
+    fn value_is_stable() {
+        // 版をまたいで変わらないことの確認。変わったら形式の版を上げる
+        assert_eq!(
+            Regex::new("a+b").unwrap().pattern_hash(),
+            0x47dc_0a4f_76d5_e731
+        );
+    }
+
+    #[test]
+    fn leading_flags_are_normalized() {
+        let ml = hash(RegexBuilder::new("^ab").multi_line(true));
+        assert_eq!(Regex::new("(?m)^ab").unwrap().pattern_hash(), ml);
+        assert_ne!(Regex::new("^ab").unwrap().pattern_hash(), ml);
+        // literal では (?m) もただの文字
+        assert_ne!(hash(RegexBuilder::new("(?m)^ab").literal(true)), ml);
+    }
+
+    #[test]
+    fn options_change_the_hash() {
+        let base = hash(&mut RegexBuilder::new("a|ab"));
+        for other in [
+            hash(RegexBuilder::new("a|ab").match_kind(MatchKind::LeftmostFirst)),
+            hash(RegexBuilder::new("a|ab").thread_limit(8)),
+            hash(RegexBuilder::new("a|ab").max_haystack_len(8)),
+            hash(RegexBuilder::new("a|ab").captures(false)),
+            hash(RegexBuilder::new("a|ab").equivalent_bytes(b"ab")),
+            hash(RegexBuilder::new("a|ab").define_class("x", ClassSet::from_ranges([(0, 1)]))),
+        ] {
+            assert_ne!(other, base);
+        }
+        // 照合に効かない設定は含めない
+        assert_eq!(hash(RegexBuilder::new("a|ab").repetition_limit(5)), base);
+    }
+
+    #[test]
+    fn definition_order_does_not_matter() {
+        let ab = hash(
+            RegexBuilder::new("x")
+                .equivalent_bytes(b"ab")
+                .equivalent_bytes(b"cd"),
+        );
+        let cd = hash(
+            RegexBuilder::new("x")
+                .equivalent_bytes(b"cd")
+                .equivalent_bytes(b"ab"),
+        );
+        assert_eq!(ab, cd);
+        let one = ClassSet::from_ranges([(1, 1)]);
+        let two = ClassSet::from_ranges([(2, 2)]);
+        let xy = hash(
+            RegexBuilder::new("x")
+                .define_class("x", one.clone())
+                .define_class("y", two.clone()),
+        );
+        let yx = hash(
+            RegexBuilder::new("x")
+                .define_class("y", two)
+                .define_class("x", one),
+        );
+        assert_eq!(xy, yx);
+    }
+}
@@ -0,0 +1,157 @@
+// generate.rs
+//! パターンに一致するバイト列を乱択で作る（プロパティテストやファズの種用）。
+//!
+//! NFA を start から乱択で歩き、文字を読む辺ではその集合から1バイトを選ぶ。
+//! 長さの上限は `min_rest`（受理まで最低あと何バイト要るか）で守る。
+use crate::Regex;
+use crate::nfa::Label;
+use crate::search::Run;
+
+// 歩き直す回数の上限（単語境界などで検証に落ちた場合）
+const ATTEMPTS: usize = 64;
+
+/// 依存を増やさないための小さな擬似乱数（xorshift64*）
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // 0 は不動点なので避ける
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15 | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// 0..n の一様乱数（n > 0）
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+impl Regex {
+    /// 完全一致する長さ `max_len` 以下のバイト列を、`seed` から決定的に1つ作る。
+    /// 否定クラスや `.` は集合の中から一様に選ぶので、UTF-8 とは限らない。
+    /// そのような列が存在しなければ `None`。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"[^a-z]\d+").unwrap();
+    /// for seed in 0..20 {
+    ///     let v = re.random_matching_bytes(seed, 5).unwrap();
+    ///     assert!((2..=5).contains(&v.len()));
+    ///     assert!(!v[0].is_ascii_lowercase());
+    ///     assert!(v[1..].iter().all(u8::is_ascii_digit));
+    /// }
+    /// ```
+    pub fn random_matching_bytes(&self, seed: u64, max_len: usize) -> Option<Vec<u8>> {
+        if self.min_rest[self.start] > max_len {
+            return None;
+        }
+        let mut rng = Rng::new(seed);
+        (0..ATTEMPTS).find_map(|_| {
+            let out = self.random_walk(&mut rng, max_len)?;
+            // Look の判定は歩いている途中では決められないので、最後に確かめる
+            let mut run = Run::new(false, true);
+            let ok =
+                matches!(self.try_run(&out, 0, &mut run), Ok(Some((end, _))) if end == out.len());
+            ok.then_some(out)
+        })
+    }
+
+    fn random_walk(&self, rng: &mut Rng, max_len: usize) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut s = self.start;
+        // ε の輪で回り続けないよう、辿る辺の数にも上限を置く
+        for _ in 0..(max_len + 1) * (self.states.len() + 1) {
+            let left = max_len - out.len();
+            // この先まだ受理に届く辺だけを候補にする
+            let choices: Vec<&(Label, usize)> = self.states[s]
+                .edges
+                .iter()
+                .filter(|(lbl, tgt)| {
+                    let cost = usize::from(lbl.byte_set().is_some());
+                    self.min_rest[*tgt].saturating_add(cost) <= left
+                })
+                .collect();
+            if s == self.accept && rng.below(choices.len() + 1) == 0 {
+                return Some(out);
+            }
+            if choices.is_empty() {
+                return (s == self.accept).then_some(out);
+            }
+            let (lbl, tgt) = choices[rng.below(choices.len())];
+            if let Some(set) = lbl.byte_set() {
+                let k = rng.below(set.count());
+                out.push(set.nth(k)?);
+            }
+            s = *tgt;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod generate_tests {
+    use crate::{Regex, RegexBuilder};
+
+    fn accepts(re: &Regex, v: &[u8]) -> bool {
+        // 生成物は UTF-8 とは限らないので、ASCII の場合だけ公開 API で確かめる
+        std::str::from_utf8(v).is_ok_and(|s| re.is_match(s))
+    }
+
+    #[test]
+    fn deterministic_for_seed() {
+        let re = Regex::new("(ab|c)*d").unwrap();
+        assert_eq!(
+            re.random_matching_bytes(7, 10),
+            re.random_matching_bytes(7, 10)
+        );
+        let distinct: std::collections::HashSet<_> = (0..50)
+            .map(|seed| re.random_matching_bytes(seed, 10).unwrap())
+            .collect();
+        assert!(distinct.len() > 3, "{distinct:?}");
+    }
+
+    #[test]
+    fn honors_length_bound() {
+        let re = Regex::new("a+b?").unwrap();
+        for seed in 0..100 {
+            let v = re.random_matching_bytes(seed, 3).unwrap();
+            assert!(!v.is_empty() && v.len() <= 3);
+            assert!(accepts(&re, &v), "{v:?}");
+        }
+        assert_eq!(Regex::new("abc").unwrap().random_matching_bytes(0, 2), None);
+        assert_eq!(
+            Regex::new("x*").unwrap().random_matching_bytes(3, 0),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn negated_classes_stay_outside() {
+        let re = Regex::new("[^a-y]").unwrap();
+        for seed in 0..200 {
+            let v = re.random_matching_bytes(seed, 1).unwrap();
+            assert_eq!(v.len(), 1);
+            assert!(!(b'a'..=b'y').contains(&v[0]));
+        }
+    }
+
+    #[test]
+    fn word_boundaries_are_verified() {
+        let re = RegexBuilder::new("[a-z ]+")
+            .whole_word(true)
+            .build()
+            .unwrap();
+        for seed in 0..50 {
+            let v = re.random_matching_bytes(seed, 6).unwrap();
+            assert!(accepts(&re, &v), "{v:?}");
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// group.rs
+//! キャプチャグループの情報を1か所にまとめた表。
+//!
+//! グループ番号は開き括弧の出現順で、0 はパターン全体（暗黙のグループ）。
+//! 実行器はグループ g の (開始, 終了) をスロット表の g 番目に記録する。
+use crate::span::Span;
+
+/// `Regex::group_info` が返すグループの表
+///
+/// ```
+/// use my_regex::Regex;
+/// let pat = r"(\w+)=(\d+)";
+/// let info = Regex::new(pat).unwrap().group_info().clone();
+/// assert_eq!(info.len(), 3);
+/// assert_eq!(info.explicit_len(), 2);
+/// assert_eq!(info.pattern_span(2).unwrap().slice(pat), r"(\d+)");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupInfo {
+    // [g] = グループ g の名前（`spans` と同じ長さ）。[0] は常に None
+    names: Vec<Option<String>>,
+    // [0]=パターン全体, [g]=グループ g の括弧を含むパターン中の範囲
+    spans: Vec<Span>,
+    // 記録するグループ数（0 を含む）。captures(false) なら 1
+    slots: usize,
+}
+
+impl GroupInfo {
+    /// `spans` と `names` は [0] がパターン全体、以降が括弧の出現順。
+    /// `captures` が false なら、パターン上の位置と名前は残すが記録はグループ 0 だけにする
+    pub(crate) fn new(spans: Vec<Span>, names: Vec<Option<String>>, captures: bool) -> Self {
+        let slots = if captures { spans.len() } else { 1 };
+        Self {
+            names,
+            spans,
+            slots,
+        }
+    }
+
+    /// グループ 0 を含むグループ数（`captures` が返す `Vec` の長さ）
+    pub fn len(&self) -> usize {
+        self.slots
+    }
+
+    /// 常に false（グループ 0 は必ずある）
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// 明示的な（括弧で書いた）グループの数
+    pub fn explicit_len(&self) -> usize {
+        self.slots - 1
+    }
+
+    /// グループの名前。名前の無いグループと範囲外は None
+    pub fn name(&self, group: usize) -> Option<&str> {
+        self.names.get(group)?.as_deref()
+    }
+
+    /// 名前からグループ番号を引く（`captures(false)` でも番号は返す）
+    pub fn to_index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n.as_deref() == Some(name))
+    }
+
+    /// 名前付きグループの名前（グループ番号順）
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().filter_map(|n| n.as_deref())
+    }
+
+    /// グループがパターン文字列のどこに書かれているか（括弧を含む）
+    pub fn pattern_span(&self, group: usize) -> Option<Span> {
+        self.spans.get(group).copied()
+    }
+
+    /// すべてのグループのパターン中の範囲。[0] はパターン全体
+    pub(crate) fn pattern_spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// グループ g の記録先（実行器のスロット表の添字）。記録しないグループは None
+    pub fn slot(&self, group: usize) -> Option<usize> {
+        (group < self.slots).then_some(group)
+    }
+}
+
+#[cfg(test)]
+mod group_tests {
+    use crate::{Regex, RegexBuilder, Span};
+
+    #[test]
+    fn counts_follow_parens() {
+        let info = Regex::new("((a)|b)(c)").unwrap().group_info().clone();
+        assert_eq!((info.len(), info.explicit_len()), (4, 3));
+        assert_eq!(info.slot(3), Some(3));
+        assert_eq!(info.slot(4), None);
+        assert_eq!(info.name(1), None);
+        assert_eq!(info.to_index("x"), None);
+
+        let info = Regex::new("(?<k>a)(b)(?P<v>c)")
+            .unwrap()
+            .group_info()
+            .clone();
+        assert_eq!(info.name(1), Some("k"));
+        assert_eq!(info.name(2), None);
+        assert_eq!(info.to_index("v"), Some(3));
+        assert_eq!(info.name(0), None);
+
+        // 読まれないグループも番号は持つ
+        let re = Regex::new("(b)(a){0}").unwrap();
+        assert_eq!(re.group_info().len(), 3);
+        let caps = re.captures("b").unwrap();
+        assert_eq!((caps.len(), &caps[1]), (3, "b"));
+        assert!(caps.get(2).is_none());
+    }
+
+    #[test]
+    fn no_captures_keeps_pattern_spans() {
+        let re = RegexBuilder::new("(a)(b)").captures(false).build().unwrap();
+        let info = re.group_info();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info.slot(1), None);
+        assert_eq!(info.pattern_span(2), Some(Span::new(3, 6)));
+    }
+}
@@ -0,0 +1,97 @@
+// iter.rs
+//! `Iterator<Item = &str>` 用の拡張トレイト（feature `iter`）。
+//!
+//! ```
+//! use my_regex::Regex;
+//! use my_regex::iter::RegexIterExt;
+//! let re = Regex::new(r"(\w+)=(\d+)").unwrap();
+//! let text = "a=1\n# comment\nb=22";
+//! let keys: Vec<&str> = text.lines().regex_captures(&re).map(|c| c.get(1).unwrap().as_str()).collect();
+//! assert_eq!(keys, vec!["a", "b"]);
+//! assert_eq!(text.lines().regex_filter(&re).count(), 2);
+//! ```
+use crate::{Captures, Regex};
+
+pub trait RegexIterExt<'a>: Iterator<Item = &'a str> + Sized {
+    /// `re.is_match` が真の要素だけを通す
+    fn regex_filter(self, re: &Regex) -> RegexFilter<'_, Self> {
+        RegexFilter { inner: self, re }
+    }
+
+    /// 一致した要素のキャプチャ（`Regex::captures` と同じ形）を返し、一致しない要素は飛ばす
+    fn regex_captures(self, re: &Regex) -> RegexCaptures<'_, Self> {
+        RegexCaptures { inner: self, re }
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a str>> RegexIterExt<'a> for I {}
+
+/// [`RegexIterExt::regex_filter`] の返すイテレータ
+#[derive(Clone, Debug)]
+pub struct RegexFilter<'r, I> {
+    inner: I,
+    re: &'r Regex,
+}
+
+impl<'a, I: Iterator<Item = &'a str>> Iterator for RegexFilter<'_, I> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.inner.by_ref().find(|s| self.re.is_match(s))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+/// [`RegexIterExt::regex_captures`] の返すイテレータ
+#[derive(Clone, Debug)]
+pub struct RegexCaptures<'r, I> {
+    inner: I,
+    re: &'r Regex,
+}
+
+impl<'a, I: Iterator<Item = &'a str>> Iterator for RegexCaptures<'_, I> {
+    type Item = Captures<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.by_ref().find_map(|s| self.re.captures(s))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod iter_tests {
+    use super::RegexIterExt;
+    use crate::Regex;
+
+    #[test]
+    fn filter_keeps_order_and_laziness() {
+        let re = Regex::new("[a-z]+").unwrap();
+        let items = ["abc", "12", "x", "", "Q"];
+        let got: Vec<&str> = items.iter().copied().regex_filter(&re).collect();
+        assert_eq!(got, vec!["abc", "x"]);
+
+        let mut it = items.iter().copied().regex_filter(&re);
+        assert_eq!(it.next(), Some("abc"));
+        assert_eq!(it.size_hint(), (0, Some(4)));
+    }
+
+    #[test]
+    fn captures_skip_non_matching() {
+        let re = Regex::new(r"(\d+)-(\d*)").unwrap();
+        let got: Vec<(String, String)> = "1-2\nxx\n30-"
+            .lines()
+            .regex_captures(&re)
+            .map(|c| (c[1].to_string(), c[2].to_string()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![("1".into(), "2".into()), ("30".into(), "".into())]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// json.rs
+//! 一致結果の JSON 表現（feature `serde_json`）。
+//!
+//! CLI やログ転送がそれぞれ独自の形を決めずに済むよう、ここで形を1つに決める。
+//! 位置はバイトオフセット、`end` は含まない。
+use serde_json::{Value, json};
+
+use crate::{Captures, Match, PatternReport};
+
+impl Match<'_> {
+    /// `{"start": 開始, "end": 終端, "text": 一致した文字列}`
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[a-z]+").unwrap();
+    /// let m = re.match_prefix("abc1").unwrap();
+    /// assert_eq!(m.to_json().to_string(), r#"{"end":3,"start":0,"text":"abc"}"#);
+    /// ```
+    pub fn to_json(&self) -> Value {
+        json!({
+            "start": self.start(),
+            "end": self.end(),
+            "text": self.as_str(),
+        })
+    }
+}
+
+impl Captures<'_> {
+    /// グループ順の配列。各要素は `Match::to_json` の形、参加しなかったグループは `null`
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("(a)|(b)").unwrap();
+    /// let v = re.captures("b").unwrap().to_json();
+    /// assert_eq!(v[1], serde_json::Value::Null);
+    /// assert_eq!(v[2]["text"], "b");
+    /// ```
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.iter()
+                .map(|m| m.map_or(Value::Null, |m| m.to_json()))
+                .collect(),
+        )
+    }
+}
+
+impl PatternReport {
+    /// フィールド名をそのままキーにしたオブジェクト。`prefilter` が無ければ `null`
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let v = Regex::new("(a+)b").unwrap().report().to_json();
+    /// assert_eq!(v["groups"], 1);
+    /// assert_eq!(v["unbounded_repetition"], true);
+    /// assert_eq!(v["engines"][0], "nfa");
+    /// ```
+    pub fn to_json(&self) -> Value {
+        json!({
+            "states": self.states,
+            "edges": self.edges,
+            "groups": self.groups,
+            "unbounded_repetition": self.unbounded_repetition,
+            "prefilter": self.prefilter,
+            "engines": self.engines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use crate::{Regex, SearchOptions};
+
+    #[test]
+    fn match_fields() {
+        let re = Regex::new("b+").unwrap();
+        let opts = SearchOptions {
+            anchored: false,
+            ..Default::default()
+        };
+        let m = re.search("aébbc", &opts).unwrap();
+        let v = m.to_json();
+        assert_eq!(v["start"], 3);
+        assert_eq!(v["end"], 5);
+        assert_eq!(v["text"], "bb");
+    }
+
+    #[test]
+    fn captures_keep_group_order() {
+        let re = Regex::new(r"(\w+)=(\d+)?").unwrap();
+        let v = re.captures("ab=").unwrap().to_json();
+        assert_eq!(v.as_array().unwrap().len(), 3);
+        assert_eq!(v[0]["end"], 3);
+        assert_eq!(v[1]["text"], "ab");
+        assert!(v[2].is_null());
+    }
+
+    #[test]
+    fn report_fields() {
+        let r = Regex::new("a{2}").unwrap().report();
+        let v = r.to_json();
+        assert_eq!(v["states"], r.states);
+        assert_eq!(v["edges"], r.edges);
+        assert_eq!(v["groups"], 0);
+        assert_eq!(v["unbounded_repetition"], false);
+        assert!(v["prefilter"].is_null());
+        assert_eq!(v["engines"].as_array().unwrap().len(), r.engines.len());
+    }
+}
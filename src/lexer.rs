@@ -0,0 +1,265 @@
+// lexer.rs
+//! 順序付きのトークン規則から字句解析器を作る。
+//!
+//! 今の位置から始まる一致を全規則で探し、最も長いものを1トークンとする（同じ長さなら先に足した規則）。
+//! 読み飛ばしの規則（空白・コメントなど）も同じ土俵で競い、勝ったらトークンを返さずに進む。
+use std::fmt;
+use std::iter::FusedIterator;
+
+use crate::Regex;
+use crate::error::{Error, MatchError};
+use crate::search::SearchOptions;
+use crate::span::Span;
+
+/// `Lexer` を作るビルダー
+///
+/// ```
+/// use my_regex::{LexerBuilder, Span};
+/// let lexer = LexerBuilder::new()
+///     .rule("[0-9]+")
+///     .rule("[a-z]+")
+///     .rule(r"\+|==?")
+///     .skip(r"\s+")
+///     .build()
+///     .unwrap();
+/// let tokens: Vec<(usize, &str)> = lexer
+///     .tokens("x = 12+y")
+///     .map(|t| t.map(|(rule, span)| (rule, span.slice("x = 12+y"))))
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(tokens, vec![(1, "x"), (2, "="), (0, "12"), (2, "+"), (1, "y")]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LexerBuilder {
+    // (パターン, 読み飛ばしか)。足した順
+    patterns: Vec<(String, bool)>,
+}
+
+impl LexerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// トークンの規則を足す。規則の番号は `rule` を呼んだ順（0 から、`skip` は数えない）
+    pub fn rule(&mut self, pattern: &str) -> &mut Self {
+        self.patterns.push((pattern.to_string(), false));
+        self
+    }
+
+    /// 読み飛ばす規則を足す。一致した部分はトークンとして返さない
+    pub fn skip(&mut self, pattern: &str) -> &mut Self {
+        self.patterns.push((pattern.to_string(), true));
+        self
+    }
+
+    /// すべての規則をコンパイルする。失敗は (足した順の添字, エラー) をすべて返す
+    /// （添字は `rule` と `skip` を通して数える。`Regex::try_new_many` と同じ形）
+    pub fn build(&self) -> Result<Lexer, Vec<(usize, Error)>> {
+        let regexes = Regex::try_new_many(self.patterns.iter().map(|(p, _)| p))?;
+        let mut next_id = 0;
+        let rules = regexes
+            .into_iter()
+            .zip(&self.patterns)
+            .map(|(re, &(_, skip))| {
+                let id = (!skip).then(|| {
+                    next_id += 1;
+                    next_id - 1
+                });
+                (re, id)
+            })
+            .collect();
+        Ok(Lexer { rules })
+    }
+}
+
+/// 順序付きのトークン規則。`LexerBuilder` で作る
+#[derive(Clone)]
+pub struct Lexer {
+    // (パターン, 規則の番号)。読み飛ばしの規則は None
+    rules: Vec<(Regex, Option<usize>)>,
+}
+
+impl Lexer {
+    /// 入力を先頭からトークンに切り分ける。要素は (規則の番号, 入力中の位置)。
+    /// どの規則も1バイト以上一致しない位置に来たら `LexError::NoMatch` を1つ返して終わる
+    /// （空一致はトークンにしない）。
+    pub fn tokens<'l, 'h>(&'l self, hay: &'h str) -> LexerTokens<'l, 'h> {
+        LexerTokens {
+            lexer: self,
+            hay,
+            at: 0,
+            failed: false,
+        }
+    }
+
+    // 位置 at から始まる最長の一致: (規則の番号, 終端)。同じ長さなら先の規則
+    fn longest_at(
+        &self,
+        hay: &str,
+        at: usize,
+    ) -> Result<Option<(Option<usize>, usize)>, MatchError> {
+        let opts = SearchOptions {
+            anchored: true,
+            span: Some(Span::new(at, hay.len())),
+            ..Default::default()
+        };
+        let mut best: Option<(Option<usize>, usize)> = None;
+        for (re, id) in &self.rules {
+            if let Some(m) = re.try_search(hay, &opts)?
+                && m.end() > best.map_or(at, |(_, end)| end)
+            {
+                best = Some((*id, m.end()));
+            }
+        }
+        Ok(best)
+    }
+}
+
+/// `Lexer::tokens` の失敗
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LexError {
+    /// この位置から1バイト以上一致する規則が無い
+    NoMatch { pos: usize },
+    /// 規則の照合が上限などで失敗した
+    Search(MatchError),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::NoMatch { pos } => write!(f, "no token rule matches at byte {pos}"),
+            LexError::Search(e) => write!(f, "token search failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// `Lexer::tokens` が返すイテレータ
+#[derive(Clone)]
+pub struct LexerTokens<'l, 'h> {
+    lexer: &'l Lexer,
+    hay: &'h str,
+    // 次のトークンを探す位置
+    at: usize,
+    // 失敗を返した後はもう何も返さない
+    failed: bool,
+}
+
+impl LexerTokens<'_, '_> {
+    /// 次に読む位置（ここより前はトークンか読み飛ばしとして消費済み）
+    pub fn position(&self) -> usize {
+        self.at
+    }
+}
+
+impl Iterator for LexerTokens<'_, '_> {
+    type Item = Result<(usize, Span), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed && self.at < self.hay.len() {
+            match self.lexer.longest_at(self.hay, self.at) {
+                Ok(Some((id, end))) => {
+                    let span = Span::new(self.at, end);
+                    self.at = end;
+                    if let Some(id) = id {
+                        return Some(Ok((id, span)));
+                    }
+                }
+                Ok(None) => {
+                    self.failed = true;
+                    return Some(Err(LexError::NoMatch { pos: self.at }));
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(LexError::Search(e)));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl FusedIterator for LexerTokens<'_, '_> {}
+
+#[cfg(test)]
+mod lexer_tests {
+    use super::{LexError, Lexer, LexerBuilder};
+    use crate::{ErrorKind, MatchError, Span};
+
+    fn lex<'h>(lexer: &Lexer, hay: &'h str) -> Vec<Result<(usize, &'h str), LexError>> {
+        lexer
+            .tokens(hay)
+            .map(|t| t.map(|(id, span)| (id, span.slice(hay))))
+            .collect()
+    }
+
+    #[test]
+    fn longest_match_wins_then_rule_order() {
+        let lexer = LexerBuilder::new()
+            .rule("if")
+            .rule("[a-z]+")
+            .rule("=")
+            .rule("==")
+            .build()
+            .unwrap();
+        assert_eq!(
+            lex(&lexer, "if==iff"),
+            vec![Ok((0, "if")), Ok((3, "==")), Ok((1, "iff"))]
+        );
+    }
+
+    #[test]
+    fn skip_rules_compete_but_yield_nothing() {
+        let lexer = LexerBuilder::new()
+            .skip(r"\s+")
+            .rule("[a-z]+")
+            .skip("#[^\n]*")
+            .rule("#")
+            .build()
+            .unwrap();
+        assert_eq!(lex(&lexer, " ab # c\nd"), vec![Ok((0, "ab")), Ok((0, "d"))]);
+        assert!(lex(&lexer, "  ").is_empty());
+        assert!(lex(&lexer, "").is_empty());
+    }
+
+    #[test]
+    fn stops_at_the_first_unknown_byte() {
+        let lexer = LexerBuilder::new()
+            .rule("[0-9]+")
+            .rule("x*")
+            .build()
+            .unwrap();
+        let mut tokens = lexer.tokens("12?3");
+        assert_eq!(tokens.next(), Some(Ok((0, Span::new(0, 2)))));
+        // x* は空一致しかしないのでトークンにならない
+        assert_eq!(tokens.next(), Some(Err(LexError::NoMatch { pos: 2 })));
+        assert_eq!(tokens.position(), 2);
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn errors() {
+        let Err(errs) = LexerBuilder::new().skip(" ").rule("(a").build() else {
+            panic!("build should fail");
+        };
+        assert_eq!(errs.len(), 1);
+        assert_eq!((errs[0].0, errs[0].1.kind), (1, ErrorKind::UnbalancedParen));
+        let lexer = LexerBuilder::new().rule("(a|aa)*b").build().unwrap();
+        let too_many = crate::RegexBuilder::new("(a|aa)*b")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        let limited = Lexer {
+            rules: vec![(too_many, Some(0))],
+        };
+        assert_eq!(lex(&lexer, "aab"), vec![Ok((0, "aab"))]);
+        assert!(matches!(
+            lex(&limited, "aaaab")[..],
+            [Err(LexError::Search(MatchError::TooManyThreads {
+                limit: 1
+            }))]
+        ));
+    }
+}
@@ -1,34 +1,228 @@
 // lib.rs
+#[cfg(feature = "tokio")]
+mod async_io;
+mod builder;
+mod captures;
+mod class;
+#[cfg(feature = "dfa")]
+pub mod codegen;
+#[cfg(test)]
+mod conformance;
+mod context;
+#[cfg(feature = "dfa")]
+mod dfa;
+mod diff;
 mod error;
+mod estimate;
+mod fingerprint;
+mod generate;
+mod group;
+#[cfg(feature = "iter")]
+pub mod iter;
+#[cfg(feature = "serde_json")]
+mod json;
+mod lexer;
+mod lines;
+pub mod lint;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod nfa;
 mod parse;
-mod token;
-
-use crate::nfa::build_nfa;
-use crate::parse::{insert_concat, to_postfix};
-use crate::token::tokenize;
-use crate::{
-    error::Error,
-    nfa::{Label, State},
+mod pikevm;
+mod progress;
+mod replace;
+mod report;
+mod resume;
+mod ruleset;
+mod search;
+mod span;
+mod split;
+mod strategy;
+mod stream;
+mod suggest;
+mod template;
+pub mod token;
+mod validate;
+
+pub use crate::builder::{MatchKind, RegexBuilder};
+pub use crate::captures::{AllGroupMatches, Captures};
+pub use crate::class::ClassSet;
+pub use crate::context::{ContextMatch, ContextMatches};
+pub use crate::diff::PatternDiff;
+pub use crate::error::{Error, ErrorKind, Feature, MatchError};
+pub use crate::group::GroupInfo;
+pub use crate::lexer::{LexError, Lexer, LexerBuilder, LexerTokens};
+pub use crate::lines::{LineMatch, LineMatches};
+pub use crate::progress::AlternativeProgress;
+pub use crate::replace::{NoExpand, Replacer};
+pub use crate::report::PatternReport;
+pub use crate::resume::SearchState;
+pub use crate::ruleset::{RuleSet, Rules, SetMatches};
+pub use crate::search::{FindMatches, SearchOptions};
+pub use crate::span::Span;
+pub use crate::split::{Segment, Split, SplitN};
+pub use crate::stream::MatchSink;
+pub use crate::template::PatternTemplate;
+pub use crate::token::escape;
+pub use crate::validate::ValidationError;
+
+use std::sync::Arc;
+
+use crate::builder::Config;
+use crate::class::ByteSet;
+use crate::nfa::{Label, State};
+use crate::nfa::{build_nfa, check_nested_repetition};
+use crate::parse::{drop_captures, insert_concat_with_pos, to_postfix_with_pos};
+use crate::search::{Run, Scratch};
+use crate::strategy::Strategy;
+use crate::token::{
+    InlineFlags, Lexeme, Look, LookCache, Token, apply_equivalence, group_name, leading_flags, lex,
+    scoped_flags,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Regex {
     states: Vec<State>,
     start: usize,
     accept: usize,
-    groups: usize, // ★ 追加：キャプチャ数（1..=groups）
+    // 各状態から accept までに最低限必要なバイト数（早期打ち切り用）
+    min_rest: Vec<usize>,
+    // 照合エンジンの振り分け（固定文字列なら NFA を回さない、など）
+    strategy: Strategy,
+    // Look を含まない（＝ε閉包が位置に依存しない）なら読み飛ばし最適化が使える
+    can_skip: bool,
+    // 同時に生きてよいスレッド数の上限
+    thread_limit: Option<usize>,
+    // これより長い入力は照合しない
+    max_haystack_len: Option<usize>,
+    match_kind: MatchKind,
+    // グループ数・名前・パターン中の位置。全実行器がスロット数をここから取る
+    group_info: Arc<GroupInfo>,
+    // 正規化したパターンと設定のハッシュ（`pattern_hash`）
+    pattern_hash: u64,
+    // トップレベルの `|` の各枝の (パターン中の範囲, 入口の状態)。`|` が無ければパターン全体の1つ
+    branches: Vec<(Span, usize)>,
+    // 照合回数などの計数（clone と共有）
+    #[cfg(feature = "metrics")]
+    counters: std::sync::Arc<metrics::Counters>,
+}
+
+/// 失敗を返さない API で探索が失敗したとき
+fn search_failed(e: MatchError) -> ! {
+    panic!("search failed: {e:?} (use the try_* methods when limits are configured)")
+}
+
+/// 報告する区間が UTF-8 の文字境界に乗っているかを確かめる（feature `boundary-audit`）。
+/// エンジンはバイト単位で動くので、`.` などが文字の途中で止まると後段の切り出しでパニックする。
+/// 監査を有効にすると、その手前で `MatchError::MisalignedSpan` として返す。無効なら何もしない。
+#[inline]
+fn audit_span(hay: &str, start: usize, end: usize) -> Result<(), MatchError> {
+    if cfg!(feature = "boundary-audit")
+        && !(hay.is_char_boundary(start) && hay.is_char_boundary(end))
+    {
+        return Err(MatchError::MisalignedSpan {
+            span: Span::new(start, end),
+        });
+    }
+    Ok(())
+}
+
+/// 一致した部分の位置（バイトオフセット）と中身
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match<'h> {
+    hay: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    fn new(hay: &'h str, start: usize, end: usize) -> Self {
+        Self { hay, start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.start, self.end)
+    }
+
+    /// `&hay[m.range()]` で一致部分を切り出せる
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.hay[self.start..self.end]
+    }
+}
+
+/// コンパイル済みの状態遷移を1状態1行で表示する。
+/// 例: `   3        'a' -> 4, ε -> 7`（start / accept には印を付ける）
+impl std::fmt::Debug for Regex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Regex {{ states: {}, groups: {} }}",
+            self.states.len(),
+            self.group_info.explicit_len()
+        )?;
+        let width = self.states.len().saturating_sub(1).to_string().len();
+        for (sid, st) in self.states.iter().enumerate() {
+            let mark = if sid == self.start {
+                "start "
+            } else if sid == self.accept {
+                "accept"
+            } else {
+                ""
+            };
+            write!(f, "  {sid:>width$} {mark:<6}")?;
+            for (k, (lbl, to)) in st.edges.iter().enumerate() {
+                let sep = if k == 0 { " " } else { ", " };
+                write!(f, "{sep}{lbl} -> {to}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
 }
 
 // 各スレッドが持つキャプチャ: (start,end) を Option<usize> で
 type GroupSlot = (Option<usize>, Option<usize>);
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 struct Thread {
     s: usize,
-    caps: Vec<GroupSlot>, // index=グループ番号（0は未使用）
+    caps: Vec<GroupSlot>, // index=グループ番号（0 の開始側はスレッドの開始位置）
+}
+
+/// 位置 `at` から始まるスレッドのスロット表。スロットがあれば 0 番に開始位置を入れる
+fn start_caps(at: usize, slots: usize) -> Vec<GroupSlot> {
+    let mut caps = vec![(None, None); slots];
+    if let Some(slot) = caps.first_mut() {
+        slot.0 = Some(at);
+    }
+    caps
+}
+
+/// スロット表が記録している開始位置（スロットを持たない実行では None）
+fn thread_start(caps: &[GroupSlot]) -> Option<usize> {
+    caps.first().and_then(|slot| slot.0)
+}
+
+/// unanchored 探索で位置 `i` から新しいスレッドを始めてよいか（UTF-8 の文字の途中は除く）
+fn is_char_start(bytes: &[u8], i: usize) -> bool {
+    bytes.get(i).is_none_or(|&b| !(0x80..0xC0).contains(&b))
 }
 
+/// 最長一致（`MatchKind::LeftmostLongest`）で、受理候補 `a` を今の最良 `b` より採るか。
+/// 規則は上から順に見て、最初に差が付いたもので決める。キャプチャが同じになる候補どうしを除けば
+/// 全順序なので、スレッドの並び順によらず結果は一つに決まる（`conformance` のテストで固定）。
 fn better_choice(a: &(usize, Vec<GroupSlot>), b: &(usize, Vec<GroupSlot>)) -> bool {
     // 1) end 位置（i）が大きい方を優先（最長一致）
     if a.0 != b.0 {
@@ -51,147 +245,662 @@ fn better_choice(a: &(usize, Vec<GroupSlot>), b: &(usize, Vec<GroupSlot>)) -> bo
             _ => {}
         }
     }
-    // 4) ここまで同じなら b を維持（a を採用しない）
+    // 4) それでも同じなら、番号の小さいグループから見て参加している方（`(a)?(a)?` は前が取る）
+    for g in 1..len {
+        let (pa, pb) = (participates(ga[g]), participates(gb[g]));
+        if pa != pb {
+            return pa;
+        }
+    }
+    // 5) ここまで同じなら報告するキャプチャも同じ。b を維持（a を採用しない）
     false
 }
 
+// `captures_from_slots` がグループとして報告するスロットか（閉じていない繰り返しの途中は除く）
+fn participates(slot: GroupSlot) -> bool {
+    matches!(slot, (Some(s), Some(e)) if s <= e)
+}
+
 impl Regex {
     pub fn new(pat: &str) -> Result<Self, Error> {
-        // アンカーは常に有効（^…$ を暗黙）
-        let tokens = tokenize(pat)?;
-        let tokens = insert_concat(&tokens);
-        let postfix = to_postfix(&tokens)?;
-        let nfa = build_nfa(&postfix)?;
+        RegexBuilder::new(pat).build()
+    }
 
-        // NFA中の最大グループ番号を拾う
-        let mut gmax = 0usize;
-        for st in &nfa.states {
-            for (lbl, _) in &st.edges {
-                match lbl {
-                    Label::CapBegin(g) | Label::CapEnd(g) => gmax = gmax.max(*g),
-                    _ => {}
-                }
+    /// 複数パターンをまとめてコンパイルする。
+    /// すべて成功すれば入力順の `Vec<Regex>`、1つでも失敗すれば
+    /// (入力中の添字, エラー) を失敗したものすべてについて返す。
+    ///
+    /// クラスは固定長ビットマップで各状態に直接埋め込むので、
+    /// パターン間で共有すべき大きなテーブルは今のところ無い。
+    pub fn try_new_many<I, S>(patterns: I) -> Result<Vec<Regex>, Vec<(usize, Error)>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut ok = Vec::new();
+        let mut errs = Vec::new();
+        for (i, p) in patterns.into_iter().enumerate() {
+            match Regex::new(p.as_ref()) {
+                Ok(re) => ok.push(re),
+                Err(e) => errs.push((i, e)),
+            }
+        }
+        if errs.is_empty() { Ok(ok) } else { Err(errs) }
+    }
+
+    /// 構文機能に対応しているかを返す。フロントエンドがエラー文字列に頼らず
+    /// 入力補助や案内を切り替えるためのもの。未対応の構文は
+    /// `ErrorKind::UnsupportedFeature` で拒否される。
+    pub fn supports(feature: Feature) -> bool {
+        match feature {
+            Feature::CaptureGroup
+            | Feature::NamedGroup
+            | Feature::CountedRepetition
+            | Feature::TextAnchor
+            | Feature::InlineFlags
+            | Feature::NonCapturingGroup => true,
+            Feature::Lookaround
+            | Feature::Backreference
+            | Feature::WordBoundary
+            | Feature::UnicodeClass => false,
+        }
+    }
+
+    pub(crate) fn with_config(pat: &str, config: &Config) -> Result<Self, Error> {
+        // 先頭の (?i) / (?m) / (?s) はビルダーで対応する設定を立てたのと同じ
+        let with_flags;
+        let (flag_len, config) = match leading_flags(pat) {
+            (len, flags) if len > 0 && !config.literal => {
+                with_flags = Config {
+                    case_insensitive: config.case_insensitive || flags.case_insensitive,
+                    multi_line: config.multi_line || flags.multi_line,
+                    dot_matches_new_line: config.dot_matches_new_line || flags.dot_matches_new_line,
+                    ..config.clone()
+                };
+                (len, &with_flags)
             }
+            _ => (0, config),
+        };
+        let pattern_hash = fingerprint::pattern_hash(&pat[flag_len..], config);
+        // アンカーは常に有効（^…$ を暗黙）
+        // 各段のトークンにはパターン中の位置を並走させ、どの段のエラーも元の位置を指すようにする
+        let mut group_spans = vec![Span::new(0, pat.len())];
+        let mut group_names = vec![None];
+        // 各トークンの位置で効いているフラグ（(?i:...) の中だけ変わる）
+        let base = InlineFlags {
+            case_insensitive: config.case_insensitive,
+            multi_line: config.multi_line,
+            dot_matches_new_line: config.dot_matches_new_line,
+        };
+        let mut flags = vec![base; pat.len()];
+        let mut bars = Vec::new();
+        let (tokens, pos): (Vec<Token>, Vec<usize>) = if config.literal {
+            pat.bytes()
+                .enumerate()
+                .map(|(i, b)| (Token::Char(b), i))
+                .unzip()
+        } else {
+            let spanned = lex(pat)?;
+            group_spans.extend(paren_spans(&spanned));
+            group_names.extend(
+                spanned
+                    .iter()
+                    .filter(|t| t.token == Token::LParen)
+                    .map(|t| group_name(t.span.slice(pat)).map(str::to_string)),
+            );
+            flags = scoped_flags(&spanned, pat, base);
+            bars = top_level_bars(&spanned);
+            spanned.into_iter().map(|t| (t.token, t.span.start)).unzip()
+        };
+        // \p{name} を定義済みの集合に置き換える
+        let tokens = tokens
+            .into_iter()
+            .zip(&pos)
+            .map(|(t, &p)| match t {
+                Token::NamedClass { name, neg } => match config.classes.get(&name) {
+                    Some(set) if neg => Ok(Token::CodepointClass(set.negate())),
+                    Some(set) => Ok(Token::CodepointClass(set.clone())),
+                    None => error::err(ErrorKind::UnsupportedFeature(Feature::UnicodeClass), p),
+                },
+                other => Ok(other),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // {m,n} は NFA 上で回数分複製するので、信頼できないパターンから巨大な展開を作らせない
+        for (t, &p) in tokens.iter().zip(&pos) {
+            if let Token::Repeat { min, max } = t
+                && max.unwrap_or(*min) > config.repetition_limit
+            {
+                return Err(Error {
+                    kind: ErrorKind::RepetitionTooLarge {
+                        limit: config.repetition_limit,
+                    },
+                    pos: p,
+                });
+            }
+        }
+        // 複数行モードでなければ ^ / $ は入力の先頭・末尾でだけ成り立つ（\A / \z と同じ）。
+        // `.` は既定では \n に一致しない
+        let (plain, folded) = (
+            config.equivalences_for(false),
+            config.equivalences_for(true),
+        );
+        let tokens: Vec<Token> = tokens
+            .into_iter()
+            .zip(&flags)
+            .map(|(t, f)| {
+                let t = match t {
+                    Token::Look(Look::LineStart) if !f.multi_line => Token::Look(Look::TextStart),
+                    Token::Look(Look::LineEnd) if !f.multi_line => Token::Look(Look::TextEnd),
+                    Token::Dot if !f.dot_matches_new_line => Token::Class {
+                        ranges: vec![(b'\n', b'\n')],
+                        neg: true,
+                    },
+                    other => other,
+                };
+                apply_equivalence(t, if f.case_insensitive { &folded } else { &plain })
+            })
+            .collect();
+        let (tokens, pos) = insert_concat_with_pos(&tokens, &pos);
+        let (mut postfix, mut postfix_pos) = to_postfix_with_pos(&tokens, &pos)?;
+        if !config.captures {
+            // 記録しないなら Cap の状態そのものを作らない
+            (postfix, postfix_pos) = drop_captures(&postfix, &postfix_pos);
+        }
+        if config.whole_word {
+            // \b · (pattern) · \b  →  後置: \b P · \b ·
+            postfix.insert(0, Token::Look(Look::WordBoundary));
+            postfix_pos.insert(0, 0);
+            postfix.push(Token::Concat);
+            postfix.push(Token::Look(Look::WordBoundary));
+            postfix.push(Token::Concat);
+            postfix_pos.extend([pat.len(); 3]);
         }
+        let top_alts: Vec<bool> = postfix
+            .iter()
+            .zip(&postfix_pos)
+            .map(|(t, p)| *t == Token::Alt && bars.contains(p))
+            .collect();
+        // build_nfa のエラー位置は後置列の添字なので、パターン中の位置に引き直す
+        let at_pattern = |e: Error| Error {
+            pos: postfix_pos.get(e.pos).copied().unwrap_or(pat.len()),
+            ..e
+        };
+        check_nested_repetition(&postfix, config.repetition_limit).map_err(at_pattern)?;
+        let nfa = build_nfa(&postfix, &top_alts).map_err(at_pattern)?;
+
+        let strategy = Strategy::choose(&tokens, &nfa, config);
+        let min_rest = nfa.min_remaining();
+        let can_skip = !nfa
+            .states
+            .iter()
+            .any(|st| st.edges.iter().any(|(l, _)| matches!(l, Label::Look(_))));
 
         Ok(Self {
             states: nfa.states,
             start: nfa.start,
             accept: nfa.accept,
-            groups: gmax,
+            min_rest,
+            strategy,
+            can_skip,
+            thread_limit: config.thread_limit,
+            max_haystack_len: config.max_haystack_len,
+            match_kind: config.match_kind,
+            group_info: Arc::new(GroupInfo::new(group_spans, group_names, config.captures)),
+            pattern_hash,
+            branches: if nfa.branches.is_empty() {
+                vec![(Span::new(flag_len, pat.len()), nfa.start)]
+            } else {
+                // 枝 k は k-1 番目の `|` の直後から k 番目の `|` まで
+                let starts = std::iter::once(flag_len).chain(bars.iter().map(|&b| b + 1));
+                let ends = bars.iter().copied().chain(std::iter::once(pat.len()));
+                starts
+                    .zip(ends)
+                    .map(|(lo, hi)| Span::new(lo, hi))
+                    .zip(nfa.branches)
+                    .collect()
+            },
+            #[cfg(feature = "metrics")]
+            counters: metrics::counters(config.metrics_name.as_deref()),
         })
     }
 
+    /// 各キャプチャグループがパターン文字列のどこに書かれているか（括弧を含む）。
+    /// 添字は `captures` と同じで、`[0]` はパターン全体。
+    /// `RegexBuilder::captures(false)` でもパターン上の位置は返す。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let pat = r"(\w+)=(\d+)";
+    /// let re = Regex::new(pat).unwrap();
+    /// let spans = re.capture_group_spans_in_pattern();
+    /// assert_eq!(spans[2].slice(pat), r"(\d+)");
+    /// ```
+    pub fn capture_group_spans_in_pattern(&self) -> &[Span] {
+        self.group_info.pattern_spans()
+    }
+
+    /// キャプチャグループの表（数・名前・パターン中の位置）
+    pub fn group_info(&self) -> &GroupInfo {
+        &self.group_info
+    }
+
+    /// パターンと照合に効く設定から決まる 64bit のハッシュ。分散したノード間で共有する
+    /// キャッシュのキーなど、プロセスや版をまたいで同じ値が要る用途向け（標準の `Hash` は使わない）。
+    ///
+    /// 先頭の `(?i)` などはビルダーで同じ設定を立てたものと同じ値になる。
+    /// `repetition_limit` や `metrics_name` のように、コンパイルできた後の照合に効かない設定は含めない。
+    /// 形式を変えるときは計算の版を上げるので、値が一致すれば同じ形式で計算されている。
+    ///
+    /// ```
+    /// use my_regex::{Regex, RegexBuilder};
+    /// let a = Regex::new("(?m)^ab").unwrap();
+    /// let b = RegexBuilder::new("^ab").multi_line(true).build().unwrap();
+    /// assert_eq!(a.pattern_hash(), b.pattern_hash());
+    /// assert_ne!(a.pattern_hash(), Regex::new("^ab").unwrap().pattern_hash());
+    /// ```
+    pub fn pattern_hash(&self) -> u64 {
+        self.pattern_hash
+    }
+
     /// 完全一致（全消費）かどうか
+    ///
+    /// # Panics
+    /// `RegexBuilder::thread_limit` などの上限を超えた場合。上限を設定したら `try_is_match` を使う。
     pub fn is_match(&self, hay: &str) -> bool {
-        self.captures(hay).is_some()
+        self.try_is_match(hay).unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `is_match` の失敗を返す版
+    pub fn try_is_match(&self, hay: &str) -> Result<bool, MatchError> {
+        self.try_is_match_bytes(hay.as_bytes())
+    }
+
+    /// `is_match` の、UTF-8 とは限らないバイト列版（パケットの中身など）。
+    /// `.` や否定クラスは1バイトに一致するので、不正な UTF-8 もそのまま照合できる。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"GET .*\r").unwrap();
+    /// assert!(re.is_match_bytes(b"GET /\xff\xfe\r"));
+    /// ```
+    ///
+    /// # Panics
+    /// `is_match` と同じ。
+    pub fn is_match_bytes(&self, hay: &[u8]) -> bool {
+        self.try_is_match_bytes(hay)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `is_match_bytes` の失敗を返す版
+    pub fn try_is_match_bytes(&self, bytes: &[u8]) -> Result<bool, MatchError> {
+        self.try_is_match_in(bytes, &mut Run::new(false, true))
+    }
+
+    // 完全一致の判定を、呼び出し側の `Run`（作業領域ごと）で行う
+    fn try_is_match_in(&self, bytes: &[u8], run: &mut Run) -> Result<bool, MatchError> {
+        let res = self.check_haystack_len(bytes).and_then(|_| {
+            Ok(matches!(self.try_run(bytes, 0, run)?, Some((end, _)) if end == bytes.len()))
+        });
+        self.observe(res, |&ok| ok)
+    }
+
+    /// 完全一致しない入力について、一致が不可能になった最初の位置を返す。
+    /// そのバイトを読んだ時点で全スレッドが死んだならその位置、
+    /// 最後まで読めたが受理に届かない（入力が足りない）なら `hay.len()`。
+    /// 一致するなら `None`。入力フォームで「このあたりから書式が違う」と示すためのもの。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"\d\d\d\d-\d\d-\d\d").unwrap();
+    /// assert_eq!(re.shortest_fail_position("2024-1x-01"), Some(6));
+    /// assert_eq!(re.shortest_fail_position("2024-10"), Some(7));
+    /// assert_eq!(re.shortest_fail_position("2024-10-01"), None);
+    /// ```
+    ///
+    /// # Panics
+    /// `RegexBuilder::thread_limit` などの上限を超えた場合。
+    pub fn shortest_fail_position(&self, hay: &str) -> Option<usize> {
+        self.try_shortest_fail_position(hay)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `shortest_fail_position` の失敗を返す版
+    pub fn try_shortest_fail_position(&self, hay: &str) -> Result<Option<usize>, MatchError> {
+        self.check_haystack_len(hay)?;
+        self.fail_position_from(self.start, hay.as_bytes())
     }
 
-    /// 完全一致時にキャプチャを返す。
-    /// 返り値: Vec<Option<&str>> で、[0] が全体、[1..=groups] が各グループ。
-    pub fn captures<'a>(&self, hay: &'a str) -> Option<Vec<Option<&'a str>>> {
+    // 状態 `entry` から読み始めたときの `shortest_fail_position`
+    fn fail_position_from(&self, entry: usize, bytes: &[u8]) -> Result<Option<usize>, MatchError> {
+        // 残り長による打ち切りはしない（死んだ位置そのものを知りたいので）
+        let mut curr = vec![Thread {
+            s: entry,
+            caps: Vec::new(),
+        }];
+        self.eps_closure(&mut curr, bytes, 0);
+        self.check_thread_limit(&curr)?;
+        for (i, &b) in bytes.iter().enumerate() {
+            let mut next = self.step_byte(&curr, b);
+            if next.is_empty() {
+                return Ok(Some(i));
+            }
+            self.eps_closure(&mut next, bytes, i + 1);
+            self.check_thread_limit(&next)?;
+            curr = next;
+        }
+        let accepted = curr.iter().any(|t| t.s == self.accept);
+        Ok((!accepted).then_some(bytes.len()))
+    }
+
+    /// 複数の入力をまとめて完全一致で判定する。結果は入力順に並ぶ。
+    /// 実行器の作業領域は入力をまたいで使い回すので、`is_match` を繰り返すより確保が少ない。
+    ///
+    /// # Panics
+    /// `is_match` と同じ。上限を設定したら `try_matches_many` を使う。
+    pub fn matches_many<'a>(&self, haystacks: impl Iterator<Item = &'a str>) -> Vec<bool> {
+        self.try_matches_many(haystacks)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `matches_many` の失敗を返す版。最初に失敗した入力で止める
+    pub fn try_matches_many<'a>(
+        &self,
+        haystacks: impl Iterator<Item = &'a str>,
+    ) -> Result<Vec<bool>, MatchError> {
+        let mut run = Run::new(false, true);
+        haystacks
+            .map(|hay| self.try_is_match_in(hay.as_bytes(), &mut run))
+            .collect()
+    }
+
+    /// 先頭（位置0）から始まる最長一致を返す。入力全体を消費しなくてもよい。
+    /// 手書きレキサのループで「ここから読めるトークン」を取るための基本操作。
+    pub fn match_prefix<'h>(&self, hay: &'h str) -> Option<Match<'h>> {
+        let (end, _) = self.run(hay.as_bytes(), Run::new(false, false))?;
+        audit_span(hay, 0, end).unwrap_or_else(|e| search_failed(e));
+        Some(Match::new(hay, 0, end))
+    }
+
+    /// 入力のどこかにある一致のうち、最も左から始まるものを返す。
+    /// 同じ位置から始まる候補の選び方は `match_kind` に従う（既定は最長）。
+    ///
+    /// # Panics
+    /// `try_find` が `MatchError` を返す場合。
+    pub fn find<'h>(&self, hay: &'h str) -> Option<Match<'h>> {
+        self.try_find(hay).unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `find` の失敗を返す版
+    pub fn try_find<'h>(&self, hay: &'h str) -> Result<Option<Match<'h>>, MatchError> {
+        let opts = SearchOptions {
+            anchored: false,
+            ..Default::default()
+        };
+        self.try_search(hay, &opts)
+    }
+
+    /// 完全一致時にキャプチャを返す。`[0]` が全体、`[1..]` が各グループ。
+    ///
+    /// # Panics
+    /// `RegexBuilder::thread_limit` などの上限を超えた場合。上限を設定したら `try_captures` を使う。
+    pub fn captures<'h>(&self, hay: &'h str) -> Option<Captures<'h>> {
+        self.try_captures(hay).unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `captures` の失敗を返す版
+    pub fn try_captures<'h>(&self, hay: &'h str) -> Result<Option<Captures<'h>>, MatchError> {
+        let res = self.full_captures(hay);
+        self.observe(res, Option::is_some)
+    }
+
+    fn full_captures<'h>(&self, hay: &'h str) -> Result<Option<Captures<'h>>, MatchError> {
+        self.check_haystack_len(hay)?;
         let bytes = hay.as_bytes();
-        let (end, caps) = self.run(bytes)?;
+        let Some((end, caps)) = self.try_run(bytes, 0, &mut Run::new(true, true))? else {
+            return Ok(None);
+        };
 
         if end != bytes.len() {
-            return None; // 全消費のみOK
+            return Ok(None); // 全消費のみOK
         }
+        self.captures_from_slots(hay, 0, end, &caps).map(Some)
+    }
 
+    /// 実行器のスロット表を `Captures` にする。`[0]` は一致全体 `start..end`
+    fn captures_from_slots<'h>(
+        &self,
+        hay: &'h str,
+        start: usize,
+        end: usize,
+        caps: &[GroupSlot],
+    ) -> Result<Captures<'h>, MatchError> {
         // [0]=全体, 1..=groups
-        let mut out: Vec<Option<&'a str>> = vec![None; self.groups + 1];
-        out[0] = Some(hay); // 全体（常に完全一致前提）
-
-        for g in 1..=self.groups {
-            if let Some((Some(s), Some(e))) = caps.get(g).copied() {
-                if s <= e && e <= hay.len() {
-                    out[g] = Some(&hay[s..e]);
-                }
+        let mut out = vec![None; self.group_info.len()];
+        out[0] = Some((start, end));
+
+        for (slot, cap) in out.iter_mut().zip(caps.iter()).skip(1) {
+            if let (Some(s), Some(e)) = *cap
+                && s <= e
+                && e <= hay.len()
+            {
+                audit_span(hay, s, e)?;
+                *slot = Some((s, e));
             }
         }
-        Some(out)
+        Ok(Captures::new(hay, out, Arc::clone(&self.group_info)))
     }
 
     // ===== 実行器（NFAシミュレーション with captures） =====
 
-    fn run(&self, bytes: &[u8]) -> Option<(usize, Vec<GroupSlot>)> {
+    /// 失敗を返さない呼び出し元向け（上限超過はパニック）
+    fn run(&self, bytes: &[u8], mut run: Run) -> Option<(usize, Vec<GroupSlot>)> {
+        let res = self
+            .check_haystack_len(bytes)
+            .and_then(|_| self.try_run(bytes, 0, &mut run));
+        self.observe(res, Option::is_some)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// 計数を取らない構成では結果をそのまま返す（feature `metrics` 側の実装と対）
+    #[cfg(not(feature = "metrics"))]
+    #[inline]
+    fn observe<T>(
+        &self,
+        res: Result<T, MatchError>,
+        _matched: impl FnOnce(&T) -> bool,
+    ) -> Result<T, MatchError> {
+        res
+    }
+
+    /// `RegexBuilder::max_haystack_len` の検査。各 API の入口で入力全体に対して呼ぶ。
+    pub(crate) fn check_haystack_len(&self, hay: impl AsRef<[u8]>) -> Result<(), MatchError> {
+        let len = hay.as_ref().len();
+        match self.max_haystack_len {
+            Some(limit) if len > limit => Err(MatchError::HaystackTooLong { len, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 全スレッドを (状態, caps) で並走させ、`better_choice` で最良の受理を選ぶ。
+    /// 最長一致は末尾まで読めればそれが最長なので `run.need_end` は見なくてよい。
+    ///
+    /// `run.unanchored` なら一致が見つかるまで各位置で開始スレッドを足す。同じ状態にいる
+    /// スレッドは開始の最も左のものだけ残す（右から始まったものが受理できるなら左のものもできる）。
+    /// 一致が見つかった後は、それより左から始まったスレッドが尽きるまで読み進める。
+    fn try_run_longest(
+        &self,
+        bytes: &[u8],
+        at: usize,
+        slots: usize,
+        run: &mut Run,
+    ) -> Result<Option<(usize, Vec<GroupSlot>)>, MatchError> {
         let n = bytes.len();
 
-        let mut curr = vec![Thread {
+        let Scratch {
+            mut curr,
+            next: mut spare,
+            seen,
+        } = std::mem::take(&mut run.scratch);
+        // caps が空なら CapBegin/CapEnd は添字範囲外として素通りする
+        curr.clear();
+        curr.push(Thread {
             s: self.start,
-            caps: vec![(None, None); self.groups + 1],
-        }];
-        self.eps_closure(&mut curr, 0);
+            caps: start_caps(at, slots),
+        });
+        self.eps_closure(&mut curr, bytes, at);
+        self.check_thread_limit(&curr)?;
 
         let mut last: Option<(usize, Vec<GroupSlot>)> = None;
 
-        let mut i = 0usize;
+        let mut i = at;
         while i <= n {
-            // 受理チェック：全受理スレッドからベターなものを選ぶ
+            // 受理チェック：全受理スレッドからベターなものを選ぶ（開始が左のものが先）
             for t in curr.iter().filter(|t| t.s == self.accept) {
                 let cand = (i, t.caps.clone());
-                if let Some(best) = &mut last {
-                    if better_choice(&cand, best) {
-                        *best = cand;
+                let take = last.as_ref().is_none_or(|best| {
+                    match thread_start(&cand.1).cmp(&thread_start(&best.1)) {
+                        std::cmp::Ordering::Equal => better_choice(&cand, best),
+                        ord => ord.is_lt(),
                     }
-                } else {
+                });
+                if take {
                     last = Some(cand);
                 }
             }
+            // 一致より右から始まったスレッドは要らない。earliest なら同じ開始のものも終わり
+            if let Some((_, best)) = &last {
+                let best = thread_start(best);
+                curr.retain(|t| {
+                    let start = thread_start(&t.caps);
+                    start < best || (!run.earliest && start == best)
+                });
+            }
+            let adding = run.unanchored && last.is_none();
 
-            if i == n {
+            if i == n || (curr.is_empty() && !adding) {
                 break;
             }
 
-            let b = bytes[i];
-            let mut next: Vec<Thread> = Vec::new();
+            // 残りバイト数では accept に届かないスレッドしか無ければ打ち切り
+            // （これから足す開始スレッドも、今の位置から始めて届かないなら届かない）
+            if curr.iter().all(|t| self.min_rest[t.s] > n - i)
+                && (!adding || self.min_rest[self.start] > n - i)
+            {
+                break;
+            }
 
-            for thr in &curr {
-                for (lbl, tgt) in &self.states[thr.s].edges {
-                    match lbl {
-                        Label::Byte(c) if *c == b => {
-                            next.push(Thread {
-                                s: *tgt,
-                                caps: thr.caps.clone(),
-                            });
-                        }
-                        Label::Any => {
-                            next.push(Thread {
-                                s: *tgt,
-                                caps: thr.caps.clone(),
-                            });
-                        }
-                        Label::Class { ranges, neg } => {
-                            let hit = ranges.iter().any(|&(lo, hi)| lo <= b && b <= hi);
-                            if (*neg && !hit) || (!*neg && hit) {
-                                next.push(Thread {
-                                    s: *tgt,
-                                    caps: thr.caps.clone(),
-                                });
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+            run.step()?;
+            let mut next = std::mem::take(&mut spare);
+            self.step_byte_into(&curr, bytes[i], &mut next);
+            if adding && is_char_start(bytes, i + 1) {
+                next.push(Thread {
+                    s: self.start,
+                    caps: start_caps(i + 1, slots),
+                });
             }
 
-            if next.is_empty() {
+            if next.is_empty() && !adding {
+                spare = next;
                 break;
             }
 
-            self.eps_closure(&mut next, i + 1);
-            curr = dedup_threads(next);
+            self.eps_closure(&mut next, bytes, i + 1);
+            if run.unanchored {
+                keep_leftmost_starts(&mut next);
+            }
+            self.check_thread_limit(&next)?;
+
+            // 末尾の `.*` や `[^X]*` のように、1バイト読んでもスレッド集合が変わらず、
+            // しかも全スレッドが同じバイト集合しか読まないなら、
+            // その集合に入るバイトが続く限り結果は同じ。まとめて読み飛ばす。
+            if self.can_skip
+                && next == curr
+                && let Some(set) = self.uniform_byte_set(&curr)
+            {
+                let skip = set.prefix_len(&bytes[i + 1..]);
+                run.step_n(skip)?;
+                i += skip;
+            }
+
+            spare = std::mem::replace(&mut curr, next);
             i += 1;
         }
 
-        last
+        run.scratch = Scratch {
+            curr,
+            next: spare,
+            seen,
+        };
+        Ok(last)
+    }
+
+    /// 各スレッドがバイト `b` を1つ読んだ先（ε閉包はまだ取らない）
+    fn step_byte(&self, curr: &[Thread], b: u8) -> Vec<Thread> {
+        let mut next = Vec::new();
+        self.step_byte_into(curr, b, &mut next);
+        next
+    }
+
+    // `step_byte` の、呼び出し側のバッファに入れる版
+    fn step_byte_into(&self, curr: &[Thread], b: u8, next: &mut Vec<Thread>) {
+        next.clear();
+        for thr in curr {
+            for (lbl, tgt) in &self.states[thr.s].edges {
+                match lbl {
+                    Label::Byte(c) if *c == b => {
+                        next.push(Thread {
+                            s: *tgt,
+                            caps: thr.caps.clone(),
+                        });
+                    }
+                    Label::Any => {
+                        next.push(Thread {
+                            s: *tgt,
+                            caps: thr.caps.clone(),
+                        });
+                    }
+                    Label::Class(set) if set.contains(b) => {
+                        next.push(Thread {
+                            s: *tgt,
+                            caps: thr.caps.clone(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn check_thread_limit(&self, set: &[Thread]) -> Result<(), MatchError> {
+        match self.thread_limit {
+            Some(limit) if set.len() > limit => Err(MatchError::TooManyThreads { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// スレッド集合の「文字を読む遷移」がすべて同じバイト集合なら、その集合
+    fn uniform_byte_set(&self, set: &[Thread]) -> Option<ByteSet> {
+        let mut found: Option<ByteSet> = None;
+        for thr in set {
+            for (lbl, _) in &self.states[thr.s].edges {
+                if let Some(bs) = lbl.byte_set() {
+                    match found {
+                        Some(f) if f != bs => return None,
+                        _ => found = Some(bs),
+                    }
+                }
+            }
+        }
+        found
     }
 
-    /// ε・CapBegin・CapEnd を辿って集合を閉じる。
-    /// `pos` は「いまの入力位置」（Cap記録に使う）。
-    fn eps_closure(&self, set: &mut Vec<Thread>, pos: usize) {
+    /// ε・CapBegin・CapEnd・Look を辿って集合を閉じる。
+    /// `pos` は「いまの入力位置」（Cap記録と Look の判定に使う）。
+    fn eps_closure(&self, set: &mut Vec<Thread>, bytes: &[u8], pos: usize) {
         use std::collections::VecDeque;
         let mut q: VecDeque<Thread> = set.clone().into();
         set.clear();
+        let mut looks = LookCache::default();
 
         // 訪問管理は (state, caps の指紋) で重複を抑える
         // ここでは簡便のため、(state, caps 全体) をそのまま比較して dedup。
@@ -225,6 +934,12 @@ impl Regex {
                         }
                         q.push_back(Thread { s: *tgt, caps: c });
                     }
+                    Label::Look(look) if looks.matches(*look, bytes, pos) => {
+                        q.push_back(Thread {
+                            s: *tgt,
+                            caps: thr.caps.clone(),
+                        });
+                    }
                     _ => {} // 文字を読む遷移はここでは進まない
                 }
             }
@@ -235,6 +950,60 @@ impl Regex {
     }
 }
 
+// 開き括弧の順（= グループ番号順）に、対応する括弧で囲まれた範囲を返す。
+// 括弧の対応は字句解析後にまだ検査されていないが、呼び出し元で構文解析が通ってから使う。
+fn paren_spans(tokens: &[Lexeme]) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut open: Vec<Option<usize>> = Vec::new(); // spans の添字（キャプチャしないグループは None）
+    for t in tokens {
+        match t.token {
+            Token::LParen => {
+                open.push(Some(spans.len()));
+                spans.push(t.span);
+            }
+            Token::NonCapturing => open.push(None),
+            Token::RParen => {
+                if let Some(Some(k)) = open.pop() {
+                    spans[k].end = t.span.end;
+                }
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+// 括弧の外にある `|` の位置
+fn top_level_bars(tokens: &[Lexeme]) -> Vec<usize> {
+    let mut depth = 0usize;
+    let mut bars = Vec::new();
+    for t in tokens {
+        match t.token {
+            Token::LParen | Token::NonCapturing => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            Token::Alt if depth == 0 => bars.push(t.span.start),
+            _ => {}
+        }
+    }
+    bars
+}
+
+// 同じ状態にいるスレッドのうち、開始位置が最も左のものだけ残す。
+// `dedup_threads` の後なら、同じ状態の中は caps（先頭がスロット 0 の開始位置）の順に並んでいる
+fn keep_leftmost_starts(v: &mut Vec<Thread>) {
+    let mut prev: Option<(usize, Option<usize>)> = None;
+    v.retain(|t| {
+        let start = thread_start(&t.caps);
+        match prev {
+            Some((s, leftmost)) if s == t.s => start == leftmost,
+            _ => {
+                prev = Some((t.s, start));
+                true
+            }
+        }
+    });
+}
+
 // 重複除去（素朴版）：(state, caps) が同一なら1つにまとめる
 fn dedup_threads(mut v: Vec<Thread>) -> Vec<Thread> {
     v.sort_by(|a, b| a.s.cmp(&b.s).then_with(|| a.caps.cmp(&b.caps)));
@@ -326,8 +1095,16 @@ mod tests {
     // 追加テスト用ヘルパ：captures を取り出す
     fn mc(p: &str, s: &str) -> Option<Vec<Option<String>>> {
         let re = Regex::new(p).expect("Regex::new failed");
-        re.captures(s)
-            .map(|v| v.into_iter().map(|o| o.map(|z| z.to_string())).collect())
+        re.captures(s).map(|c| {
+            c.iter()
+                .map(|o| o.map(|m| m.as_str().to_string()))
+                .collect()
+        })
+    }
+
+    // 各グループの文字列（参加しなかったものは None）
+    fn groups<'h>(caps: &Captures<'h>) -> Vec<Option<&'h str>> {
+        caps.iter().map(|o| o.map(|m| m.as_str())).collect()
     }
 
     // ========= ここから追加テスト =========
@@ -367,11 +1144,22 @@ mod tests {
     }
 
     #[test]
-    fn dot_matches_any_including_newline() {
-        // 仕様：Any は改行も含む
-        assert!(m(r"a.b", "a\nb"));
+    fn dot_excludes_newline_unless_dot_all() {
+        // 仕様：既定の . は \n 以外、(?s) / dot_matches_new_line なら改行も含む
+        assert!(!m(r"a.b", "a\nb"));
+        assert!(m(r"a.b", "a\rb"));
         assert!(m(r".", "x"));
         assert!(!m(r".", ""));
+        assert!(m(r"(?s)a.b", "a\nb"));
+        assert!(m(r"(?is)A.B", "a\nb"));
+        let re = RegexBuilder::new("a.*")
+            .dot_matches_new_line(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("a\nb\n"));
+        // 探索では行をまたがない
+        let re = Regex::new("a.*").unwrap();
+        assert_eq!(re.find("xab\ncd").unwrap().as_str(), "ab");
     }
 
     #[test]
@@ -476,8 +1264,670 @@ mod tests {
         assert_eq!(got[2], Some("123-XYZ".into()));
     }
 
+    // ==== 照合の振る舞い・ビルダー・検索 API ====
+
+    #[test]
+    fn early_abort_keeps_results() {
+        // 残り長が足りない入力でも判定は変わらない
+        assert!(!m(r"(a|b)*cccc", "abababccc"));
+        assert!(m(r"(a|b)*cccc", "abababcccc"));
+        assert!(!m(r".*xyz", &"a".repeat(10_000)));
+        let got = mc(r"(\w+)-(\d\d)", "abc-12").unwrap();
+        assert_eq!(got[2], Some("12".into()));
+    }
+
+    #[test]
+    fn builder_without_captures() {
+        let re = RegexBuilder::new(r"(a)(b(c))?")
+            .captures(false)
+            .build()
+            .unwrap();
+        assert!(re.is_match("abc"));
+        assert!(re.is_match("a"));
+        assert!(!re.is_match("ab"));
+        // 全体一致だけが返る
+        assert_eq!(groups(&re.captures("abc").unwrap()), vec![Some("abc")]);
+        // キャプチャ遷移は1本も残らず、その分の状態も作らない
+        assert!(!format!("{re:?}").contains("S1"));
+        let with_caps = Regex::new(r"(a)(b(c))?").unwrap();
+        assert_eq!(re.report().states + 6, with_caps.report().states);
+        let empty = RegexBuilder::new("a()*b").captures(false).build().unwrap();
+        assert!(empty.is_match("ab") && !empty.is_match("a"));
+    }
+
+    #[test]
+    fn builder_literal_mode() {
+        let re = RegexBuilder::new("a.b*(c)").literal(true).build().unwrap();
+        assert!(re.is_match("a.b*(c)"));
+        assert!(!re.is_match("axbbc"));
+        assert!(!re.is_match("a.b*(c)d"));
+        assert_eq!(
+            groups(&re.captures("a.b*(c)").unwrap()),
+            vec![Some("a.b*(c)")]
+        );
+
+        // 通常モードでは構文エラーになるパターンも通る
+        assert!(Regex::new("(ab").is_err());
+        let re = RegexBuilder::new("(ab").literal(true).build().unwrap();
+        assert!(re.is_match("(ab"));
+        assert!(
+            RegexBuilder::new(r"\")
+                .literal(true)
+                .build()
+                .unwrap()
+                .is_match(r"\")
+        );
+    }
+
+    #[test]
+    fn builder_whole_word() {
+        let re = RegexBuilder::new(r".*foo.*")
+            .whole_word(true)
+            .build()
+            .unwrap();
+        // 先頭と末尾が単語境界であること
+        assert!(re.is_match("foo"));
+        assert!(re.is_match("foo bar"));
+        assert!(!re.is_match(" foo"));
+        assert!(!re.is_match("foo "));
+
+        let re = RegexBuilder::new(r"\w+").whole_word(true).build().unwrap();
+        assert!(re.is_match("word_1"));
+
+        // 非単語文字だけのパターンは前後に単語文字が無いので境界が立たない
+        let re = RegexBuilder::new("-").whole_word(true).build().unwrap();
+        assert!(!re.is_match("-"));
+
+        // literal との併用でも境界を見る
+        let re = RegexBuilder::new("a b")
+            .literal(true)
+            .whole_word(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("a b"));
+        let re = RegexBuilder::new("a ")
+            .literal(true)
+            .whole_word(true)
+            .build()
+            .unwrap();
+        assert!(!re.is_match("a "));
+    }
+
+    #[test]
+    fn matches_many_in_order() {
+        let re = Regex::new(r"\d+").unwrap();
+        let got = re.matches_many(["12", "x", "", "007", "1a"].into_iter());
+        assert_eq!(got, vec![true, false, false, true, false]);
+        assert!(re.matches_many(std::iter::empty()).is_empty());
+
+        // 作業領域を使い回しても、1つずつ is_match した結果と変わらない
+        let hays = ["aab", "b", "", "ab", "aaaaaaab", "ba", "a"];
+        for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+            for pat in [r"a*b", r"(a|ab)(b?)", r"a+"] {
+                let re = RegexBuilder::new(pat).match_kind(kind).build().unwrap();
+                let want: Vec<bool> = hays.iter().map(|h| re.is_match(h)).collect();
+                assert_eq!(re.matches_many(hays.into_iter()), want, "{pat} {kind:?}");
+            }
+        }
+
+        let re = RegexBuilder::new(r"(a|a)*")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            re.try_matches_many(["", "aaaa"].into_iter()),
+            Err(MatchError::TooManyThreads { limit: 1 })
+        );
+    }
+
+    #[test]
+    fn find_leftmost_anywhere() {
+        let re = Regex::new(r"[0-9]+").unwrap();
+        let m = re.find("id=42, n=7").unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (3, 5, "42"));
+        assert!(re.find("none").is_none());
+        // is_match と違い、全体一致は要らない
+        assert!(!re.is_match("x1"));
+        assert_eq!(re.find("x1").unwrap().span(), Span::new(1, 2));
+
+        // 左端が優先。その中では最長
+        let re = Regex::new(r"b|abc").unwrap();
+        assert_eq!(re.find("xabc").unwrap().as_str(), "abc");
+
+        // 空一致は先頭で見つかる
+        let re = Regex::new(r"z*").unwrap();
+        assert_eq!(re.find("abc").unwrap().span(), Span::new(0, 0));
+        assert_eq!(re.find("é").unwrap().span(), Span::new(0, 0));
+    }
+
+    #[test]
+    fn match_offsets_index_the_haystack() {
+        let hay = "név: 42";
+        let m = Regex::new("[0-9]+").unwrap().find(hay).unwrap();
+        assert_eq!(m.range(), 6..8);
+        assert_eq!(&hay[m.range()], m.as_str());
+        assert_eq!(m.span().range(), m.range());
+    }
+
+    #[test]
+    fn try_find_reports_limits() {
+        let re = RegexBuilder::new("a").max_haystack_len(2).build().unwrap();
+        assert_eq!(
+            re.try_find("xxxa"),
+            Err(MatchError::HaystackTooLong { len: 4, limit: 2 })
+        );
+        assert_eq!(re.try_find("xa").unwrap().unwrap().start(), 1);
+    }
+
+    #[test]
+    fn match_prefix_longest_from_start() {
+        let re = Regex::new(r"\d+").unwrap();
+        let m = re.match_prefix("123abc").unwrap();
+        assert_eq!((m.start(), m.end()), (0, 3));
+        assert_eq!(m.as_str(), "123");
+        assert!(re.match_prefix("abc123").is_none());
+
+        // 最長を選ぶ
+        let re = Regex::new(r"if|ifelse|i").unwrap();
+        assert_eq!(re.match_prefix("ifelse(x)").unwrap().as_str(), "ifelse");
+
+        // 空一致も一致として返す
+        let re = Regex::new(r"a*").unwrap();
+        assert_eq!(re.match_prefix("bbb").unwrap().end(), 0);
+    }
+
+    #[test]
+    fn is_match_without_capture_tracking_agrees() {
+        // is_match は記録なし、captures は記録ありで走る。結果は一致すること
+        for (p, s) in [
+            (r"(a|ab)(c|bcd)(d*)", "abcd"),
+            (r"(\w+)\s+(.+)", "abc   123-XYZ"),
+            (r"((a*)*b)+", "aabab"),
+            (r"(ab)?c", "c"),
+            (r"(ab)?c", "abcc"),
+        ] {
+            let re = Regex::new(p).unwrap();
+            assert_eq!(re.is_match(s), re.captures(s).is_some(), "{p} / {s}");
+        }
+    }
+
+    #[test]
+    fn builder_equivalent_bytes() {
+        let re = RegexBuilder::new("ab[c-d]")
+            .equivalent_bytes(b"aA")
+            .equivalent_bytes(b"dD")
+            .build()
+            .unwrap();
+        assert!(re.is_match("abc"));
+        assert!(re.is_match("AbD"));
+        assert!(!re.is_match("aBc"));
+
+        // 否定クラスは同一視で閉じてから否定する
+        let re = RegexBuilder::new("[^i]")
+            .equivalent_bytes(b"iI")
+            .build()
+            .unwrap();
+        assert!(!re.is_match("i"));
+        assert!(!re.is_match("I"));
+        assert!(re.is_match("x"));
+
+        // literal モードにも効く
+        let re = RegexBuilder::new("a.")
+            .literal(true)
+            .equivalent_bytes(b".,")
+            .build()
+            .unwrap();
+        assert!(re.is_match("a,"));
+        assert!(!re.is_match("ax"));
+    }
+
+    #[test]
+    fn builder_case_insensitive() {
+        let re = RegexBuilder::new("Error: [a-c]+")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("ERROR: AbC"));
+        assert!(re.is_match("error: b"));
+        assert!(!re.is_match("error; b"));
+        // 先頭の (?i) と同じ正規表現になる
+        let inline = Regex::new("(?i)Error: [a-c]+").unwrap();
+        assert_eq!(re.pattern_hash(), inline.pattern_hash());
+        // (?-i:...) でグループの中だけ戻せる
+        let re = RegexBuilder::new("x(?-i:y)z")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("XyZ") && !re.is_match("xYz"));
+    }
+
+    #[test]
+    fn case_insensitive_literals() {
+        // 固定文字列でも同じ結果（NFA を通らない）
+        let re = Regex::new("(?i)GeT").unwrap();
+        assert!(re.is_match("get") && re.is_match("GET"));
+        assert!(!re.is_match("ge"));
+        assert_eq!(re.find("x gEt y").unwrap().span(), Span::new(2, 5));
+        let re = Regex::new("(?i)é").unwrap();
+        assert!(re.is_match("é"));
+        assert!(!re.is_match("É"));
+    }
+
+    #[test]
+    fn inline_case_insensitive_flag() {
+        let re = Regex::new("(?i)Error: [a-c]+").unwrap();
+        assert!(re.is_match("ERROR: AbC"));
+        assert!(!re.is_match("error; b"));
+        // 位置はフラグを含めたパターン中の位置のまま
+        assert_eq!(Regex::new("(?i)a(").unwrap_err().pos, 5);
+        assert_eq!(re.group_info().len(), 1);
+        let re = Regex::new("(?i)(?<w>x+)").unwrap();
+        assert_eq!(re.captures("xX").unwrap().name("w").unwrap().as_str(), "xX");
+        assert_eq!(re.group_info().pattern_span(1), Some(Span::new(4, 12)));
+        // literal ではただの文字
+        let lit = RegexBuilder::new("(?i)a").literal(true).build().unwrap();
+        assert!(lit.is_match("(?i)a") && !lit.is_match("A"));
+    }
+
+    #[test]
+    fn scoped_flag_groups() {
+        let re = Regex::new("a(?i:b(c))d").unwrap();
+        assert!(re.is_match("aBCd") && !re.is_match("ABcd") && !re.is_match("abcD"));
+        // キャプチャしないグループは番号を取らない
+        assert_eq!(re.group_info().len(), 2);
+        assert_eq!(&re.captures("abCd").unwrap()[1], "C");
+        assert_eq!(re.group_info().pattern_span(1), Some(Span::new(6, 9)));
+        // (?-i:...) でグループの中だけ戻す
+        assert!(Regex::new("(?i)a(?-i:b)").unwrap().is_match("Ab"));
+        // s と m もグループの中だけ
+        let re = Regex::new("(?s:a.)b.").unwrap();
+        assert!(re.is_match("a\nbx") && !re.is_match("a\nb\n"));
+        let re = Regex::new("(?m:a$\n^)b").unwrap();
+        assert!(re.is_match("a\nb"));
+        assert_eq!(Regex::new("(?m:a$)b|c$").unwrap().find("c\n"), None);
+        // (?:) は空に一致し、量指定子も付けられる
+        let re = Regex::new("a(?:)*(?:b|c)+").unwrap();
+        assert!(re.is_match("abcb") && !re.is_match("a"));
+    }
+
+    #[test]
+    fn line_anchors() {
+        let starts =
+            |re: &Regex, hay: &str| re.find_iter(hay).map(|m| m.start()).collect::<Vec<_>>();
+        let hay = "ab\nab\n";
+        // 既定では入力の先頭・末尾だけ
+        assert_eq!(starts(&Regex::new("^ab").unwrap(), hay), vec![0]);
+        assert_eq!(starts(&Regex::new("ab$").unwrap(), "ab\nab"), vec![3]);
+        assert_eq!(
+            starts(&Regex::new("ab$").unwrap(), hay),
+            Vec::<usize>::new()
+        );
+        // (?m) とビルダーは同じ。\A / \z は複数行でも入力の先頭・末尾のまま
+        let multi = Regex::new("(?m)^ab$").unwrap();
+        assert_eq!(starts(&multi, hay), vec![0, 3]);
+        let built = RegexBuilder::new("^ab$").multi_line(true).build().unwrap();
+        assert_eq!(starts(&built, hay), vec![0, 3]);
+        assert_eq!(starts(&Regex::new(r"(?m)\Aab").unwrap(), hay), vec![0]);
+        // 空行にも一致する
+        assert_eq!(starts(&Regex::new("(?m)^$").unwrap(), "a\n\nb"), vec![2]);
+        // フラグは重ねられる
+        assert!(Regex::new("(?mi)^AB$").unwrap().find("x\nab").is_some());
+        // 完全一致ではもともと全体が固定されている
+        assert!(Regex::new("^a+$").unwrap().is_match("aa"));
+    }
+
+    #[test]
+    fn try_new_many_reports_every_failure() {
+        let res = Regex::try_new_many([r"\d+", "a|b", "[a-z]+"]).unwrap();
+        assert_eq!(res.len(), 3);
+        assert!(res[2].is_match("xyz"));
+
+        let errs = Regex::try_new_many(vec!["ok", "(a", "fine", "*x"]).unwrap_err();
+        let idx: Vec<usize> = errs.iter().map(|(i, _)| *i).collect();
+        assert_eq!(idx, vec![1, 3]);
+        assert_eq!(errs[0].1.kind, ErrorKind::UnbalancedParen);
+        assert_eq!(errs[1].1.kind, ErrorKind::DanglingQuantifier);
+    }
+
+    #[test]
+    fn bulk_skip_tails_give_same_results() {
+        let long = format!("key={}", "v".repeat(100_000));
+        assert!(m(r"key=.*", &long));
+        assert!(m(r"key=[^;]*", &long));
+        assert!(!m(r"key=[^;]*", &format!("{long};more")));
+        assert!(!m(r"key=[^v]*", &long));
+        assert_eq!(
+            Regex::new(r"(\w+)=(.*)")
+                .unwrap()
+                .match_prefix(&long)
+                .unwrap()
+                .end(),
+            long.len()
+        );
+        // グループ末尾の位置が毎回変わるキャプチャ付きは読み飛ばせないが、結果は同じ
+        let got = mc(r"(\w+)=([^,]*)", &long[..1_000]).unwrap();
+        assert_eq!(got[2].as_deref().map(str::len), Some(996));
+
+        // 途中で止まるバイトがあれば最長一致はそこまで
+        let re = Regex::new(r"a[^;]*").unwrap();
+        assert_eq!(re.match_prefix("abc;def").unwrap().as_str(), "abc");
+    }
+
+    #[test]
+    fn thread_limit_reports_error() {
+        // キャプチャ付きだと (状態, caps) ごとにスレッドが分かれて増える
+        let re = RegexBuilder::new(r"(a*)*(a*)*b")
+            .thread_limit(8)
+            .build()
+            .unwrap();
+        assert_eq!(
+            re.try_captures("aaaaaaaab"),
+            Err(MatchError::TooManyThreads { limit: 8 })
+        );
+
+        // 上限に収まる入力はそのまま
+        let re = RegexBuilder::new(r"ab|cd").thread_limit(8).build().unwrap();
+        assert_eq!(re.try_is_match("cd"), Ok(true));
+        assert_eq!(
+            groups(&re.try_captures("ab").unwrap().unwrap()),
+            vec![Some("ab")]
+        );
+        assert_eq!(re.try_is_match("ax"), Ok(false));
+    }
+
+    #[test]
+    #[should_panic(expected = "TooManyThreads")]
+    fn thread_limit_panics_in_infallible_api() {
+        let re = RegexBuilder::new(r"(a|a)*")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        re.captures("aaaa");
+    }
+
+    #[test]
+    fn max_haystack_len_rejects_long_input() {
+        let re = RegexBuilder::new("a*").max_haystack_len(4).build().unwrap();
+        assert_eq!(re.try_is_match("aaaa"), Ok(true));
+        let too_long = MatchError::HaystackTooLong { len: 5, limit: 4 };
+        assert_eq!(re.try_is_match("aaaaa"), Err(too_long));
+        assert_eq!(re.try_captures("aaaaa"), Err(too_long));
+        // 範囲を絞っても入力全体の長さで判定する
+        let opts = crate::SearchOptions {
+            span: Some(Span::new(0, 1)),
+            ..Default::default()
+        };
+        assert_eq!(re.try_search("aaaaa", &opts), Err(too_long));
+    }
+
+    #[test]
+    #[should_panic(expected = "HaystackTooLong")]
+    fn max_haystack_len_panics_in_infallible_api() {
+        let re = RegexBuilder::new("a*").max_haystack_len(0).build().unwrap();
+        re.match_prefix("a");
+    }
+
+    #[test]
+    fn user_defined_classes() {
+        let greek = ClassSet::from_ranges([(0x3B1, 0x3C9)]); // α..ω
+        let re = RegexBuilder::new(r"\p{greek}+-\P{greek}")
+            .define_class("greek", greek.clone())
+            .build()
+            .unwrap();
+        assert!(re.is_match("αβγ-x"));
+        assert!(re.is_match("ω-日"));
+        assert!(!re.is_match("αβγ-δ"));
+        assert!(!re.is_match("abc-x"));
+        // 1文字は複数バイトでも1回の繰り返しに数える
+        let re = RegexBuilder::new(r"\p{g}{2}")
+            .define_class("g", greek)
+            .build()
+            .unwrap();
+        assert!(re.is_match("αβ"));
+        assert!(!re.is_match("αβγ"));
+
+        let e = Regex::new(r"ab\p{nope}").unwrap_err();
+        assert_eq!(
+            (e.kind, e.pos),
+            (ErrorKind::UnsupportedFeature(Feature::UnicodeClass), 2)
+        );
+    }
+
+    #[cfg(feature = "boundary-audit")]
+    #[test]
+    fn boundary_audit_reports_misaligned_spans() {
+        // `.` は1バイトなので、グループが「é」の途中で切れる
+        let re = Regex::new("(.)(.)").unwrap();
+        assert_eq!(
+            re.try_captures("é"),
+            Err(MatchError::MisalignedSpan {
+                span: Span::new(0, 1)
+            })
+        );
+        let opts = crate::SearchOptions::default();
+        assert_eq!(
+            Regex::new(".").unwrap().try_search("é", &opts),
+            Err(MatchError::MisalignedSpan {
+                span: Span::new(0, 1)
+            })
+        );
+        // 文字単位で正しく切れるなら通る
+        assert!(Regex::new("(.)(.)").unwrap().try_captures("ab").is_ok());
+    }
+
+    #[test]
+    fn counted_repetition() {
+        assert!(m("a{3}", "aaa"));
+        assert!(!m("a{3}", "aa"));
+        assert!(!m("a{3}", "aaaa"));
+        assert!(m("a{2,}", "aa"));
+        assert!(m("a{2,}", "aaaaaaa"));
+        assert!(!m("a{2,}", "a"));
+        assert!(m("(ab){1,3}c", "ababc"));
+        assert!(!m("(ab){1,3}c", "abababababc"));
+        assert!(!m("(ab){1,3}c", "c"));
+        assert!(m("x{0,2}y", "y"));
+        assert!(m("x{0,}y", "xxxxy"));
+        assert!(m("x{0}y", "y"));
+        assert!(!m("x{0}y", "xy"));
+        assert!(m(r"\d{4}-\d{2}", "2024-10"));
+        assert!(m("[a-c]{2}{", "ab{"));
+
+        // 複製されたグループは同じ番号に書かれ、最後の回が残る
+        assert_eq!(
+            mc("(a|b){3}", "abb"),
+            Some(vec![Some("abb".into()), Some("b".into())])
+        );
+        assert_eq!(mc("(a){0,2}b", "b"), Some(vec![Some("b".into()), None]));
+    }
+
+    #[test]
+    fn repetition_limit() {
+        let e = Regex::new("xa{1,99999999}").unwrap_err();
+        assert_eq!(
+            (e.kind, e.pos),
+            (ErrorKind::RepetitionTooLarge { limit: 1000 }, 2)
+        );
+        assert!(Regex::new("a{1000}").is_ok());
+        assert!(Regex::new("a{1001,}").is_err());
+
+        let re = RegexBuilder::new("a{5}").repetition_limit(5).build();
+        assert!(re.is_ok());
+        let e = RegexBuilder::new("a{2,6}")
+            .repetition_limit(5)
+            .build()
+            .unwrap_err();
+        assert_eq!(e.kind, ErrorKind::RepetitionTooLarge { limit: 5 });
+
+        // 入れ子は回数の積で見る（外側の {200} の位置を指す）
+        let e = Regex::new("(a{1000}){200}").unwrap_err();
+        assert_eq!(
+            (e.kind, e.pos),
+            (ErrorKind::RepetitionTooLarge { limit: 1000 }, 9)
+        );
+        assert!(Regex::new("((a{1000}){1000}){1000}").is_err());
+        assert!(Regex::new("(?:a{10}b){100}").is_ok());
+        assert!(Regex::new("(?:a{10}b){101}").is_err());
+        // 並んだ繰り返しは掛け合わせない
+        assert!(Regex::new("a{1000}b{1000}(c|d{1000})*").is_ok());
+        let e = RegexBuilder::new("(x(ab){3,}){2}")
+            .repetition_limit(5)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            (e.kind, e.pos),
+            (ErrorKind::RepetitionTooLarge { limit: 5 }, 11)
+        );
+    }
+
+    #[test]
+    fn shortest_fail_position_cases() {
+        let re = Regex::new(r"[a-z]+(,[a-z]+)*").unwrap();
+        assert_eq!(re.shortest_fail_position("ab,cd"), None);
+        assert_eq!(re.shortest_fail_position("ab,,cd"), Some(3));
+        assert_eq!(re.shortest_fail_position("ab,"), Some(3));
+        assert_eq!(re.shortest_fail_position(""), Some(0));
+        assert_eq!(re.shortest_fail_position("1"), Some(0));
+
+        // 固定文字列の戦略でも NFA と同じ位置を返す
+        let lit = RegexBuilder::new("a.c").literal(true).build().unwrap();
+        assert_eq!(lit.shortest_fail_position("a.x"), Some(2));
+        assert_eq!(lit.shortest_fail_position("a.cd"), Some(3));
+
+        // 単語境界は読んだ後の位置で判定される
+        let ww = RegexBuilder::new("cat").whole_word(true).build().unwrap();
+        assert_eq!(ww.shortest_fail_position("cat"), None);
+        assert_eq!(ww.shortest_fail_position("cats"), Some(3));
+    }
+
+    fn kind(p: &str, k: MatchKind) -> Regex {
+        RegexBuilder::new(p).match_kind(k).build().unwrap()
+    }
+
+    #[test]
+    fn match_kind_prefix_selection() {
+        let hay = "ifelse";
+        let first = kind("i|if|ifelse", MatchKind::LeftmostFirst);
+        assert_eq!(first.match_prefix(hay).unwrap().as_str(), "i");
+        let longest = kind("i|if|ifelse", MatchKind::LeftmostLongest);
+        assert_eq!(longest.match_prefix(hay).unwrap().as_str(), "ifelse");
+
+        // 貪欲な量指定子は LeftmostFirst でも最長側を優先する
+        let first = kind("a*", MatchKind::LeftmostFirst);
+        assert_eq!(first.match_prefix("aaab").unwrap().as_str(), "aaa");
+    }
+
+    #[test]
+    fn match_kind_full_match_captures() {
+        // 完全一致は MatchKind によらず成否が同じ。キャプチャの割り振りだけが変わる
+        let p = r"(a|ab)(c|bcd)(d*)";
+        let first = kind(p, MatchKind::LeftmostFirst);
+        let got = groups(&first.captures("abcd").unwrap());
+        assert_eq!(got, vec![Some("abcd"), Some("a"), Some("bcd"), Some("")]);
+
+        for k in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+            let re = kind(p, k);
+            assert!(re.is_match("abcd"));
+            assert!(re.is_match("abcdddd"));
+            assert!(!re.is_match("abx"));
+        }
+
+        // 完全一致のためには優先度の低い枝も生かしておく必要がある
+        let first = kind("(a|ab)", MatchKind::LeftmostFirst);
+        assert_eq!(&first.captures("ab").unwrap()[1], "ab");
+    }
+
+    #[test]
+    fn leftmost_first_keeps_existing_capture_results() {
+        let re = kind(r"(\w+)\s+(.+)", MatchKind::LeftmostFirst);
+        let got = re.captures("abc   123-XYZ").unwrap();
+        assert_eq!(&got[1], "abc");
+        assert_eq!(&got[2], "123-XYZ");
+
+        let re = kind(r"(ab)+", MatchKind::LeftmostFirst);
+        assert_eq!(&re.captures("abab").unwrap()[1], "ab");
+
+        let re = kind(r"(ab)?c", MatchKind::LeftmostFirst);
+        assert_eq!(re.captures("c").unwrap().get(1), None);
+    }
+
+    #[test]
+    fn group_spans_in_pattern() {
+        let pat = r"((a|b)c)*-(x)()";
+        let re = Regex::new(pat).unwrap();
+        let got: Vec<&str> = re
+            .capture_group_spans_in_pattern()
+            .iter()
+            .map(|s| s.slice(pat))
+            .collect();
+        assert_eq!(got, vec![pat, "((a|b)c)", "(a|b)", "(x)", "()"]);
+        assert_eq!(got.len(), re.captures("acbc-x").unwrap().len());
+
+        // 固定文字列モードの括弧はグループではない
+        let lit = RegexBuilder::new("(a)").literal(true).build().unwrap();
+        assert_eq!(lit.capture_group_spans_in_pattern(), &[Span::new(0, 3)]);
+    }
+
+    #[test]
+    fn debug_lists_program() {
+        let re = Regex::new(r"(a|\d)").unwrap();
+        let out = format!("{re:?}");
+        assert!(out.starts_with("Regex { states: "), "{out}");
+        assert!(out.contains("groups: 1"), "{out}");
+        assert!(out.contains("start"), "{out}");
+        assert!(out.contains("accept"), "{out}");
+        assert!(out.contains("'a' -> "), "{out}");
+        assert!(out.contains("[0-9] -> "), "{out}");
+        assert!(out.contains("S1 -> ") && out.contains("E1 -> "), "{out}");
+        // 1状態1行（+ 見出し行）
+        assert_eq!(out.lines().count(), re.states.len() + 1);
+    }
+
     // ==== エラー系（構文エラー） ====
 
+    #[test]
+    fn error_positions_point_into_pattern() {
+        // 字句解析・構文解析・NFA 構築のどの段で出たエラーもパターン中の位置を指す
+        let e = Regex::new(r"\d\d\w(ab").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::UnbalancedParen, 6));
+
+        let e = Regex::new(r"\d\d)").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::UnbalancedParen, 4));
+
+        let e = Regex::new(r"[a-z]+*").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::DanglingQuantifier, 6));
+
+        // "a|" は NFA 構築時に右辺が無いと分かる。位置は '|'
+        let e = Regex::new(r"\w\w|").unwrap_err();
+        assert_eq!(e.pos, 4);
+        // 左辺の無い '|' はグループの組み立て時に発覚する。位置は '('
+        let e = Regex::new(r"x(|y)").unwrap_err();
+        assert_eq!(e.pos, 1);
+    }
+
+    #[test]
+    fn supports_agrees_with_parser() {
+        let probes = [
+            (Feature::CaptureGroup, "(a)"),
+            (Feature::NonCapturingGroup, "(?:a)"),
+            (Feature::NamedGroup, "(?P<x>a)"),
+            (Feature::InlineFlags, "(?i)a"),
+            (Feature::Lookaround, "a(?=b)"),
+            (Feature::Backreference, r"(a)\1"),
+            (Feature::CountedRepetition, "a{2}"),
+            (Feature::WordBoundary, r"\ba"),
+            (Feature::TextAnchor, r"\Aa"),
+            (Feature::UnicodeClass, r"\p{L}"),
+        ];
+        for (f, pat) in probes {
+            let res = Regex::new(pat);
+            assert_eq!(Regex::supports(f), res.is_ok(), "{}", f.name());
+            if let Err(e) = res {
+                assert_eq!(e.kind, ErrorKind::UnsupportedFeature(f));
+            }
+        }
+    }
+
     #[test]
     fn error_unbalanced_paren() {
         let e = Regex::new("(ab");
@@ -518,7 +1968,7 @@ mod nfa_capture_cut_tests {
 
     fn make_nfa(pat: &str) -> Nfa {
         let p = make_postfix(pat);
-        build_nfa(&p).unwrap()
+        build_nfa(&p, &[]).unwrap()
     }
 
     /// 後置記法を記号列にして比較しやすくする
@@ -529,15 +1979,19 @@ mod nfa_capture_cut_tests {
             .map(|t| match t {
                 Char(_) => "c",
                 Dot => ".",
-                Class { .. } => "[",
+                Class { .. } | NamedClass { .. } | CodepointClass(_) => "[",
                 Star => "*",
                 Plus => "+",
                 Qmark => "?",
+                Repeat { .. } => "{",
                 Concat => "·",
                 Alt => "|",
                 CapStart(_) => "S",
                 CapEnd(_) => "E",
-                LParen | RParen => unreachable!("Paren should not appear in postfix"),
+                Look(_) => "^",
+                LParen | NonCapturing | RParen => {
+                    unreachable!("Paren should not appear in postfix")
+                }
             })
             .collect::<Vec<_>>()
             .join(" ")
@@ -574,17 +2028,15 @@ mod nfa_capture_cut_tests {
                 match lbl {
                     Label::CapBegin(_) => cb += 1,
                     Label::CapEnd(_) => ce += 1,
-                    Label::Class { ranges, neg } => {
-                        // \w: [0-9A-Za-z_]
-                        if !*neg
-                            && ranges.iter().any(|&(a, b)| a == b'0' && b == b'9')
-                            && ranges.iter().any(|&(a, b)| a == b'A' && b == b'Z')
-                            && ranges.iter().any(|&(a, b)| a == b'a' && b == b'z')
+                    Label::Class(set) => {
+                        // \w: [0-9A-Za-z_]（ビットマップ上は 0-9, A-Z, _, a-z の4区間）
+                        if set.ranges()
+                            == vec![(b'0', b'9'), (b'A', b'Z'), (b'_', b'_'), (b'a', b'z')]
                         {
                             has_w = true;
                         }
-                        // \s: space を含む
-                        if !*neg && ranges.iter().any(|&(a, b)| a == b' ' && b == b' ') {
+                        // \s: space を含み、英字は含まない
+                        if set.contains(b' ') && !set.contains(b'a') {
                             has_s = true;
                         }
                     }
@@ -638,11 +2090,11 @@ mod nfa_capture_cut_tests {
                 }
                 for (lbl, v) in &nfa.states[u].edges {
                     match lbl {
-                        Label::Eps | Label::CapBegin(_) | Label::CapEnd(_) => {
-                            if !seen[*v] {
-                                seen[*v] = true;
-                                q.push_back(*v);
-                            }
+                        Label::Eps | Label::CapBegin(_) | Label::CapEnd(_) | Label::Look(_)
+                            if !seen[*v] =>
+                        {
+                            seen[*v] = true;
+                            q.push_back(*v);
                         }
                         _ => {} // 文字を消費するラベルは辿らない
                     }
@@ -666,7 +2118,7 @@ mod nfa_capture_cut_tests {
 
         let re = Regex::new(r"(\w+)\s+(.+)").unwrap();
         let caps = re.captures("abc   123-XYZ").expect("should match fully");
-        assert_eq!(caps[1], Some("abc"));
-        assert_eq!(caps[2], Some("123-XYZ"));
+        assert_eq!(&caps[1], "abc");
+        assert_eq!(&caps[2], "123-XYZ");
     }
 }
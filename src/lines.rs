@@ -0,0 +1,189 @@
+// lines.rs
+//! `io::BufRead` を行ごとに読んで照合するイテレータ（grep の `-n` / `-b` 相当）。
+//!
+//! 一致の位置は読んだ行の先頭からではなく入力全体での位置で返すので、
+//! 呼び出し側が各行で何バイト読んだかを数えなくてよい。
+use std::io::{self, BufRead};
+use std::iter::FusedIterator;
+
+use crate::search::Cursor;
+use crate::{MatchError, Regex, Span};
+
+/// `Regex::match_lines` が返す一致1つ分。行は読み捨てるので文字列は持ち主ごと返す。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineMatch {
+    /// 一致があった行の番号（1 始まり）
+    pub line_no: usize,
+    /// その行の先頭の、入力全体でのバイト位置
+    pub line_offset: usize,
+    /// 入力全体での一致の範囲
+    pub span: Span,
+    /// 一致した文字列
+    pub text: String,
+}
+
+/// `Regex::match_lines` が返すイテレータ。行の順、行の中では左から順に並ぶ。
+///
+/// 読み込みの失敗・UTF-8 でない行（`io::ErrorKind::InvalidData`）・
+/// スレッド数が `thread_limit` を超えた場合（`io::ErrorKind::Other`）は `Err` を 1 つ返して終わる。
+pub struct LineMatches<'r, R> {
+    re: &'r Regex,
+    reader: R,
+    line: String,
+    // 改行を除いた行の長さ
+    content: usize,
+    line_no: usize,
+    // 今の行の先頭の位置と、次の行の先頭の位置
+    line_offset: usize,
+    next_offset: usize,
+    // 今の行をどこまで探したか。None なら次の行を読む
+    cursor: Option<Cursor>,
+    done: bool,
+}
+
+impl<R: BufRead> LineMatches<'_, R> {
+    // 次の行を読み、改行を除いた部分を探す準備をする。入力の終わりなら false
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        let n = self.reader.read_line(&mut self.line)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.line_no += 1;
+        self.line_offset = self.next_offset;
+        self.next_offset += n;
+        let content = self.line.strip_suffix('\n').unwrap_or(&self.line);
+        self.content = content.strip_suffix('\r').unwrap_or(content).len();
+        self.cursor = Some(Cursor::new(Span::new(0, self.content)));
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Iterator for LineMatches<'_, R> {
+    type Item = io::Result<LineMatch>;
+
+    fn next(&mut self) -> Option<io::Result<LineMatch>> {
+        while !self.done {
+            let Some(cursor) = self.cursor.as_mut() else {
+                match self.read_line() {
+                    Ok(true) => continue,
+                    Ok(false) => self.done = true,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+                continue;
+            };
+            let (re, hay) = (self.re, &self.line[..self.content]);
+            let found: Result<_, MatchError> = cursor.try_next(hay, |opts| {
+                Ok(re.try_search(hay, opts)?.map(|m| (m.span(), m)))
+            });
+            match found {
+                Ok(Some(m)) => {
+                    let base = self.line_offset;
+                    return Some(Ok(LineMatch {
+                        line_no: self.line_no,
+                        line_offset: base,
+                        span: Span::new(base + m.start(), base + m.end()),
+                        text: m.as_str().to_owned(),
+                    }));
+                }
+                Ok(None) => self.cursor = None,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(io::Error::other(e)));
+                }
+            }
+        }
+        None
+    }
+}
+
+// 終わりと失敗のどちらの後も done が立ったままなので、None を返し続ける
+impl<R: BufRead> FusedIterator for LineMatches<'_, R> {}
+
+impl Regex {
+    /// `r` を1行ずつ読み、各行の一致を `find_iter` と同じ順で返す。
+    /// 行末の改行（`\n` または `\r\n`）は照合の対象にしないので、`$` は改行の手前で一致する。
+    /// 行をまたぐ一致は見つからない。
+    ///
+    /// ```
+    /// use my_regex::{Regex, Span};
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let input = "a1\r\nbb\nc22 3\n".as_bytes();
+    /// let got: Vec<_> = re
+    ///     .match_lines(input)
+    ///     .map(|m| m.map(|m| (m.line_no, m.span, m.text)))
+    ///     .collect::<std::io::Result<_>>()
+    ///     .unwrap();
+    /// assert_eq!(got, vec![
+    ///     (1, Span::new(1, 2), "1".to_string()),
+    ///     (3, Span::new(8, 10), "22".to_string()),
+    ///     (3, Span::new(11, 12), "3".to_string()),
+    /// ]);
+    /// ```
+    pub fn match_lines<R: BufRead>(&self, r: R) -> LineMatches<'_, R> {
+        LineMatches {
+            re: self,
+            reader: r,
+            line: String::new(),
+            content: 0,
+            line_no: 0,
+            line_offset: 0,
+            next_offset: 0,
+            cursor: None,
+            done: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod lines_tests {
+    use std::io::{BufReader, ErrorKind};
+
+    use crate::{RegexBuilder, Span};
+
+    #[test]
+    fn offsets_are_absolute_and_anchors_see_one_line() {
+        let re = RegexBuilder::new("^x*$").build().unwrap();
+        let input = "xx\n\nab\r\nx";
+        // 小さいバッファで読んでも、行の位置は入力全体で数える
+        let got: Vec<(usize, usize, Span)> = re
+            .match_lines(BufReader::with_capacity(2, input.as_bytes()))
+            .map(|m| m.map(|m| (m.line_no, m.line_offset, m.span)))
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            got,
+            vec![
+                (1, 0, Span::new(0, 2)),
+                (2, 3, Span::new(3, 3)),
+                (4, 8, Span::new(8, 9)),
+            ]
+        );
+        for (_, _, span) in &got {
+            assert!(input[span.range()].chars().all(|c| c == 'x'));
+        }
+    }
+
+    #[test]
+    fn errors_end_the_iterator() {
+        let re = RegexBuilder::new("a").build().unwrap();
+        let mut it = re.match_lines(&b"a\n\xff\na\n"[..]);
+        assert_eq!(it.next().unwrap().unwrap().line_no, 1);
+        assert_eq!(
+            it.next().unwrap().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert!(it.next().is_none());
+
+        let re = RegexBuilder::new("(a|aa)*b")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        let mut it = re.match_lines(&b"aaaa\n"[..]);
+        assert_eq!(it.next().unwrap().unwrap_err().kind(), ErrorKind::Other);
+        assert!(it.next().is_none());
+    }
+}
@@ -0,0 +1,262 @@
+// lint.rs
+//! パターンの書き方の注意点を、規則番号付きの診断として返す（CI でのレビュー用）。
+//!
+//! 診断はコンパイルの可否とは別で、通るパターンにも出る。構文エラーは `RX000` として1件だけ返す。
+//!
+//! ```
+//! use my_regex::lint::{Rule, Severity, lint};
+//! let diags = lint("abc+");
+//! assert_eq!(diags[0].rule, Rule::QuantifiedLiteralTail);
+//! assert_eq!(diags[0].severity, Severity::Warning);
+//! assert_eq!(diags[0].to_string(), "RX001 warning at 2..4: quantifier applies to single character of multi-char literal");
+//! ```
+use std::fmt;
+
+use crate::Regex;
+use crate::span::Span;
+use crate::token::{Lexeme, Token, lex};
+
+/// 診断の規則
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    /// RX000: パターンがコンパイルできない
+    Syntax,
+    /// RX001: `abc+` の `+` は `c` だけに掛かる（`?` は意図的なことが多いので対象外）
+    QuantifiedLiteralTail,
+    /// RX002: 上限の無い量指定子の中に、さらに上限の無い量指定子がある（`(a+)*`）
+    NestedUnboundedQuantifier,
+    /// RX003: 文字クラスの範囲が重なっている（`[a-fc]`）
+    OverlappingClassRanges,
+    /// RX004: 文字クラスに非 ASCII 文字がある。クラスはバイト単位なので1文字として扱われない
+    NonAsciiInClass,
+}
+
+impl Rule {
+    /// `RX001` などの規則番号
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::Syntax => "RX000",
+            Rule::QuantifiedLiteralTail => "RX001",
+            Rule::NestedUnboundedQuantifier => "RX002",
+            Rule::OverlappingClassRanges => "RX003",
+            Rule::NonAsciiInClass => "RX004",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Rule::Syntax => "pattern does not compile",
+            Rule::QuantifiedLiteralTail => {
+                "quantifier applies to single character of multi-char literal"
+            }
+            Rule::NestedUnboundedQuantifier => "unbounded quantifier nested in another",
+            Rule::OverlappingClassRanges => "character class ranges overlap",
+            Rule::NonAsciiInClass => "non-ASCII character in class is matched byte by byte",
+        }
+    }
+
+    fn severity(self) -> Severity {
+        match self {
+            Rule::Syntax | Rule::NonAsciiInClass => Severity::Error,
+            Rule::QuantifiedLiteralTail | Rule::NestedUnboundedQuantifier => Severity::Warning,
+            Rule::OverlappingClassRanges => Severity::Info,
+        }
+    }
+}
+
+/// 診断の重さ。`Error` は意図どおりに動かない（またはコンパイルできない）もの
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// 1件の診断。`span` はパターン中のバイト範囲
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(rule: Rule, span: Span) -> Self {
+        Self {
+            rule,
+            severity: rule.severity(),
+            span,
+            message: rule.message().to_string(),
+        }
+    }
+}
+
+/// `RX001 warning at 2..4: ...`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} at {:?}: {}",
+            self.rule.code(),
+            self.severity,
+            self.span,
+            self.message
+        )
+    }
+}
+
+/// パターンを調べて診断を返す（パターン中の位置順）
+pub fn lint(pattern: &str) -> Vec<Diagnostic> {
+    // 括弧の対応などは構文解析まで進まないと分からないので、既定の設定で一度コンパイルする
+    let tokens = match Regex::new(pattern).and_then(|_| lex(pattern)) {
+        Ok(t) => t,
+        Err(e) => {
+            let mut d = Diagnostic::new(Rule::Syntax, e.span());
+            d.message = format!("{}: {:?}", d.message, e.kind);
+            return vec![d];
+        }
+    };
+    let mut out = Vec::new();
+    for (k, t) in tokens.iter().enumerate() {
+        match &t.token {
+            Token::Star | Token::Plus | Token::Repeat { .. } => {
+                if let [.., a, b] = &tokens[..k]
+                    && matches!(a.token, Token::Char(_))
+                    && matches!(b.token, Token::Char(_))
+                {
+                    out.push(Diagnostic::new(
+                        Rule::QuantifiedLiteralTail,
+                        Span::new(b.span.start, t.span.end),
+                    ));
+                }
+                if is_unbounded(&t.token)
+                    && let Some(open) = group_with_unbounded_inside(&tokens[..k])
+                {
+                    out.push(Diagnostic::new(
+                        Rule::NestedUnboundedQuantifier,
+                        Span::new(open, t.span.end),
+                    ));
+                }
+            }
+            Token::Class { ranges, .. } => {
+                if ranges.iter().any(|&(lo, hi)| lo >= 0x80 || hi >= 0x80) {
+                    out.push(Diagnostic::new(Rule::NonAsciiInClass, t.span));
+                } else if overlaps(ranges) {
+                    out.push(Diagnostic::new(Rule::OverlappingClassRanges, t.span));
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+fn is_unbounded(t: &Token) -> bool {
+    matches!(
+        t,
+        Token::Star | Token::Plus | Token::Repeat { max: None, .. }
+    )
+}
+
+// `before` が `)` で終わるなら、対応する `(` までの間に上限の無い量指定子があるか。
+// あればその `(` の位置
+fn group_with_unbounded_inside(before: &[Lexeme]) -> Option<usize> {
+    let (last, inner) = before.split_last()?;
+    if last.token != Token::RParen {
+        return None;
+    }
+    let mut depth = 0usize;
+    let mut found = false;
+    for t in inner.iter().rev() {
+        match t.token {
+            Token::RParen => depth += 1,
+            Token::LParen | Token::NonCapturing if depth == 0 => {
+                return found.then_some(t.span.start);
+            }
+            Token::LParen | Token::NonCapturing => depth -= 1,
+            ref q if is_unbounded(q) => found = true,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn overlaps(ranges: &[(u8, u8)]) -> bool {
+    let mut sorted: Vec<(u8, u8)> = ranges.to_vec();
+    sorted.sort_unstable();
+    sorted.windows(2).any(|w| w[1].0 <= w[0].1)
+}
+
+#[cfg(test)]
+mod lint_tests {
+    use super::{Rule, Severity, lint};
+    use crate::Span;
+
+    fn rules(pat: &str) -> Vec<(Rule, Span)> {
+        lint(pat).into_iter().map(|d| (d.rule, d.span)).collect()
+    }
+
+    #[test]
+    fn quantified_literal_tail() {
+        assert_eq!(
+            rules(r"ab+ x\.{2}"),
+            vec![
+                (Rule::QuantifiedLiteralTail, Span::new(1, 3)),
+                (Rule::QuantifiedLiteralTail, Span::new(5, 10)),
+            ]
+        );
+        // 1文字だけ、グループ、`?` は対象外
+        assert!(rules(r"a+ (ab)+ colou?r").is_empty());
+    }
+
+    #[test]
+    fn nested_unbounded() {
+        assert_eq!(
+            rules(r"x(a+|b)*"),
+            vec![(Rule::NestedUnboundedQuantifier, Span::new(1, 8))]
+        );
+        assert_eq!(
+            rules(r"((a)*b){2,}"),
+            vec![(Rule::NestedUnboundedQuantifier, Span::new(0, 11))]
+        );
+        assert!(rules(r"(a+){2}").is_empty());
+        assert!(rules(r"(a?b)+").is_empty());
+        // 内側のグループ同士は別々に見る
+        assert!(rules(r"(a+)(b)*").is_empty());
+    }
+
+    #[test]
+    fn class_rules_and_severities() {
+        let d = lint("[a-fc][é]");
+        assert_eq!(d.len(), 2);
+        assert_eq!(
+            (d[0].rule, d[0].severity),
+            (Rule::OverlappingClassRanges, Severity::Info)
+        );
+        assert_eq!(
+            (d[1].rule, d[1].severity, d[1].span),
+            (Rule::NonAsciiInClass, Severity::Error, Span::new(6, 10))
+        );
+        assert!(lint("[a-cd-f]").is_empty());
+    }
+
+    #[test]
+    fn syntax_error_is_single_diagnostic() {
+        let d = lint("(ab");
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].rule.code(), "RX000");
+        assert!(d[0].to_string().starts_with("RX000 error at "), "{}", d[0]);
+    }
+}
@@ -0,0 +1,191 @@
+// metrics.rs
+//! `Regex` ごとの照合回数などの計数（feature `metrics`）。
+//!
+//! 計数はすべての `Regex` が持ち、`Regex::stats` で読める。
+//! `RegexBuilder::metrics_name` で名前を付けたものは全体の登録簿にも載り、
+//! `metrics::snapshot` / `metrics::prometheus_text` でまとめて書き出せる。
+//! `Regex` を clone したものは計数を共有する。落とされた `Regex` は登録簿から消える。
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::Regex;
+use crate::error::MatchError;
+use crate::strategy::Engine;
+
+/// ある時点の計数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// 照合の呼び出し回数（`is_match` / `captures` / `match_prefix` / `search` 系）
+    pub searches: u64,
+    /// そのうち一致したもの
+    pub matches: u64,
+    /// そのうち固定文字列比較ではなく NFA シミュレーションで照合したもの
+    pub slow_path: u64,
+    /// そのうち `SearchOptions::budget` を使い切って打ち切られたもの
+    pub budget_aborts: u64,
+}
+
+impl Stats {
+    // (系列名, 値)。書き出しの順番もこれ
+    fn series(&self) -> [(&'static str, u64); 4] {
+        [
+            ("searches", self.searches),
+            ("matches", self.matches),
+            ("slow_path", self.slow_path),
+            ("budget_aborts", self.budget_aborts),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    searches: AtomicU64,
+    matches: AtomicU64,
+    slow_path: AtomicU64,
+    budget_aborts: AtomicU64,
+}
+
+impl Counters {
+    fn load(&self) -> Stats {
+        Stats {
+            searches: self.searches.load(Ordering::Relaxed),
+            matches: self.matches.load(Ordering::Relaxed),
+            slow_path: self.slow_path.load(Ordering::Relaxed),
+            budget_aborts: self.budget_aborts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// 名前付きの計数の登録簿
+static REGISTRY: Mutex<Vec<(String, Weak<Counters>)>> = Mutex::new(Vec::new());
+
+/// 計数を作り、名前があれば登録簿に載せる
+pub(crate) fn counters(name: Option<&str>) -> Arc<Counters> {
+    let c = Arc::new(Counters::default());
+    if let Some(name) = name {
+        let mut reg = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        reg.retain(|(_, w)| w.strong_count() > 0);
+        reg.push((name.to_string(), Arc::downgrade(&c)));
+    }
+    c
+}
+
+/// 登録簿にある（まだ生きている）`Regex` の名前と計数。登録順
+pub fn snapshot() -> Vec<(String, Stats)> {
+    let mut reg = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    reg.retain(|(_, w)| w.strong_count() > 0);
+    reg.iter()
+        .filter_map(|(name, w)| Some((name.clone(), w.upgrade()?.load())))
+        .collect()
+}
+
+/// `snapshot` を Prometheus のテキスト形式で書き出す。名前は `regex` ラベルに入る
+///
+/// ```
+/// use my_regex::{RegexBuilder, metrics};
+/// let re = RegexBuilder::new("[0-9]+").metrics_name("digits").build().unwrap();
+/// re.is_match("42");
+/// assert!(metrics::prometheus_text().contains(r#"my_regex_matches_total{regex="digits"} 1"#));
+/// ```
+pub fn prometheus_text() -> String {
+    let snap = snapshot();
+    let mut out = String::new();
+    for (k, (metric, _)) in Stats::default().series().into_iter().enumerate() {
+        // String への書き込みは失敗しない
+        let _ = writeln!(out, "# TYPE my_regex_{metric}_total counter");
+        for (name, stats) in &snap {
+            let _ = writeln!(
+                out,
+                "my_regex_{metric}_total{{regex=\"{}\"}} {}",
+                escape_label(name),
+                stats.series()[k].1
+            );
+        }
+    }
+    out
+}
+
+// ラベル値では \ と " と改行をエスケープする
+fn escape_label(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+impl Regex {
+    /// この `Regex`（と clone したもの）の計数
+    pub fn stats(&self) -> Stats {
+        self.counters.load()
+    }
+
+    /// 照合1回分の結果を計数に足して、そのまま返す
+    pub(crate) fn observe<T>(
+        &self,
+        res: Result<T, MatchError>,
+        matched: impl FnOnce(&T) -> bool,
+    ) -> Result<T, MatchError> {
+        let c = &self.counters;
+        c.searches.fetch_add(1, Ordering::Relaxed);
+        if self.strategy.engine == Engine::Nfa {
+            c.slow_path.fetch_add(1, Ordering::Relaxed);
+        }
+        match &res {
+            Ok(v) if matched(v) => {
+                c.matches.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(MatchError::BudgetExceeded { .. }) => {
+                c.budget_aborts.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::{Stats, escape_label, snapshot};
+    use crate::{Regex, RegexBuilder, SearchOptions};
+
+    #[test]
+    fn counts_per_regex_and_shares_with_clones() {
+        let re = Regex::new("a+").unwrap();
+        assert!(re.is_match("aa"));
+        assert!(!re.is_match("b"));
+        let twin = re.clone();
+        assert!(twin.captures("a").is_some());
+        let opts = SearchOptions {
+            anchored: false,
+            budget: Some(1),
+            ..Default::default()
+        };
+        assert!(re.try_search("bbba", &opts).is_err());
+        assert_eq!(
+            re.stats(),
+            Stats {
+                searches: 4,
+                matches: 2,
+                slow_path: 4,
+                budget_aborts: 1,
+            }
+        );
+        assert_eq!(Regex::new("a+").unwrap().stats(), Stats::default());
+    }
+
+    #[test]
+    fn registry_drops_dead_regexes() {
+        let name = "metrics_tests::registry";
+        let re = RegexBuilder::new("x").metrics_name(name).build().unwrap();
+        re.match_prefix("xy");
+        let found = snapshot().into_iter().find(|(n, _)| n == name);
+        assert_eq!(found.map(|(_, s)| s.matches), Some(1));
+        drop(re);
+        assert!(snapshot().iter().all(|(n, _)| n != name));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+    }
+}
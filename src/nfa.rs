@@ -1,15 +1,69 @@
 // nfa.rs
-use crate::error::{Error, ErrorKind, err};
-use crate::token::Token;
+use crate::class::{ByteSet, ClassSet};
+use crate::error::{Error, ErrorKind, Feature, err};
+use crate::token::{Look, Token};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) enum Label {
     Eps,
     Byte(u8),
     Any,
-    Class { ranges: Vec<(u8, u8)>, neg: bool },
+    Class(ByteSet), // neg は畳み込み済み
     CapBegin(usize),
     CapEnd(usize),
+    Look(Look), // 入力を消費しない位置判定
+}
+
+impl Label {
+    /// 1バイト読む遷移なら、読めるバイトの集合
+    pub(crate) fn byte_set(&self) -> Option<ByteSet> {
+        match self {
+            Label::Byte(c) => Some(ByteSet::singleton(*c)),
+            Label::Any => Some(ByteSet::full()),
+            Label::Class(set) => Some(*set),
+            _ => None,
+        }
+    }
+}
+
+/// プログラム表示用: 1ラベルを短い記号で書く
+impl std::fmt::Display for Label {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn byte(f: &mut std::fmt::Formatter<'_>, b: u8) -> std::fmt::Result {
+            if b.is_ascii_graphic() || b == b' ' {
+                write!(f, "{}", b as char)
+            } else {
+                write!(f, "\\x{b:02X}")
+            }
+        }
+        match self {
+            Label::Eps => write!(f, "ε"),
+            Label::Byte(b) => {
+                write!(f, "'")?;
+                byte(f, *b)?;
+                write!(f, "'")
+            }
+            Label::Any => write!(f, "."),
+            Label::Class(set) => {
+                write!(f, "[")?;
+                for (lo, hi) in set.ranges() {
+                    byte(f, lo)?;
+                    if lo != hi {
+                        write!(f, "-")?;
+                        byte(f, hi)?;
+                    }
+                }
+                write!(f, "]")
+            }
+            Label::CapBegin(g) => write!(f, "S{g}"),
+            Label::CapEnd(g) => write!(f, "E{g}"),
+            Label::Look(Look::WordBoundary) => write!(f, "\\b"),
+            Label::Look(Look::LineStart) => write!(f, "^"),
+            Label::Look(Look::LineEnd) => write!(f, "$"),
+            Label::Look(Look::TextStart) => write!(f, "\\A"),
+            Label::Look(Look::TextEnd) => write!(f, "\\z"),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -22,9 +76,84 @@ pub(crate) struct Nfa {
     pub states: Vec<State>,
     pub start: usize,
     pub accept: usize,
+    // トップレベルの `|` の各枝の入口の状態（左から）。`|` が無ければ空
+    pub branches: Vec<usize>,
+}
+
+impl Nfa {
+    /// 各状態から accept までに最低限消費すべきバイト数。
+    /// ε/Cap はコスト0、文字を読む遷移はコスト1 として accept から逆向きに 0-1 BFS。
+    /// accept に到達できない状態は usize::MAX。
+    pub(crate) fn min_remaining(&self) -> Vec<usize> {
+        use std::collections::VecDeque;
+
+        let n = self.states.len();
+        // 逆辺: rev[to] = [(from, cost)]
+        let mut rev: Vec<Vec<(usize, usize)>> = vec![Vec::new(); n];
+        for (from, st) in self.states.iter().enumerate() {
+            for (lbl, to) in &st.edges {
+                let cost = match lbl {
+                    Label::Eps | Label::CapBegin(_) | Label::CapEnd(_) | Label::Look(_) => 0,
+                    Label::Byte(_) | Label::Any | Label::Class(_) => 1,
+                };
+                rev[*to].push((from, cost));
+            }
+        }
+
+        let mut dist = vec![usize::MAX; n];
+        let mut q = VecDeque::new();
+        dist[self.accept] = 0;
+        q.push_back(self.accept);
+        while let Some(u) = q.pop_front() {
+            for &(v, cost) in &rev[u] {
+                let d = dist[u] + cost;
+                if d < dist[v] {
+                    dist[v] = d;
+                    if cost == 0 {
+                        q.push_front(v);
+                    } else {
+                        q.push_back(v);
+                    }
+                }
+            }
+        }
+        dist
+    }
 }
 
-pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
+/// `{m,n}` は回数分だけ部分式を複製するので、入れ子になると外側の回数が掛かる
+/// （`(a{1000}){200}` は `a` を 20 万個作る）。後置列を評価して、各 `{m,n}` の中身が
+/// 何個に複製されるか（自身と内側の回数の積）が `limit` を超えないかを確かめる。
+/// エラー位置は後置列の添字。形の崩れた後置列は `build_nfa` に任せて素通りする
+pub(crate) fn check_nested_repetition(postfix: &[Token], limit: usize) -> Result<(), Error> {
+    // 部分式ごとに、その中で最も多く複製される原子の個数
+    let mut st: Vec<usize> = Vec::new();
+    for (i, t) in postfix.iter().enumerate() {
+        match t {
+            Token::Concat | Token::Alt => {
+                let b = st.pop().unwrap_or(1);
+                let a = st.pop().unwrap_or(1);
+                st.push(a.max(b));
+            }
+            Token::Star | Token::Plus | Token::Qmark => {}
+            Token::Repeat { min, max } => {
+                let inner = st.pop().unwrap_or(1);
+                let copies = inner.saturating_mul(max.unwrap_or(*min).max(1));
+                if copies > limit {
+                    return err(ErrorKind::RepetitionTooLarge { limit }, i);
+                }
+                st.push(copies);
+            }
+            Token::LParen | Token::NonCapturing | Token::RParen => {}
+            _ => st.push(1),
+        }
+    }
+    Ok(())
+}
+
+/// `top_alts[i]` が true の `|`（後置列の i 番目）をトップレベルの選択として、
+/// その枝の入口を `Nfa::branches` に残す。トップレベルの `|` は左結合で、前の `|` が左の枝になる
+pub(crate) fn build_nfa(postfix: &[Token], top_alts: &[bool]) -> Result<Nfa, Error> {
     // ===== 内部ビルダー（未パッチの to を持つ） =====
     #[derive(Clone, Debug)]
     struct EdgeBuilder {
@@ -39,10 +168,12 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
     // 「穴」を (state_id, edge_index) の2-tupleで表す
     type Hole = (usize, usize);
 
+    // 部分式の状態は後置列の順に作られるので、lo..(作成時点の states.len()) が連続してその部分式のもの
     #[derive(Clone, Debug)]
     struct Frag {
         start: usize,
         outs: Vec<Hole>,
+        lo: usize,
     }
 
     impl StateBuilder {
@@ -98,13 +229,46 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
             Token::Star => '*',
             Token::Plus => '+',
             Token::Qmark => '?',
-            Token::LParen => '(',
+            Token::LParen | Token::NonCapturing => '(',
             Token::RParen => ')',
             Token::Dot => '.',
             Token::Char(c) => *c as char,
             Token::Class { .. } => ']',
             Token::CapStart(_gid) => '(',
             Token::CapEnd(_gid) => ')',
+            Token::Look(_) => 'b',
+            Token::Repeat { .. } => '{',
+            Token::NamedClass { .. } | Token::CodepointClass(_) => ']',
+        }
+    }
+
+    // 文字集合の UTF-8 表現: 各バイト範囲列を1本の鎖にし、start から分岐する
+    fn codepoint_frag(states: &mut Vec<StateBuilder>, set: &ClassSet) -> Frag {
+        let s = new_state(states);
+        let mut outs = Vec::new();
+        for seq in set.utf8_sequences() {
+            let mut prev = s;
+            for (k, &(lo, hi)) in seq.iter().enumerate() {
+                let label = if lo == hi {
+                    Label::Byte(lo)
+                } else {
+                    let mut bs = ByteSet::empty();
+                    bs.insert_range(lo, hi);
+                    Label::Class(bs)
+                };
+                if k + 1 == seq.len() {
+                    outs.push(hole(states, prev, label));
+                } else {
+                    let t = new_state(states);
+                    edge_to(states, prev, label, t);
+                    prev = t;
+                }
+            }
+        }
+        Frag {
+            start: s,
+            outs,
+            lo: s,
         }
     }
 
@@ -115,6 +279,157 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
         Frag {
             start: s,
             outs: vec![h],
+            lo: s,
+        }
+    }
+
+    // 各合成。量指定子の展開（{m,n}）からも使う
+    fn concat(states: &mut [StateBuilder], a: Frag, b: Frag) -> Frag {
+        patch(states, &a.outs, b.start);
+        Frag {
+            start: a.start,
+            outs: b.outs,
+            lo: a.lo.min(b.lo),
+        }
+    }
+
+    fn star(states: &mut Vec<StateBuilder>, a: Frag) -> Frag {
+        let s = new_state(states);
+        // ε->A.start と ε->外（穴）
+        edge_to(states, s, Label::Eps, a.start);
+        let h = hole(states, s, Label::Eps);
+        // A の末端から S へ戻す
+        patch(states, &a.outs, s);
+        Frag {
+            start: s,
+            outs: vec![h],
+            lo: a.lo,
+        }
+    }
+
+    // A の末尾から Split
+    fn plus(states: &mut Vec<StateBuilder>, a: Frag) -> Frag {
+        let s = new_state(states);
+        edge_to(states, s, Label::Eps, a.start);
+        let h = hole(states, s, Label::Eps);
+        patch(states, &a.outs, s);
+        // start は A を保つ（最低1回）
+        Frag {
+            start: a.start,
+            outs: vec![h],
+            lo: a.lo,
+        }
+    }
+
+    fn qmark(states: &mut Vec<StateBuilder>, a: Frag) -> Frag {
+        let s = new_state(states);
+        edge_to(states, s, Label::Eps, a.start);
+        let h = hole(states, s, Label::Eps);
+        let mut outs = a.outs;
+        outs.push(h);
+        Frag {
+            start: s,
+            outs,
+            lo: a.lo,
+        }
+    }
+
+    // パッチ前の部分式の写し。states[lo..] を複製して番号だけずらす
+    struct Template {
+        states: Vec<StateBuilder>,
+        lo: usize,
+        start: usize,
+        outs: Vec<Hole>,
+    }
+
+    impl Template {
+        fn new(states: &[StateBuilder], a: &Frag) -> Self {
+            Self {
+                states: states[a.lo..].to_vec(),
+                lo: a.lo,
+                start: a.start,
+                outs: a.outs.clone(),
+            }
+        }
+
+        fn instantiate(&self, states: &mut Vec<StateBuilder>) -> Frag {
+            let base = states.len();
+            let shift = |id: usize| id - self.lo + base;
+            for sb in &self.states {
+                let mut sb = sb.clone();
+                for e in &mut sb.edges {
+                    e.to = e.to.map(shift);
+                }
+                states.push(sb);
+            }
+            Frag {
+                start: shift(self.start),
+                outs: self
+                    .outs
+                    .iter()
+                    .map(|&(sid, ei)| (shift(sid), ei))
+                    .collect(),
+                lo: base,
+            }
+        }
+    }
+
+    // A{min,max}: 必須の min 個を連接し、残りは (A(A(A)?)?)? のように入れ子の省略可能にする。
+    // 上限なしなら最後の1個を A+（min=0 なら A*）にする。
+    // キャプチャの遷移も複製されるので、どの回の A も同じグループ番号に書く。
+    fn repeat(states: &mut Vec<StateBuilder>, a: Frag, min: usize, max: Option<usize>) -> Frag {
+        if max == Some(0) {
+            // A{0} / A{0,0}: 空に一致する。A の状態は到達しないが、穴は埋めておく
+            let s = new_state(states);
+            let h = hole(states, s, Label::Eps);
+            patch(states, &a.outs, s);
+            return Frag {
+                start: s,
+                outs: vec![h],
+                lo: a.lo,
+            };
+        }
+        let tmpl = Template::new(states, &a);
+        // 1個目は元の部分式をそのまま使い、2個目以降を複製する
+        let mut original = Some(a);
+        let mut next_copy = |states: &mut Vec<StateBuilder>| {
+            original.take().unwrap_or_else(|| tmpl.instantiate(states))
+        };
+
+        let mut required: Option<Frag> = None;
+        for k in 0..min {
+            let mut c = next_copy(states);
+            if max.is_none() && k + 1 == min {
+                c = plus(states, c);
+            }
+            required = Some(match required {
+                Some(r) => concat(states, r, c),
+                None => c,
+            });
+        }
+
+        let optional = match max {
+            None if min == 0 => {
+                let c = next_copy(states);
+                Some(star(states, c))
+            }
+            None => None,
+            Some(max) => {
+                let copies: Vec<Frag> = (min..max).map(|_| next_copy(states)).collect();
+                copies.into_iter().rev().fold(None, |inner, c| {
+                    let body = match inner {
+                        Some(inner) => concat(states, c, inner),
+                        None => c,
+                    };
+                    Some(qmark(states, body))
+                })
+            }
+        };
+
+        match (required, optional) {
+            (Some(r), Some(o)) => concat(states, r, o),
+            (Some(f), None) | (None, Some(f)) => f,
+            (None, None) => unreachable!("max == 0 is handled above"),
         }
     }
 
@@ -125,6 +440,7 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
     let global_start = new_state(&mut states);
 
     let mut st: Vec<Frag> = Vec::new();
+    let mut branches = Vec::new();
 
     for (i, t) in postfix.iter().enumerate() {
         match t {
@@ -134,10 +450,7 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
             Token::Class { ranges, neg } => {
                 st.push(make_unary_frag(
                     &mut states,
-                    Label::Class {
-                        ranges: ranges.clone(),
-                        neg: *neg,
-                    },
+                    Label::Class(ByteSet::from_ranges(ranges, *neg)),
                 ));
             }
 
@@ -145,62 +458,49 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
             Token::Concat => {
                 let (a, b) = pop2(&mut st, i, t)?;
                 // A.outs を B.start にパッチ
-                patch(&mut states, &a.outs, b.start);
-                st.push(Frag {
-                    start: a.start,
-                    outs: b.outs,
-                });
+                st.push(concat(&mut states, a, b));
             }
 
             // A | B
             Token::Alt => {
                 let (a, b) = pop2(&mut st, i, t)?;
+                if top_alts.get(i).copied().unwrap_or(false) {
+                    if branches.is_empty() {
+                        branches.push(a.start);
+                    }
+                    branches.push(b.start);
+                }
                 let s = new_state(&mut states);
                 edge_to(&mut states, s, Label::Eps, a.start);
                 edge_to(&mut states, s, Label::Eps, b.start);
+                let lo = a.lo;
                 let mut outs = a.outs;
                 outs.extend_from_slice(&b.outs);
-                st.push(Frag { start: s, outs });
+                st.push(Frag { start: s, outs, lo });
             }
 
             // A*
             Token::Star => {
                 let a = pop1(&mut st, i, t)?;
-                let s = new_state(&mut states);
-                // ε->A.start と ε->外（穴）
-                edge_to(&mut states, s, Label::Eps, a.start);
-                let h = hole(&mut states, s, Label::Eps);
-                // A の末端から S へ戻す
-                patch(&mut states, &a.outs, s);
-                st.push(Frag {
-                    start: s,
-                    outs: vec![h],
-                });
+                st.push(star(&mut states, a));
             }
 
             // A+  (A の末尾から Split)
             Token::Plus => {
                 let a = pop1(&mut st, i, t)?;
-                let s = new_state(&mut states);
-                edge_to(&mut states, s, Label::Eps, a.start);
-                let h = hole(&mut states, s, Label::Eps);
-                patch(&mut states, &a.outs, s);
-                // start は A を保つ（最低1回）
-                st.push(Frag {
-                    start: a.start,
-                    outs: vec![h],
-                });
+                st.push(plus(&mut states, a));
             }
 
             // A?
             Token::Qmark => {
                 let a = pop1(&mut st, i, t)?;
-                let s = new_state(&mut states);
-                edge_to(&mut states, s, Label::Eps, a.start);
-                let h = hole(&mut states, s, Label::Eps);
-                let mut outs = a.outs;
-                outs.push(h);
-                st.push(Frag { start: s, outs });
+                st.push(qmark(&mut states, a));
+            }
+
+            // A{min,max}
+            Token::Repeat { min, max } => {
+                let a = pop1(&mut st, i, t)?;
+                st.push(repeat(&mut states, a, *min, *max));
             }
             Token::CapStart(gid) => {
                 st.push(make_unary_frag(&mut states, Label::CapBegin(*gid)));
@@ -208,13 +508,23 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
             Token::CapEnd(gid) => {
                 st.push(make_unary_frag(&mut states, Label::CapEnd(*gid)));
             }
+            Token::Look(look) => {
+                st.push(make_unary_frag(&mut states, Label::Look(*look)));
+            }
+            Token::CodepointClass(set) => st.push(codepoint_frag(&mut states, set)),
+            // 名前は Regex::with_config で解決済みの前提
+            Token::NamedClass { .. } => {
+                return err(ErrorKind::UnsupportedFeature(Feature::UnicodeClass), i);
+            }
 
             // 括弧は postfix 済みの前提
-            Token::LParen | Token::RParen => return err(ErrorKind::UnbalancedParen, i),
+            Token::LParen | Token::NonCapturing | Token::RParen => {
+                return err(ErrorKind::UnbalancedParen, i);
+            }
         }
     }
 
-    let top = st.pop().ok_or_else(|| Error {
+    let top = st.pop().ok_or(Error {
         kind: ErrorKind::UnexpectedToken('$'),
         pos: postfix.len(),
     })?;
@@ -249,20 +559,21 @@ pub(crate) fn build_nfa(postfix: &[Token]) -> Result<Nfa, Error> {
         states: final_states,
         start: global_start, // ★ start=0 固定
         accept,
+        branches,
     })
 }
 
 #[cfg(test)]
 mod nfa_tests {
     use super::*;
-    use crate::parse::{insert_concat, to_postfix};
+    use crate::parse::{drop_captures, insert_concat, to_postfix};
     use crate::token::tokenize;
 
     fn make_nfa(pat: &str) -> Nfa {
         let t = tokenize(pat).unwrap();
         let t = insert_concat(&t);
         let p = to_postfix(&t).unwrap();
-        build_nfa(&p).unwrap()
+        build_nfa(&p, &[]).unwrap()
     }
 
     fn labels(nfa: &Nfa, sid: usize) -> Vec<String> {
@@ -273,9 +584,10 @@ mod nfa_tests {
                 Label::Eps => "ε".to_string(),
                 Label::Byte(b) => format!("{}", *b as char),
                 Label::Any => ".".to_string(),
-                Label::Class { .. } => "[]".to_string(),
+                Label::Class(_) => "[]".to_string(),
                 Label::CapBegin(g) => format!("S{}", g),
                 Label::CapEnd(g) => format!("E{}", g),
+                Label::Look(_) => "look".to_string(),
             })
             .collect()
     }
@@ -352,7 +664,7 @@ mod nfa_tests {
             for (l, _) in &st.edges {
                 match l {
                     Label::Any => seen_dot = true,
-                    Label::Class { .. } => seen_class = true,
+                    Label::Class(_) => seen_class = true,
                     _ => {}
                 }
             }
@@ -378,10 +690,58 @@ mod nfa_tests {
         assert!(has_begin && has_end);
     }
 
+    #[test]
+    fn min_remaining_counts_consuming_edges() {
+        let nfa = make_nfa("ab(c|de)f*");
+        let dist = nfa.min_remaining();
+        // start からは a, b, c の3バイトが最低限必要（f* は0回でよい）
+        assert_eq!(dist[nfa.start], 3);
+        assert_eq!(dist[nfa.accept], 0);
+    }
+
+    #[test]
+    fn min_remaining_plus_needs_one() {
+        let nfa = make_nfa("a+");
+        assert_eq!(nfa.min_remaining()[nfa.start], 1);
+        let nfa = make_nfa("a*");
+        assert_eq!(nfa.min_remaining()[nfa.start], 0);
+    }
+
+    #[test]
+    fn dropped_captures_build_no_cap_states() {
+        let with_caps = make_nfa("(a)(b(c))");
+        let t = insert_concat(&tokenize("(a)(b(c))").unwrap());
+        let p = to_postfix(&t).unwrap();
+        let pos: Vec<usize> = (0..p.len()).collect();
+        let nfa = build_nfa(&drop_captures(&p, &pos).0, &[]).unwrap();
+        let caps = nfa
+            .states
+            .iter()
+            .flat_map(|st| st.edges.iter())
+            .filter(|(l, _)| matches!(l, Label::CapBegin(_) | Label::CapEnd(_)))
+            .count();
+        assert_eq!(caps, 0);
+        // グループ1つにつき CapBegin / CapEnd の2状態が減る
+        assert_eq!(nfa.states.len() + 6, with_caps.states.len());
+    }
+
+    #[test]
+    fn label_display() {
+        use crate::class::ByteSet;
+        assert_eq!(Label::Eps.to_string(), "ε");
+        assert_eq!(Label::Byte(b'a').to_string(), "'a'");
+        assert_eq!(Label::Byte(b'\n').to_string(), "'\\x0A'");
+        assert_eq!(Label::Any.to_string(), ".");
+        let set = ByteSet::from_ranges(&[(b'0', b'9'), (b'_', b'_')], false);
+        assert_eq!(Label::Class(set).to_string(), "[0-9_]");
+        assert_eq!(Label::CapBegin(1).to_string(), "S1");
+        assert_eq!(Label::CapEnd(2).to_string(), "E2");
+    }
+
     #[test]
     fn error_on_empty_postfix() {
         // build_nfa は空入力で UnexpectedToken を返す
-        let err = build_nfa(&[]).unwrap_err();
+        let err = build_nfa(&[], &[]).unwrap_err();
         assert!(matches!(err.kind, ErrorKind::UnexpectedToken(_)));
     }
 }
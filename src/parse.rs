@@ -4,11 +4,26 @@ use crate::{
 };
 
 /// 連接が必要な箇所に `Concat` を挿入する
+#[cfg(test)] // 位置の要らないテスト用
 pub(crate) fn insert_concat(tokens: &[Token]) -> Vec<Token> {
+    let pos: Vec<usize> = (0..tokens.len()).collect();
+    insert_concat_with_pos(tokens, &pos).0
+}
+
+/// `insert_concat` の位置付き版。`pos[i]` は tokens[i] のパターン中の位置。
+/// 挿入した Concat には直後のトークンの位置を付ける。
+pub(crate) fn insert_concat_with_pos(tokens: &[Token], pos: &[usize]) -> (Vec<Token>, Vec<usize>) {
     fn is_atom_start(t: &Token) -> bool {
         matches!(
             t,
-            Token::Char(_) | Token::Dot | Token::LParen | Token::Class { .. }
+            Token::Char(_)
+                | Token::Dot
+                | Token::LParen
+                | Token::NonCapturing
+                | Token::Class { .. }
+                | Token::Look(_)
+                | Token::NamedClass { .. }
+                | Token::CodepointClass(_)
         )
     }
 
@@ -19,29 +34,48 @@ pub(crate) fn insert_concat(tokens: &[Token]) -> Vec<Token> {
             | Token::Dot
             | Token::RParen
             | Token::Class { .. }
+            | Token::Look(_)
+            | Token::NamedClass { .. }
+            | Token::CodepointClass(_)
             // 直前要素に作用した量指定子の“後ろ側”も、次が来たら連接対象になり得る
             | Token::Star
             | Token::Plus
             | Token::Qmark
+            | Token::Repeat { .. }
         )
     }
     let mut out = Vec::with_capacity(tokens.len() * 2);
+    let mut out_pos = Vec::with_capacity(tokens.len() * 2);
     let mut prev: Option<&Token> = None;
 
-    for t in tokens {
-        if let Some(p) = prev {
-            if is_atom_end(p) && is_atom_start(t) {
-                out.push(Token::Concat);
-            }
+    for (t, &p_t) in tokens.iter().zip(pos) {
+        if let Some(p) = prev
+            && is_atom_end(p)
+            && is_atom_start(t)
+        {
+            out.push(Token::Concat);
+            out_pos.push(p_t);
         }
         out.push(t.clone());
+        out_pos.push(p_t);
         prev = Some(t);
     }
-    out
+    (out, out_pos)
 }
 
 /// 中置トークン列（※Concat 済み想定）を後置記法へ
+#[cfg(test)] // 位置の要らないテスト用
 pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
+    let pos: Vec<usize> = (0..tokens.len()).collect();
+    Ok(to_postfix_with_pos(tokens, &pos)?.0)
+}
+
+/// `to_postfix` の位置付き版。`pos[i]` は tokens[i] のパターン中の位置で、
+/// エラー位置と、後置列の各トークンの位置（戻り値の2つ目）に使う。
+pub(crate) fn to_postfix_with_pos(
+    tokens: &[Token],
+    pos: &[usize],
+) -> Result<(Vec<Token>, Vec<usize>), Error> {
     fn is_bin_op(t: &Token) -> bool {
         matches!(t, Token::Concat | Token::Alt)
     }
@@ -54,24 +88,31 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
     }
 
     // 括弧用に (gid, mark) を持たせる。★構造体variantを明示
+    // gid が None ならキャプチャしないグループ
     #[derive(Clone, Debug)]
     enum Op {
-        LParen { gid: usize, mark: usize },
+        LParen { gid: Option<usize>, mark: usize },
         Bin(Token), // Concat / Alt
     }
 
-    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    // (トークン, パターン中の位置)
+    let mut out: Vec<(Token, usize)> = Vec::with_capacity(tokens.len());
     let mut operator_stack: Vec<(Op, usize)> = Vec::new(); // (op, pos)
 
     let mut last_was_operand = false; // 直前が「オペランド（または単項後置適用後）」か
     let mut last_was_quant = false; // 直前が量指定子（*,+,?）か
     let mut next_group_id: usize = 1; // 1-origin
 
-    for (i, t) in tokens.iter().cloned().enumerate() {
+    for (t, &i) in tokens.iter().cloned().zip(pos) {
         match t {
             // ===== オペランド =====
-            Token::Char(_) | Token::Dot | Token::Class { .. } => {
-                out.push(t);
+            Token::Char(_)
+            | Token::Dot
+            | Token::Class { .. }
+            | Token::Look(_)
+            | Token::NamedClass { .. }
+            | Token::CodepointClass(_) => {
+                out.push((t, i));
                 last_was_operand = true;
                 last_was_quant = false;
             }
@@ -81,18 +122,30 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
                 let gid = next_group_id;
                 next_group_id += 1;
                 // 開いた瞬間に CapStart を出力しておく
-                out.push(Token::CapStart(gid));
+                out.push((Token::CapStart(gid), i));
                 // この時点の out.len() を記録（中身の有無判定に使う）
                 let mark = out.len();
-                operator_stack.push((Op::LParen { gid, mark }, i));
+                operator_stack.push((
+                    Op::LParen {
+                        gid: Some(gid),
+                        mark,
+                    },
+                    i,
+                ));
                 // 直後に量指定子を許可するため operand=true にする
                 last_was_operand = true;
                 last_was_quant = false;
             }
+            Token::NonCapturing => {
+                let mark = out.len();
+                operator_stack.push((Op::LParen { gid: None, mark }, i));
+                last_was_operand = true;
+                last_was_quant = false;
+            }
             Token::RParen => {
                 // '(' まで演算子を出力
                 let (gid, mark) = loop {
-                    let Some((top, _pos_top)) = operator_stack.pop() else {
+                    let Some((top, pos_top)) = operator_stack.pop() else {
                         return Err(Error {
                             kind: ErrorKind::UnbalancedParen,
                             pos: i,
@@ -100,23 +153,46 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
                     };
                     match top {
                         Op::LParen { gid, mark } => break (gid, mark),
-                        Op::Bin(bop) => out.push(bop),
+                        Op::Bin(bop) => out.push((bop, pos_top)),
                     }
                 };
 
                 // CapStart 直後の out.len() を mark にしてある前提
                 let produced = out.len().saturating_sub(mark);
 
+                let Some(gid) = gid else {
+                    if produced == 0 {
+                        // (?:) 空: 何にも一致しないクラスの0回（= 空に一致する1オペランド）
+                        out.push((
+                            Token::Class {
+                                ranges: Vec::new(),
+                                neg: false,
+                            },
+                            i,
+                        ));
+                        out.push((
+                            Token::Repeat {
+                                min: 0,
+                                max: Some(0),
+                            },
+                            i,
+                        ));
+                    }
+                    // 非空なら中身がそのまま1オペランド
+                    last_was_operand = true;
+                    last_was_quant = false;
+                    continue;
+                };
                 if produced == 0 {
                     // () 空グループ: CapStart の直後に CapEnd を置き、Concat で結合
-                    out.push(Token::CapEnd(gid));
-                    out.push(Token::Concat);
+                    out.push((Token::CapEnd(gid), i));
+                    out.push((Token::Concat, i));
                 } else {
                     // (inner) 非空: (CapStart · inner) に Concat を1本
-                    out.push(Token::Concat);
+                    out.push((Token::Concat, i));
                     // さらに CapEnd を置いて (… · CapEnd) に Concat
-                    out.push(Token::CapEnd(gid));
-                    out.push(Token::Concat);
+                    out.push((Token::CapEnd(gid), i));
+                    out.push((Token::Concat, i));
                 }
 
                 last_was_operand = true; // () 全体で1オペランド
@@ -124,7 +200,7 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
             }
 
             // ===== 単項後置（量指定子） =====
-            Token::Star | Token::Plus | Token::Qmark => {
+            Token::Star | Token::Plus | Token::Qmark | Token::Repeat { .. } => {
                 if !last_was_operand {
                     // 例: "*a" / "|*" / "(*" など
                     return Err(Error {
@@ -139,7 +215,7 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
                         pos: i,
                     });
                 }
-                out.push(t);
+                out.push((t, i));
                 last_was_operand = true; // 「オペランド1個分」は維持
                 last_was_quant = true; // 直後の量指定子連鎖を禁止
             }
@@ -149,8 +225,8 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
                 while let Some((top, _)) = operator_stack.last() {
                     match top {
                         Op::Bin(op2) if is_bin_op(op2) && precedence(op2) >= precedence(&t) => {
-                            if let Some((Op::Bin(op2), _)) = operator_stack.pop() {
-                                out.push(op2);
+                            if let Some((Op::Bin(op2), p2)) = operator_stack.pop() {
+                                out.push((op2, p2));
                             }
                         }
                         _ => break,
@@ -178,11 +254,53 @@ pub(crate) fn to_postfix(tokens: &[Token]) -> Result<Vec<Token>, Error> {
                     pos,
                 });
             }
-            Op::Bin(b) => out.push(b),
+            Op::Bin(b) => out.push((b, pos)),
         }
     }
 
-    Ok(out)
+    Ok(out.into_iter().unzip())
+}
+
+/// 後置列からキャプチャの記録を取り除く（`RegexBuilder::captures(false)` 用）。
+/// グループは `to_postfix_with_pos` が次の形で出すので、それぞれ記録の無い形に戻す:
+///
+/// - `(inner)`: `CapStart inner · CapEnd ·` → `inner`
+/// - `()`: `CapStart CapEnd ·` → `(?:)` と同じ空の1オペランド
+pub(crate) fn drop_captures(postfix: &[Token], pos: &[usize]) -> (Vec<Token>, Vec<usize>) {
+    let mut out: Vec<(Token, usize)> = Vec::with_capacity(postfix.len());
+    let mut i = 0;
+    while i < postfix.len() {
+        match (&postfix[i], postfix.get(i + 1)) {
+            (Token::CapStart(_), Some(Token::CapEnd(_))) => {
+                out.push((
+                    Token::Class {
+                        ranges: Vec::new(),
+                        neg: false,
+                    },
+                    pos[i],
+                ));
+                out.push((
+                    Token::Repeat {
+                        min: 0,
+                        max: Some(0),
+                    },
+                    pos[i],
+                ));
+                i += 3;
+            }
+            (Token::CapStart(_), _) => i += 1,
+            (Token::CapEnd(_), _) => {
+                // 直前の Concat は CapStart と中身を繋いでいたもの
+                out.pop();
+                i += 2;
+            }
+            (t, _) => {
+                out.push((t.clone(), pos[i]));
+                i += 1;
+            }
+        }
+    }
+    out.into_iter().unzip()
 }
 
 #[cfg(test)]
@@ -219,15 +337,19 @@ mod parse_tests {
             .map(|t| match t {
                 Char(_) => "c",
                 Dot => ".",
-                Class { .. } => "[",
+                Class { .. } | NamedClass { .. } | CodepointClass(_) => "[",
                 Star => "*",
                 Plus => "+",
                 Qmark => "?",
+                Repeat { .. } => "{",
                 Concat => "·",
                 Alt => "|",
                 CapStart(_) => "S",
                 CapEnd(_) => "E",
-                LParen | RParen => unreachable!("Paren should not remain after RPN"),
+                Look(_) => "^",
+                LParen | NonCapturing | RParen => {
+                    unreachable!("Paren should not remain after RPN")
+                }
             })
             .collect::<Vec<_>>()
             .join(" ")
@@ -333,6 +455,16 @@ mod parse_tests {
         assert_eq!(s, "[ . ·");
     }
 
+    #[test]
+    fn rpn_non_capturing_group_has_no_cap_tokens() {
+        // (?:a|b)+c → a b | + c ·
+        let s = sym(&rpn("(?:a|b)+c"));
+        assert_eq!(s, "c c | + c ·");
+        // (?i:) 空 → 何にも一致しないクラスの0回
+        let s = sym(&rpn("a(?i:)"));
+        assert_eq!(s, "c [ { ·");
+    }
+
     // --- エラーパス ---------------------------------------------------------
 
     #[test]
@@ -382,4 +514,22 @@ mod parse_tests {
         let s = sym(&rpn("(ab)+"));
         assert_eq!(s, "S c c · · E · +");
     }
+
+    #[test]
+    fn drop_captures_matches_non_capturing_groups() {
+        let drop = |p: &str| {
+            let t = rpn(p);
+            let pos: Vec<usize> = (0..t.len()).collect();
+            sym(&drop_captures(&t, &pos).0)
+        };
+        for (cap, plain) in [
+            ("(ab)c", "(?:ab)c"),
+            ("a(b|c)*", "a(?:b|c)*"),
+            ("((a)b)+", "(?:(?:a)b)+"),
+            ("x()y", "x(?:)y"),
+            ("()*", "(?:)*"),
+        ] {
+            assert_eq!(drop(cap), sym(&rpn(plain)), "{cap}");
+        }
+    }
 }
@@ -0,0 +1,172 @@
+// pikevm.rs
+//! 優先順位つきスレッドリストによる NFA シミュレーション（`MatchKind::LeftmostFirst` 用）。
+//!
+//! スレッドは常に優先度順に並べ、同じ状態には最初に着いたスレッドだけを残す。
+//! 辺の並び順がそのまま優先度になる（Alt は左の枝、量指定子は「もう1回」が先）。
+//! unanchored 探索では各位置の開始スレッドを最下位に足すので、左から始まったスレッドほど優先される。
+use crate::nfa::Label;
+use crate::search::{Run, Scratch};
+use crate::token::LookCache;
+use crate::{GroupSlot, MatchError, Regex, Thread, is_char_start, start_caps, thread_start};
+
+impl Regex {
+    /// 位置 `at` から始めて、優先度最上位で受理したスレッドの (終端, キャプチャ) を返す。
+    /// `run.need_end` なら入力末尾での受理だけを数える（完全一致用）。
+    /// `run.unanchored` なら一致が見つかるまで各位置から始まるスレッドを足す。
+    pub(crate) fn try_run_first(
+        &self,
+        bytes: &[u8],
+        at: usize,
+        slots: usize,
+        run: &mut Run,
+    ) -> Result<Option<(usize, Vec<GroupSlot>)>, MatchError> {
+        let n = bytes.len();
+        let Scratch {
+            mut curr,
+            next: mut spare,
+            mut seen,
+        } = std::mem::take(&mut run.scratch);
+        curr.clear();
+        seen.clear();
+        seen.resize(self.states.len(), false);
+        // 位置が変わると自動で捨てられるので、1回の実行で使い回す
+        let mut looks = LookCache::default();
+
+        let start = Thread {
+            s: self.start,
+            caps: start_caps(at, slots),
+        };
+        self.closure_ordered(&mut curr, &mut seen, &mut looks, start, bytes, at);
+        self.check_thread_limit(&curr)?;
+
+        let mut last: Option<(usize, Vec<GroupSlot>)> = None;
+
+        let mut i = at;
+        loop {
+            // 受理: 最上位の受理スレッドを採り、それより下位のスレッドは以後進めない
+            // 開始位置の同じスレッドは並びの中で連続している（左から始まったものが前）
+            let mut cut = curr.len();
+            if (!run.need_end || i == n)
+                && let Some(k) = curr.iter().position(|t| t.s == self.accept)
+            {
+                last = Some((i, curr[k].caps.clone()));
+                cut = k;
+                if run.earliest {
+                    // この開始位置からの探索は終わり。より左から始まったスレッドだけ続ける
+                    let start = thread_start(&curr[k].caps);
+                    cut = curr
+                        .iter()
+                        .position(|t| thread_start(&t.caps) == start)
+                        .unwrap_or(k);
+                }
+            }
+            let live = &curr[..cut];
+            let adding = run.unanchored && last.is_none();
+
+            if i == n || (live.is_empty() && !adding) {
+                break;
+            }
+            if live.iter().all(|t| self.min_rest[t.s] > n - i)
+                && (!adding || self.min_rest[self.start] > n - i)
+            {
+                break;
+            }
+
+            run.step()?;
+            let b = bytes[i];
+            let mut next = std::mem::take(&mut spare);
+            next.clear();
+            seen.fill(false);
+            for thr in live {
+                for (lbl, tgt) in &self.states[thr.s].edges {
+                    if lbl.byte_set().is_some_and(|set| set.contains(b)) {
+                        let t = Thread {
+                            s: *tgt,
+                            caps: thr.caps.clone(),
+                        };
+                        self.closure_ordered(&mut next, &mut seen, &mut looks, t, bytes, i + 1);
+                    }
+                }
+            }
+            if adding && is_char_start(bytes, i + 1) {
+                let t = Thread {
+                    s: self.start,
+                    caps: start_caps(i + 1, slots),
+                };
+                self.closure_ordered(&mut next, &mut seen, &mut looks, t, bytes, i + 1);
+            }
+            self.check_thread_limit(&next)?;
+
+            if next.is_empty() && !adding {
+                spare = next;
+                break;
+            }
+
+            // 並びまで含めて変わらなければ、同じバイト集合が続く間は読み飛ばせる
+            if self.can_skip
+                && next == curr
+                && let Some(set) = self.uniform_byte_set(live)
+            {
+                let skip = set.prefix_len(&bytes[i + 1..]);
+                run.step_n(skip)?;
+                i += skip;
+            }
+
+            spare = std::mem::replace(&mut curr, next);
+            i += 1;
+        }
+
+        run.scratch = Scratch {
+            curr,
+            next: spare,
+            seen,
+        };
+        Ok(last)
+    }
+
+    /// `start` から ε・Cap・Look を深さ優先・辺の順に辿り、未訪問の状態を `list` の末尾に足す
+    fn closure_ordered(
+        &self,
+        list: &mut Vec<Thread>,
+        seen: &mut [bool],
+        looks: &mut LookCache,
+        start: Thread,
+        bytes: &[u8],
+        pos: usize,
+    ) {
+        let mut stack = vec![start];
+        while let Some(thr) = stack.pop() {
+            if seen[thr.s] {
+                continue;
+            }
+            seen[thr.s] = true;
+
+            // 先に書かれた辺を先に辿るため、逆順に積む
+            for (lbl, tgt) in self.states[thr.s].edges.iter().rev() {
+                match lbl {
+                    Label::Eps => stack.push(Thread {
+                        s: *tgt,
+                        caps: thr.caps.clone(),
+                    }),
+                    Label::CapBegin(g) | Label::CapEnd(g) => {
+                        let mut c = thr.caps.clone();
+                        if let Some(slot) = c.get_mut(*g) {
+                            if matches!(lbl, Label::CapBegin(_)) {
+                                slot.0 = Some(pos);
+                            } else {
+                                slot.1 = Some(pos);
+                            }
+                        }
+                        stack.push(Thread { s: *tgt, caps: c });
+                    }
+                    Label::Look(look) if looks.matches(*look, bytes, pos) => stack.push(Thread {
+                        s: *tgt,
+                        caps: thr.caps.clone(),
+                    }),
+                    _ => {} // 文字を読む遷移はここでは進まない
+                }
+            }
+            list.push(thr);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// progress.rs
+//! 一致しなかった入力について、トップレベルの `|` の枝ごとにどこまで読めたかを示す
+//! （`規則A|規則B|…` のどれが「惜しかった」かを調べる用）。
+//!
+//! 各枝の入口から `shortest_fail_position` と同じく読み進め、全スレッドが死んだ位置を取る。
+use crate::error::MatchError;
+use crate::span::Span;
+use crate::{Regex, search_failed};
+
+/// `Regex::alternative_progress` の要素: 枝1つがどこまで読めたか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlternativeProgress {
+    pattern_span: Span,
+    consumed: usize,
+    matched: bool,
+}
+
+impl AlternativeProgress {
+    /// 枝がパターン文字列のどこに書かれているか（`|` を含まない）
+    pub fn pattern_span(&self) -> Span {
+        self.pattern_span
+    }
+
+    /// 一致が不可能になるまでに読めたバイト数。最後まで読めたなら入力の長さ
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// この枝だけで入力全体に一致するか
+    pub fn is_match(&self) -> bool {
+        self.matched
+    }
+}
+
+impl Regex {
+    /// トップレベルの `|` の枝ごとに、入力をどこまで読めたかを左の枝から順に返す。
+    /// `|` が無ければパターン全体を1つの枝とする。
+    /// 完全一致しなかったとき、`consumed` の最も大きい枝が一致に最も近かった規則。
+    ///
+    /// `RegexBuilder::whole_word` の先頭の単語境界は枝の外にあるので、枝ごとには見ない。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let pat = r"\d+-\d+|[a-z]+@[a-z]+\.com";
+    /// let re = Regex::new(pat).unwrap();
+    /// let progress = re.alternative_progress("me@host.org");
+    /// let best = progress.iter().max_by_key(|p| p.consumed()).unwrap();
+    /// assert_eq!(best.pattern_span().slice(pat), r"[a-z]+@[a-z]+\.com");
+    /// assert_eq!((best.consumed(), best.is_match()), (8, false));
+    /// assert_eq!(progress[0].consumed(), 0);
+    /// ```
+    ///
+    /// # Panics
+    /// `RegexBuilder::thread_limit` などの上限を超えた場合。
+    pub fn alternative_progress(&self, hay: &str) -> Vec<AlternativeProgress> {
+        self.try_alternative_progress(hay)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `alternative_progress` の失敗を返す版
+    pub fn try_alternative_progress(
+        &self,
+        hay: &str,
+    ) -> Result<Vec<AlternativeProgress>, MatchError> {
+        self.check_haystack_len(hay)?;
+        self.branches
+            .iter()
+            .map(|&(pattern_span, entry)| {
+                let fail = self.fail_position_from(entry, hay.as_bytes())?;
+                Ok(AlternativeProgress {
+                    pattern_span,
+                    consumed: fail.unwrap_or(hay.len()),
+                    matched: fail.is_none(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use crate::{MatchError, Regex, RegexBuilder, Span};
+
+    // (枝の文字列, 読めたバイト数, 一致したか)
+    fn progress<'p>(pat: &'p str, hay: &str) -> Vec<(&'p str, usize, bool)> {
+        Regex::new(pat)
+            .unwrap()
+            .alternative_progress(hay)
+            .iter()
+            .map(|p| (p.pattern_span().slice(pat), p.consumed(), p.is_match()))
+            .collect()
+    }
+
+    #[test]
+    fn reports_each_top_level_branch() {
+        assert_eq!(
+            progress("abc|abd|x", "abz"),
+            vec![("abc", 2, false), ("abd", 2, false), ("x", 0, false)]
+        );
+        assert_eq!(
+            progress("ab|a+", "aa"),
+            vec![("ab", 1, false), ("a+", 2, true)]
+        );
+        // 入力が足りない枝は最後まで読める
+        assert_eq!(
+            progress("abc|b", "ab"),
+            vec![("abc", 2, false), ("b", 0, false)]
+        );
+    }
+
+    #[test]
+    fn nested_alternation_is_one_branch() {
+        assert_eq!(
+            progress("(a|b)c|(?:d|e)f|g", "bx"),
+            vec![
+                ("(a|b)c", 1, false),
+                ("(?:d|e)f", 0, false),
+                ("g", 0, false)
+            ]
+        );
+        assert_eq!(progress("(?i)ab|c", "AX")[0], ("ab", 1, false));
+        assert_eq!(
+            progress("(?:a|b)|c", "c"),
+            vec![("(?:a|b)", 0, false), ("c", 1, true)]
+        );
+    }
+
+    #[test]
+    fn without_alternation_the_whole_pattern_is_one_branch() {
+        let pat = r"\d\d\d\d-\d\d";
+        let re = Regex::new(pat).unwrap();
+        let got = re.alternative_progress("2024-x1");
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].pattern_span(), Span::new(0, pat.len()));
+        assert_eq!(
+            Some(got[0].consumed()),
+            re.shortest_fail_position("2024-x1")
+        );
+        // literal では | もただの文字
+        let lit = RegexBuilder::new("a|b").literal(true).build().unwrap();
+        assert_eq!(lit.alternative_progress("a|c")[0].consumed(), 2);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let re = RegexBuilder::new("a|b")
+            .max_haystack_len(2)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            re.try_alternative_progress("aaa"),
+            Err(MatchError::HaystackTooLong { len: 3, limit: 2 })
+        ));
+    }
+}
@@ -0,0 +1,495 @@
+// replace.rs
+//! 一致部分の置き換え。置換文字列中の `$1` / `${1}` / `${name}` はグループの中身に、`$$` は `$` になる。
+//! 置き換え方は `Replacer` で、文字列のほかキャプチャを受け取るクロージャも渡せる。
+use std::borrow::Cow;
+
+use crate::error::MatchError;
+use crate::search::Cursor;
+use crate::span::Span;
+use crate::{Captures, Regex, search_failed};
+
+/// 置換文字列を展開して `dst` に足す。無いグループ・参加しなかったグループは空文字列。
+/// 形になっていない `$`（`$x` や閉じていない `${`）はそのまま残す。名前は `${name}` の形でだけ読む。
+pub(crate) fn expand(caps: &Captures<'_>, template: &str, dst: &mut String) {
+    let mut rest = template;
+    while let Some(k) = rest.find('$') {
+        dst.push_str(&rest[..k]);
+        rest = &rest[k..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            dst.push('$');
+            rest = after;
+            continue;
+        }
+        match group_ref(&rest[1..]) {
+            Some((group, len)) => {
+                let m = match group {
+                    GroupRef::Index(i) => caps.get(i),
+                    GroupRef::Name(name) => caps.name(name),
+                };
+                if let Some(m) = m {
+                    dst.push_str(m.as_str());
+                }
+                rest = &rest[1 + len..];
+            }
+            None => {
+                dst.push('$');
+                rest = &rest[1..];
+            }
+        }
+    }
+    dst.push_str(rest);
+}
+
+/// 一致1つ分の置き換え後の文字列の作り方。
+/// `&str` / `String` は `$1` などを展開するテンプレート、
+/// `FnMut(&Captures) -> T`（`T: AsRef<str>`）は返した文字列をそのまま使う。
+/// `$` をそのまま入れたいときは `NoExpand`。
+///
+/// ```
+/// use my_regex::{Captures, Regex};
+/// let re = Regex::new(r"([a-z]+)=([0-9]+)").unwrap();
+/// let doubled = re.replace_all("a=1 b=21", |c: &Captures| {
+///     format!("{}={}", &c[1], c[2].parse::<u32>().unwrap() * 2)
+/// });
+/// assert_eq!(doubled, "a=2 b=42");
+/// ```
+pub trait Replacer {
+    /// 一致 `caps` の置き換え後の文字列を `dst` に足す
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String);
+
+    /// どの一致も同じ文字列に置き換えるならその文字列。
+    /// Some なら置き換え側はキャプチャを取らずに探す
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        None
+    }
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        expand(caps, self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        (!self.contains('$')).then_some(Cow::Borrowed(*self))
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        expand(caps, self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        (!self.contains('$')).then_some(Cow::Borrowed(self.as_str()))
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        expand(caps, self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        (!self.contains('$')).then_some(Cow::Borrowed(self.as_str()))
+    }
+}
+
+/// 展開せずにそのまま入れる置換文字列（`$` を含む文字列を入れるとき用）。
+///
+/// ```
+/// use my_regex::{NoExpand, Regex};
+/// let re = Regex::new("[0-9]+").unwrap();
+/// assert_eq!(re.replace_all("a1 b2", NoExpand("$1")), "a$1 b$1");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoExpand<'s>(pub &'s str);
+
+impl Replacer for NoExpand<'_> {
+    fn replace_append(&mut self, _: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self.0);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.0))
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+// 置換文字列の中のグループの参照
+enum GroupRef<'a> {
+    Index(usize),
+    Name(&'a str),
+}
+
+// `$` の直後を読み、(参照, 読んだバイト数)。数字は貪欲に読む（`$1a` は `$1` + `a`）。
+// `{}` の中は数字ならグループ番号、英数字と `_` ならグループ名
+fn group_ref(s: &str) -> Option<(GroupRef<'_>, usize)> {
+    let Some(inner) = s.strip_prefix('{') else {
+        let n = s.bytes().take_while(u8::is_ascii_digit).count();
+        return Some((GroupRef::Index(s[..n].parse().ok()?), n));
+    };
+    let body = &inner[..inner.find('}')?];
+    let len = body.len() + 2;
+    if !body.is_empty() && body.bytes().all(|b| b.is_ascii_digit()) {
+        Some((GroupRef::Index(body.parse().ok()?), len))
+    } else if !body.is_empty() && body.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+        Some((GroupRef::Name(body), len))
+    } else {
+        None
+    }
+}
+
+impl Regex {
+    /// 最も左の一致1つだけを置き換える。一致しなければ入力をそのまま借用して返す。
+    /// 置き換えた範囲も要るなら `replace_first`。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// assert_eq!(re.replace("a1b22", "#"), "a#b22");
+    /// ```
+    ///
+    /// # Panics
+    /// `try_replace` が `MatchError` を返す場合。
+    pub fn replace<'h, R: Replacer>(&self, hay: &'h str, rep: R) -> Cow<'h, str> {
+        self.try_replace(hay, rep)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `replace` の失敗を返す版
+    pub fn try_replace<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        rep: R,
+    ) -> Result<Cow<'h, str>, MatchError> {
+        Ok(self.try_replace_first(hay, rep)?.0)
+    }
+
+    /// 重ならない一致（`find_iter` と同じもの）をすべて置き換える。
+    /// 一致が1つも無ければ入力をそのまま借用して返す。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"(\w+)=(\w+)").unwrap();
+    /// assert_eq!(re.replace_all("a=1, b=2", "$2=$1"), "1=a, 2=b");
+    /// ```
+    ///
+    /// # Panics
+    /// `try_replace_all` が `MatchError` を返す場合。
+    pub fn replace_all<'h, R: Replacer>(&self, hay: &'h str, rep: R) -> Cow<'h, str> {
+        self.try_replace_all(hay, rep)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `replace_all` の失敗を返す版
+    pub fn try_replace_all<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        rep: R,
+    ) -> Result<Cow<'h, str>, MatchError> {
+        Ok(self.try_rebuild(hay, usize::MAX, rep)?.0)
+    }
+
+    /// `replace_all` の結果を、新しい文字列を作らずに `dst` の末尾に足す。
+    /// 同じバッファを使い回して多くの入力を書き換えるループ向け。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let mut out = String::new();
+    /// for line in ["a1", "b", "c22"] {
+    ///     re.replace_append(line, "#", &mut out);
+    ///     out.push('\n');
+    /// }
+    /// assert_eq!(out, "a#\nb\nc#\n");
+    /// ```
+    ///
+    /// # Panics
+    /// `try_replace_append` が `MatchError` を返す場合。
+    pub fn replace_append<R: Replacer>(&self, hay: &str, rep: R, dst: &mut String) {
+        self.try_replace_append(hay, rep, dst)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `replace_append` の失敗を返す版。失敗したときの `dst` には途中までの結果が残る
+    pub fn try_replace_append<R: Replacer>(
+        &self,
+        hay: &str,
+        rep: R,
+        dst: &mut String,
+    ) -> Result<(), MatchError> {
+        if self.try_rebuild_into(hay, usize::MAX, rep, dst)?.is_none() {
+            dst.push_str(hay);
+        }
+        Ok(())
+    }
+
+    /// 重ならない一致を左から `limit` 個まで置き換える。`limit` が 0 なら `replace_all` と同じ。
+    /// 一致が1つも無ければ入力をそのまま借用して返す。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// assert_eq!(re.replacen("1 2 3 4", 2, "n"), "n n 3 4");
+    /// assert_eq!(re.replacen("1 2 3 4", 0, "n"), "n n n n");
+    /// ```
+    ///
+    /// # Panics
+    /// `try_replacen` が `MatchError` を返す場合。
+    pub fn replacen<'h, R: Replacer>(&self, hay: &'h str, limit: usize, rep: R) -> Cow<'h, str> {
+        self.try_replacen(hay, limit, rep)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `replacen` の失敗を返す版
+    pub fn try_replacen<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        limit: usize,
+        rep: R,
+    ) -> Result<Cow<'h, str>, MatchError> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(self.try_rebuild(hay, limit, rep)?.0)
+    }
+
+    /// 最も左の一致1つだけを置き換え、置き換えた元の範囲も返す。
+    /// 一致しなければ入力をそのまま借用して返す。
+    ///
+    /// ```
+    /// use my_regex::{Regex, Span};
+    /// let re = Regex::new(r"(\w+)@(\w+)").unwrap();
+    /// let (out, span) = re.replace_first("to: me@host, you@there", "$2!$1");
+    /// assert_eq!(out, "to: host!me, you@there");
+    /// assert_eq!(span, Some(Span::new(4, 11)));
+    /// ```
+    ///
+    /// # Panics
+    /// `try_replace_first` が `MatchError` を返す場合。
+    pub fn replace_first<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        rep: R,
+    ) -> (Cow<'h, str>, Option<Span>) {
+        self.try_replace_first(hay, rep)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `replace_first` の失敗を返す版
+    pub fn try_replace_first<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        rep: R,
+    ) -> Result<(Cow<'h, str>, Option<Span>), MatchError> {
+        self.try_rebuild(hay, 1, rep)
+    }
+
+    // 左から `limit` 個までの重ならない一致（`find_iter` と同じもの）を置き換える。
+    // (結果, 最初に置き換えた範囲)。1つも無ければ入力をそのまま借用して返す
+    fn try_rebuild<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        limit: usize,
+        rep: R,
+    ) -> Result<(Cow<'h, str>, Option<Span>), MatchError> {
+        // 一致が無ければ確保しない（String::new は確保しない）
+        let mut dst = String::new();
+        Ok(match self.try_rebuild_into(hay, limit, rep, &mut dst)? {
+            Some(first) => (Cow::Owned(dst), Some(first)),
+            None => (Cow::Borrowed(hay), None),
+        })
+    }
+
+    // `try_rebuild` の本体。置き換えた結果を `dst` に足し、最初に置き換えた範囲を返す。
+    // 1つも一致しなければ `dst` には何も足さない
+    fn try_rebuild_into<R: Replacer>(
+        &self,
+        hay: &str,
+        limit: usize,
+        mut rep: R,
+        dst: &mut String,
+    ) -> Result<Option<Span>, MatchError> {
+        // 固定の文字列ならキャプチャを取らない
+        let literal = rep.no_expansion().map(Cow::into_owned);
+        let mut first = None;
+        // ここまでは dst に写し終えた
+        let mut copied = 0;
+        let mut count = 0;
+        // 一致の並びは find_iter と同じ
+        let mut cursor = Cursor::new(Span::new(0, hay.len()));
+        while count < limit {
+            let found = cursor.try_next(hay, |opts| {
+                Ok(if literal.is_some() {
+                    self.try_search(hay, opts)?
+                        .map(|m| (m.span(), (m.span(), None)))
+                } else {
+                    self.try_search_captures(hay, opts)?
+                        .map(|c| (c.whole(), (c.whole(), Some(c))))
+                })
+            })?;
+            let Some((span, caps)) = found else {
+                break;
+            };
+            if first.is_none() {
+                dst.reserve(hay.len());
+            }
+            dst.push_str(&hay[copied..span.start]);
+            match (&literal, &caps) {
+                (Some(lit), _) => dst.push_str(lit),
+                (None, Some(caps)) => rep.replace_append(caps, dst),
+                (None, None) => {}
+            }
+            first.get_or_insert(span);
+            copied = span.end;
+            count += 1;
+        }
+        if first.is_some() {
+            dst.push_str(&hay[copied..]);
+        }
+        Ok(first)
+    }
+}
+
+#[cfg(test)]
+mod replace_tests {
+    use super::expand;
+    use crate::{Captures, NoExpand, Regex, Replacer, Span};
+    use std::borrow::Cow;
+
+    fn expanded(pat: &str, hay: &str, template: &str) -> String {
+        let caps = Regex::new(pat).unwrap().captures(hay).unwrap();
+        let mut out = String::new();
+        expand(&caps, template, &mut out);
+        out
+    }
+
+    #[test]
+    fn template_references() {
+        let p = r"(a)(b)?-(c)";
+        assert_eq!(expanded(p, "a-c", "[$1|$2|$3]"), "[a||c]");
+        assert_eq!(expanded(p, "ab-c", "${2}x$2x"), "bxbx");
+        assert_eq!(expanded(p, "ab-c", "$0 $$1 $9"), "ab-c $1 ");
+        // 形になっていない $ は残す
+        assert_eq!(expanded(p, "ab-c", "$x ${1 ${} $"), "$x ${1 ${} $");
+        // 数字は貪欲に読む
+        assert_eq!(expanded(p, "ab-c", "$1a $10"), "aa ");
+    }
+
+    #[test]
+    fn template_names() {
+        let p = r"(?<y>\d+)-(?<m>\d+)(?<rest>x)?";
+        assert_eq!(expanded(p, "2024-05", "${m}/${y}"), "05/2024");
+        // 無い名前・参加しなかったグループは空、括弧の無い名前は読まない
+        assert_eq!(expanded(p, "2024-05", "[${nope}|${rest}|$m]"), "[||$m]");
+        assert_eq!(expanded(p, "2024-05", "$${y} ${y-m}"), "${y} ${y-m}");
+        let re = Regex::new(p).unwrap();
+        assert_eq!(re.replace_all("1-2 3-4", "${m}.${y}"), "2.1 4.3");
+    }
+
+    #[test]
+    fn replace_all_matches() {
+        let re = Regex::new("[0-9]+").unwrap();
+        assert_eq!(re.replace_all("v1.22.3", "<$0>"), "v<1>.<22>.<3>");
+        assert!(matches!(re.replace_all("none", "x"), Cow::Borrowed("none")));
+        assert_eq!(re.replace("v1.22.3", "#"), "v#.22.3");
+        // 参加しなかったグループは空
+        let re = Regex::new("(a)|(b)").unwrap();
+        assert_eq!(re.replace_all("abc", "[$1$2]"), "[a][b]c");
+    }
+
+    #[test]
+    fn replacers() {
+        let re = Regex::new("([a-z])([0-9])").unwrap();
+        let template = String::from("$2$1");
+        assert_eq!(re.replace_all("a1 b2", &template), "1a 2b");
+        assert_eq!(re.replace_all("a1 b2", template), "1a 2b");
+        // クロージャは呼ばれるたびに状態を持ち越せる
+        let mut n = 0;
+        let out = re.replace_all("a1 b2 c3", |c: &Captures| {
+            n += 1;
+            format!("{n}{}", &c[1])
+        });
+        assert_eq!(out, "1a 2b 3c");
+        // クロージャの結果は展開しない
+        assert_eq!(re.replace("a1", |_: &Captures| "$1"), "$1");
+        let (out, span) = re.replace_first("xa1", |c: &Captures| c[0].to_uppercase());
+        assert_eq!((out.as_ref(), span), ("xA1", Some(Span::new(1, 3))));
+    }
+
+    #[test]
+    fn no_expand_inserts_text_as_is() {
+        let re = Regex::new("(x)").unwrap();
+        assert_eq!(re.replace_all("axbx", NoExpand("$1$$")), "a$1$$b$1$$");
+        let (out, span) = re.replace_first("ax", NoExpand("${1}"));
+        assert_eq!((out.as_ref(), span), ("a${1}", Some(Span::new(1, 2))));
+        // $ の無いテンプレートも固定の文字列として扱う
+        assert_eq!("-".no_expansion(), Some(Cow::Borrowed("-")));
+        assert_eq!("$1".no_expansion(), None);
+        assert_eq!(re.replace_all("axbx", String::from("-")), "a-b-");
+    }
+
+    #[test]
+    fn replace_append_keeps_what_dst_had() {
+        let re = Regex::new("([a-z])([0-9])").unwrap();
+        let mut out = String::from(">");
+        re.replace_append("a1 b2", "$2$1", &mut out);
+        re.replace_append("none", "$2$1", &mut out);
+        re.replace_append("", "-", &mut out);
+        re.replace_append("c3", |c: &Captures| c[1].to_uppercase(), &mut out);
+        assert_eq!(out, ">1a 2bnoneC");
+        // 結果は replace_all と同じ
+        let mut out = String::new();
+        let empty = Regex::new("x*").unwrap();
+        empty.replace_append("axxé", NoExpand("-"), &mut out);
+        assert_eq!(out, empty.replace_all("axxé", "-"));
+    }
+
+    #[test]
+    fn replacen_stops_after_limit() {
+        let re = Regex::new("(a)").unwrap();
+        assert_eq!(re.replacen("aaaa", 1, "[$1]"), "[a]aaa");
+        assert_eq!(re.replacen("aaaa", 3, "-"), "---a");
+        assert_eq!(re.replacen("aaaa", 9, "-"), "----");
+        assert_eq!(re.replacen("aaaa", 0, "-"), re.replace_all("aaaa", "-"));
+        assert!(matches!(re.replacen("bbb", 2, "-"), Cow::Borrowed("bbb")));
+        // 空一致も1個と数える
+        let empty = Regex::new("x*").unwrap();
+        assert_eq!(empty.replacen("axxb", 2, "-"), "-a-b");
+    }
+
+    #[test]
+    fn replace_all_empty_matches_step_by_char() {
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(re.replace_all("axxé", "-"), "-a--é-");
+        assert_eq!(re.replace_all("", "-"), "-");
+        // 結果は find_iter の一致と同じ位置
+        let hay = "éxé";
+        let n = re.find_iter(hay).count();
+        assert_eq!(re.replace_all(hay, "-").matches('-').count(), n);
+    }
+
+    #[test]
+    fn replaces_only_the_leftmost_match() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let (out, span) = re.replace_first("v1.22.3", "<$0>");
+        assert_eq!(out, "v<1>.22.3");
+        assert_eq!(span, Some(Span::new(1, 2)));
+
+        let (out, span) = re.replace_first("none", "x");
+        assert!(matches!(out, Cow::Borrowed("none")));
+        assert_eq!(span, None);
+
+        // 空一致でも位置は報告する
+        let re = Regex::new("x*").unwrap();
+        let (out, span) = re.replace_first("ab", "-");
+        assert_eq!((out.as_ref(), span), ("-ab", Some(Span::new(0, 0))));
+    }
+}
@@ -0,0 +1,144 @@
+// report.rs
+//! コンパイル済みパターンの大きさ・複雑さのまとめ（CI で規則の変更に上限を設ける用）。
+use crate::Regex;
+#[cfg(feature = "dfa")]
+use crate::dfa::Dfa;
+use crate::strategy::Engine;
+
+/// `Regex::report` の結果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternReport {
+    /// NFA の状態数
+    pub states: usize,
+    /// NFA の遷移数（ε や Look も数える）
+    pub edges: usize,
+    /// パターンに書かれたキャプチャグループの数（グループ 0 は数えない。`captures(false)` でも数える）
+    pub groups: usize,
+    /// `*` / `+` / `{m,}` のような上限の無い繰り返しを含むか
+    pub unbounded_repetition: bool,
+    /// NFA を回さずに済ませる照合方法（`"literal"` / `"folded-literal"`）。選ばれなければ None
+    pub prefilter: Option<&'static str>,
+    /// このパターンを扱えるエンジン（`"nfa"` / `"literal"` / `"dfa"`）。
+    /// 有効な feature で使えるものだけを並べる
+    pub engines: Vec<&'static str>,
+}
+
+impl Regex {
+    /// パターンの大きさと、使えるエンジンのまとめ。
+    /// feature `dfa` があれば DFA を実際に作ってみるので、状態の多いパターンでは時間がかかる。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let r = Regex::new(r"(\w+)=(\d{1,3})").unwrap().report();
+    /// assert_eq!(r.groups, 2);
+    /// assert!(r.unbounded_repetition);
+    /// assert!(r.engines.contains(&"nfa"));
+    /// assert!(!Regex::new("a{2,5}").unwrap().report().unbounded_repetition);
+    /// ```
+    pub fn report(&self) -> PatternReport {
+        let prefilter = match self.strategy.engine {
+            Engine::Literal(_) => Some("literal"),
+            Engine::FoldedLiteral(_) => Some("folded-literal"),
+            Engine::Nfa => None,
+        };
+        let mut engines = vec!["nfa"];
+        if prefilter.is_some() {
+            engines.push("literal");
+        }
+        #[cfg(feature = "dfa")]
+        if Dfa::from_regex(self).is_ok() {
+            engines.push("dfa");
+        }
+        PatternReport {
+            states: self.states.len(),
+            edges: self.states.iter().map(|st| st.edges.len()).sum(),
+            groups: self.group_info.pattern_spans().len() - 1,
+            unbounded_repetition: self.has_cycle(),
+            prefilter,
+            engines,
+        }
+    }
+
+    // 上限の無い繰り返しは NFA 上の閉路になる（{m,n} は回数分複製するので閉路を作らない）
+    fn has_cycle(&self) -> bool {
+        // 0: 未訪問, 1: 探索中, 2: 済み
+        let mut color = vec![0u8; self.states.len()];
+        for root in 0..self.states.len() {
+            if color[root] != 0 {
+                continue;
+            }
+            // (状態, 次に見る遷移)
+            let mut stack = vec![(root, 0)];
+            color[root] = 1;
+            while let Some((s, k)) = stack.last_mut() {
+                let Some(&(_, to)) = self.states[*s].edges.get(*k) else {
+                    color[*s] = 2;
+                    stack.pop();
+                    continue;
+                };
+                *k += 1;
+                match color[to] {
+                    0 => {
+                        color[to] = 1;
+                        stack.push((to, 0));
+                    }
+                    1 => return true,
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod report_tests {
+    use crate::{Regex, RegexBuilder};
+
+    #[test]
+    fn sizes_and_groups() {
+        let r = Regex::new("(a)(?:b)(?<c>c)").unwrap().report();
+        assert_eq!(r.groups, 2);
+        assert!(r.states > 0 && r.edges >= r.states - 1);
+        // グループを記録しなくてもパターン上の数は同じ
+        let no_caps = RegexBuilder::new("(a)(b)").captures(false).build().unwrap();
+        assert_eq!(no_caps.report().groups, 2);
+        assert!(
+            Regex::new("a|bc").unwrap().report().states
+                < Regex::new("a{5}").unwrap().report().states
+        );
+    }
+
+    #[test]
+    fn unbounded_repetition() {
+        for (pat, want) in [
+            ("abc", false),
+            ("a?b{2,4}", false),
+            ("a*", true),
+            ("(ab)+c", true),
+            ("a{3,}", true),
+            ("x|y.*", true),
+        ] {
+            assert_eq!(
+                Regex::new(pat).unwrap().report().unbounded_repetition,
+                want,
+                "{pat}"
+            );
+        }
+    }
+
+    #[test]
+    fn engines() {
+        let r = Regex::new("abc").unwrap().report();
+        assert_eq!(r.prefilter, Some("literal"));
+        assert!(r.engines.contains(&"literal"));
+        let r = Regex::new("(?i)ab").unwrap().report();
+        assert_eq!(r.prefilter, Some("folded-literal"));
+        let r = Regex::new("a+").unwrap().report();
+        assert_eq!(r.prefilter, None);
+        assert_eq!(r.engines.contains(&"dfa"), cfg!(feature = "dfa"));
+        // 単語境界は DFA にできない
+        let word = RegexBuilder::new("a").whole_word(true).build().unwrap();
+        assert!(!word.report().engines.contains(&"dfa"));
+    }
+}
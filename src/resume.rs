@@ -0,0 +1,150 @@
+// resume.rs
+//! 1回の呼び出しで読むバイト数を区切って、少しずつ進める探索（非同期ランタイムやゲームループ用）。
+//!
+//! 状態は `SearchState` が持つので、呼び出しの間にスレッドを塞がない。
+//! 進め方は `match_sink` と同じ `Stepper` で、一致は `find_iter` と同じ順に出てくる。
+use std::task::Poll;
+
+use crate::error::MatchError;
+use crate::stream::Stepper;
+use crate::{Match, Regex, search_failed};
+
+/// `Regex::start_search` が返す、途中まで進めた探索
+///
+/// ```
+/// use std::task::Poll;
+/// use my_regex::Regex;
+/// let re = Regex::new("[0-9]+").unwrap();
+/// let mut state = re.start_search("a1b22");
+/// let mut found = Vec::new();
+/// loop {
+///     match state.resume(2) {
+///         Poll::Ready(Some(m)) => found.push(m.as_str()),
+///         Poll::Ready(None) => break,
+///         Poll::Pending => {} // ここで他の仕事をする
+///     }
+/// }
+/// assert_eq!(found, vec!["1", "22"]);
+/// ```
+#[derive(Clone)]
+pub struct SearchState<'r, 'h> {
+    re: &'r Regex,
+    hay: &'h str,
+    stepper: Stepper,
+}
+
+impl<'r, 'h> SearchState<'r, 'h> {
+    /// 最大 `budget` 位置ぶん進める。一致が確定したら `Ready(Some)`、入力を読み切ったら
+    /// `Ready(None)`、どちらでもなく予算を使い切ったら `Pending`。
+    /// `Ready(Some)` の後に呼べば次の一致を探す。
+    ///
+    /// 一致の終端が確定するまでの先読みも予算に含む。一致を報告すると終端まで戻るので、
+    /// 同じバイトを2回読むことがある。
+    ///
+    /// # Panics
+    /// `try_resume` が `MatchError` を返す場合。
+    pub fn resume(&mut self, budget: usize) -> Poll<Option<Match<'h>>> {
+        match self.try_resume(budget) {
+            Poll::Ready(res) => Poll::Ready(res.unwrap_or_else(|e| search_failed(e))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// `resume` の失敗を返す版（スレッド数が `thread_limit` を超えた場合）
+    pub fn try_resume(&mut self, budget: usize) -> Poll<Result<Option<Match<'h>>, MatchError>> {
+        let bytes = self.hay.as_bytes();
+        for _ in 0..budget {
+            let at = self.stepper.at;
+            if at > bytes.len() {
+                return Poll::Ready(Ok(None));
+            }
+            let prev = at.checked_sub(1).map(|i| bytes[i]);
+            match self.stepper.step(self.re, prev, bytes.get(at).copied()) {
+                Ok(Some(m)) => return Poll::Ready(Ok(Some(Match::new(self.hay, m.start, m.end)))),
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        if self.stepper.at > bytes.len() {
+            Poll::Ready(Ok(None))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// 次に調べる入力中の位置（進み具合の表示用）
+    pub fn position(&self) -> usize {
+        self.stepper.at
+    }
+}
+
+impl Regex {
+    /// `hay` の探索を始める。この時点では何も読まず、`SearchState::resume` で進める。
+    /// 一致の選び方は `match_sink` と同じく、`match_kind` によらず最も左で最長のもの。
+    pub fn start_search<'r, 'h>(&'r self, hay: &'h str) -> SearchState<'r, 'h> {
+        SearchState {
+            re: self,
+            hay,
+            stepper: Stepper::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod resume_tests {
+    use std::task::Poll;
+
+    use crate::{Match, MatchError, Regex, RegexBuilder};
+
+    // 予算 budget ずつ最後まで進め、一致と Pending の回数を返す
+    fn drive<'h>(re: &Regex, hay: &'h str, budget: usize) -> (Vec<Match<'h>>, usize) {
+        let mut state = re.start_search(hay);
+        let (mut found, mut pending) = (Vec::new(), 0);
+        loop {
+            match state.resume(budget) {
+                Poll::Ready(Some(m)) => found.push(m),
+                Poll::Ready(None) => return (found, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_with_find_iter_for_any_budget() {
+        for (pat, hay) in [("[0-9]+", "a1b22c333"), ("x*", "axxb"), ("é|b", "aéb")] {
+            let re = Regex::new(pat).unwrap();
+            let want: Vec<Match> = re.find_iter(hay).collect();
+            for budget in [1, 2, 5, 100] {
+                assert_eq!(drive(&re, hay, budget).0, want, "{pat} by {budget}");
+            }
+        }
+    }
+
+    #[test]
+    fn work_per_call_is_bounded() {
+        let re = Regex::new("z").unwrap();
+        let hay = "a".repeat(100);
+        let mut state = re.start_search(&hay);
+        assert_eq!(state.resume(10), Poll::Pending);
+        assert_eq!(state.position(), 10);
+        assert_eq!(state.resume(0), Poll::Pending);
+        assert_eq!(state.position(), 10);
+        // 100 バイトと末尾の位置で 101 回
+        assert_eq!(drive(&re, &hay, 10), (vec![], 10));
+        assert_eq!(state.resume(91), Poll::Ready(None));
+        assert_eq!(state.resume(1), Poll::Ready(None));
+    }
+
+    #[test]
+    fn thread_limit_is_reported() {
+        let re = RegexBuilder::new("(a|aa)*b")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        let mut state = re.start_search("aaaa");
+        assert!(matches!(
+            state.try_resume(10),
+            Poll::Ready(Err(MatchError::TooManyThreads { limit: 1 }))
+        ));
+    }
+}
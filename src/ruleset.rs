@@ -0,0 +1,397 @@
+// ruleset.rs
+//! 名前付きパターンの集まり。走らせたまま中身を丸ごと差し替えられる。
+//!
+//! 照合中の呼び出しは差し替え前の `Rules` を `Arc` で握ったまま最後まで使うので、
+//! 差し替えの途中の状態（一部だけ新しい規則）が見えることはない。
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+
+use crate::error::{Error, MatchError};
+use crate::replace::Replacer;
+use crate::search::Cursor;
+use crate::span::Span;
+use crate::{Captures, Regex, search_failed};
+
+/// ある時点の規則一覧（名前と、コンパイル済みのパターン）。入力順を保つ。
+#[derive(Clone)]
+pub struct Rules {
+    names: Vec<String>,
+    regexes: Vec<Regex>,
+}
+
+impl Rules {
+    /// (名前, パターン) をまとめてコンパイルする。失敗は `Regex::try_new_many` と同じ形で返す。
+    pub fn new<I, N, P>(rules: I) -> Result<Self, Vec<(usize, Error)>>
+    where
+        I: IntoIterator<Item = (N, P)>,
+        N: Into<String>,
+        P: AsRef<str>,
+    {
+        let (names, pats): (Vec<String>, Vec<P>) =
+            rules.into_iter().map(|(n, p)| (n.into(), p)).unzip();
+        let regexes = Regex::try_new_many(pats)?;
+        Ok(Self { names, regexes })
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// 規則の名前（入力順、重複あり）
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// 名前の規則のパターン（同名なら先のもの）
+    pub fn get(&self, name: &str) -> Option<&Regex> {
+        let k = self.names.iter().position(|n| n == name)?;
+        Some(&self.regexes[k])
+    }
+
+    /// 入力に一致する規則の名前すべて（入力順）
+    pub fn matches(&self, hay: &str) -> Vec<&str> {
+        self.matches_bytes(hay.as_bytes())
+    }
+
+    /// 入力に一致する最初の規則の名前。振り分け先を決める用途
+    pub fn route(&self, hay: &str) -> Option<&str> {
+        self.route_bytes(hay.as_bytes())
+    }
+
+    /// `matches` の、UTF-8 とは限らないバイト列版
+    pub fn matches_bytes(&self, hay: &[u8]) -> Vec<&str> {
+        self.set_matches_bytes(hay)
+            .iter()
+            .map(|k| self.names[k].as_str())
+            .collect()
+    }
+
+    /// 入力に一致する規則を、名前ではなく入力順の番号で返す（同名の規則も区別できる）
+    ///
+    /// ```
+    /// use my_regex::Rules;
+    /// let rules = Rules::new([("num", "[0-9]+"), ("word", r"\w+"), ("hex", "0x.*")]).unwrap();
+    /// let m = rules.set_matches("42");
+    /// assert!(m.matched(0) && m.matched(1) && !m.matched(2));
+    /// assert_eq!(m.iter().collect::<Vec<_>>(), vec![0, 1]);
+    /// assert_eq!(m.len(), 3);
+    /// ```
+    pub fn set_matches(&self, hay: &str) -> SetMatches {
+        self.set_matches_bytes(hay.as_bytes())
+    }
+
+    /// `set_matches` の、UTF-8 とは限らないバイト列版
+    pub fn set_matches_bytes(&self, hay: &[u8]) -> SetMatches {
+        SetMatches {
+            matched: self
+                .regexes
+                .iter()
+                .map(|re| re.is_match_bytes(hay))
+                .collect(),
+        }
+    }
+
+    /// `route` の、UTF-8 とは限らないバイト列版
+    pub fn route_bytes(&self, hay: &[u8]) -> Option<&str> {
+        self.names
+            .iter()
+            .zip(&self.regexes)
+            .find(|(_, re)| re.is_match_bytes(hay))
+            .map(|(n, _)| n.as_str())
+    }
+
+    /// 入力中の一致を左から重ならないように取り、一致した規則の置き換え方で置き換える。
+    /// 各所ではどれかの規則の一致のうち最も左から始まるものを採り、同じ位置なら入力順で先の規則を採る。
+    /// `rep` は規則の名前からその規則の置き換え方（`Regex::replace_all` と同じ `Replacer`）を返す。
+    /// 一致が1つも無ければ入力をそのまま借用して返す。
+    ///
+    /// ```
+    /// use my_regex::Rules;
+    /// let rules = Rules::new([("num", "[0-9]+"), ("key", "[a-z]+")]).unwrap();
+    /// let out = rules.replace_all("id=42", |name: &str| match name {
+    ///     "num" => "<$0>",
+    ///     _ => "${0}_",
+    /// });
+    /// assert_eq!(out, "id_=<42>");
+    /// ```
+    ///
+    /// # Panics
+    /// `try_replace_all` が `MatchError` を返す場合。
+    pub fn replace_all<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        rep: impl FnMut(&str) -> R,
+    ) -> Cow<'h, str> {
+        self.try_replace_all(hay, rep)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `replace_all` の失敗を返す版
+    pub fn try_replace_all<'h, R: Replacer>(
+        &self,
+        hay: &'h str,
+        mut rep: impl FnMut(&str) -> R,
+    ) -> Result<Cow<'h, str>, MatchError> {
+        // 規則ごとの、まだ使っていない次の一致（None はまだ探していない）。
+        // 探した位置より後ろから始まる一致は、もっと後ろから探しても同じものが見つかる
+        let mut pending: Vec<Option<Option<Captures<'h>>>> = vec![None; self.len()];
+        let mut cursor = Cursor::new(Span::new(0, hay.len()));
+        let mut out: Option<String> = None;
+        // ここまでは out に写し終えた
+        let mut copied = 0;
+        loop {
+            let found = cursor.try_next(hay, |opts| {
+                let from = opts.span.map_or(0, |s| s.start);
+                let mut best: Option<(usize, Span)> = None;
+                for (k, re) in self.regexes.iter().enumerate() {
+                    let stale = match &pending[k] {
+                        None => true,
+                        Some(Some(caps)) => caps.whole().start < from,
+                        Some(None) => false,
+                    };
+                    if stale {
+                        pending[k] = Some(re.try_search_captures(hay, opts)?);
+                    }
+                    if let Some(Some(caps)) = &pending[k]
+                        && best.is_none_or(|(_, b)| caps.whole().start < b.start)
+                    {
+                        best = Some((k, caps.whole()));
+                    }
+                }
+                Ok::<_, MatchError>(best.map(|(k, span)| (span, (k, pending[k].take().flatten()))))
+            })?;
+            let Some((k, Some(caps))) = found else {
+                break;
+            };
+            let span = caps.whole();
+            let dst = out.get_or_insert_with(|| String::with_capacity(hay.len()));
+            dst.push_str(&hay[copied..span.start]);
+            rep(&self.names[k]).replace_append(&caps, dst);
+            copied = span.end;
+        }
+        Ok(match out {
+            Some(mut dst) => {
+                dst.push_str(&hay[copied..]);
+                Cow::Owned(dst)
+            }
+            None => Cow::Borrowed(hay),
+        })
+    }
+}
+
+/// `Rules::set_matches` の結果。規則は `Rules` の入力順の番号で指す
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetMatches {
+    // [k] = k 番目の規則が一致したか
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    /// k 番目の規則が一致したか（範囲外は false）
+    pub fn matched(&self, k: usize) -> bool {
+        self.matched.get(k).copied().unwrap_or(false)
+    }
+
+    /// 1つでも一致したか
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    /// 一致した規則の番号（小さい順）
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter(|&(_, &yes)| yes)
+            .map(|(k, _)| k)
+    }
+
+    /// 規則の数（一致しなかったものも数える）
+    pub fn len(&self) -> usize {
+        self.matched.len()
+    }
+
+    /// 規則が1つも無いか
+    pub fn is_empty(&self) -> bool {
+        self.matched.is_empty()
+    }
+}
+
+/// 差し替え可能な規則集。スレッド間で共有して使う。
+///
+/// ```
+/// use my_regex::RuleSet;
+/// let set = RuleSet::new([("num", "[0-9]+"), ("word", r"\w+")]).unwrap();
+/// assert_eq!(set.load().route("42").unwrap(), "num");
+///
+/// set.reload([("hex", "0x[0-9a-f]+")]).unwrap();
+/// assert_eq!(set.load().route("0xff").unwrap(), "hex");
+/// assert_eq!(set.load().route("42"), None);
+/// ```
+pub struct RuleSet {
+    current: RwLock<Arc<Rules>>,
+}
+
+impl RuleSet {
+    pub fn new<I, N, P>(rules: I) -> Result<Self, Vec<(usize, Error)>>
+    where
+        I: IntoIterator<Item = (N, P)>,
+        N: Into<String>,
+        P: AsRef<str>,
+    {
+        Ok(Self::from_rules(Rules::new(rules)?))
+    }
+
+    pub fn from_rules(rules: Rules) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(rules)),
+        }
+    }
+
+    /// 今の規則一覧。返した `Arc` は後で差し替えがあっても変わらない
+    pub fn load(&self) -> Arc<Rules> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// 規則一覧を丸ごと入れ替え、前のものを返す
+    pub fn store(&self, rules: Rules) -> Arc<Rules> {
+        let mut cur = self.current.write().unwrap_or_else(|e| e.into_inner());
+        std::mem::replace(&mut *cur, Arc::new(rules))
+    }
+
+    /// コンパイルしてから入れ替える。1つでも失敗すれば今の規則はそのまま
+    pub fn reload<I, N, P>(&self, rules: I) -> Result<Arc<Rules>, Vec<(usize, Error)>>
+    where
+        I: IntoIterator<Item = (N, P)>,
+        N: Into<String>,
+        P: AsRef<str>,
+    {
+        Ok(self.store(Rules::new(rules)?))
+    }
+}
+
+#[cfg(test)]
+mod ruleset_tests {
+    use super::{RuleSet, Rules, SetMatches};
+    use crate::{Captures, ErrorKind, MatchError, RegexBuilder};
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    #[test]
+    fn route_and_matches_follow_input_order() {
+        let rules = Rules::new([("num", "[0-9]+"), ("word", r"\w+"), ("any", ".*")]).unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules.route("12"), Some("num"));
+        assert_eq!(rules.route("ab"), Some("word"));
+        assert_eq!(rules.route("a b"), Some("any"));
+        assert_eq!(rules.matches("12"), vec!["num", "word", "any"]);
+        assert!(rules.get("word").unwrap().is_match("x1"));
+        assert!(rules.get("nope").is_none());
+    }
+
+    #[test]
+    fn byte_haystacks_need_not_be_utf8() {
+        let rules = Rules::new([("magic", "\x7fELF.*"), ("any", ".*"), ("num", "[0-9]+")]).unwrap();
+        let payload = b"\x7fELF\x02\x01\xff";
+        assert_eq!(rules.route_bytes(payload), Some("magic"));
+        assert_eq!(rules.matches_bytes(payload), vec!["magic", "any"]);
+        assert_eq!(rules.matches_bytes(b"\xc0\n"), Vec::<&str>::new());
+        assert_eq!(rules.matches_bytes(b"12"), rules.matches("12"));
+    }
+
+    #[test]
+    fn set_matches_by_index() {
+        // 同名の規則も番号で区別できる
+        let rules = Rules::new([("id", "[0-9]+"), ("id", "[a-z]+"), ("any", ".*")]).unwrap();
+        let m = rules.set_matches("abc");
+        assert_eq!(m.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(m.iter().next_back(), Some(2));
+        assert!(m.matched(1) && !m.matched(0) && !m.matched(9));
+        assert!(m.matched_any());
+        assert_eq!((m.len(), m.is_empty()), (3, false));
+        assert_eq!(rules.set_matches_bytes(b"abc"), m);
+        assert!(!rules.set_matches("A\n").matched_any());
+        let empty = Rules::new(Vec::<(&str, &str)>::new()).unwrap();
+        let none: SetMatches = empty.set_matches("x");
+        assert!(none.is_empty() && !none.matched_any());
+    }
+
+    #[test]
+    fn replace_routes_each_match_to_its_rule() {
+        let rules = Rules::new([("num", "[0-9]+"), ("word", "[a-z]+"), ("ab", "ab")]).unwrap();
+        let tag = |name: &str| format!("<{name}:$0>");
+        assert_eq!(
+            rules.replace_all("ab 12 cd3", tag),
+            "<word:ab> <num:12> <word:cd><num:3>"
+        );
+        // 同じ位置から始まるなら入力順で先の規則。一致の長さは比べない
+        let rules = Rules::new([("a", "a"), ("ab", "ab"), ("b", "b+")]).unwrap();
+        assert_eq!(rules.replace_all("xabbb", tag), "x<a:a><b:bbb>");
+        // 一致が無ければ借用のまま
+        assert!(matches!(
+            rules.replace_all("xyz", tag),
+            Cow::Borrowed("xyz")
+        ));
+        // 空一致の後は1文字進める（find_iter と同じ）。空一致でも先の規則が採られる
+        let rules = Rules::new([("x", "x*"), ("y", "y")]).unwrap();
+        assert_eq!(rules.replace_all("ayxx", tag), "<x:>a<x:>y<x:xx><x:>");
+        let rules = Rules::new([("y", "y"), ("x", "x*")]).unwrap();
+        assert_eq!(rules.replace_all("ayxx", tag), "<x:>a<y:y><x:xx><x:>");
+        // 置き換え方は規則ごとに Replacer を返せばよい
+        let rules = Rules::new([("n", "[0-9]"), ("w", "[a-z]")]).unwrap();
+        let out = rules.replace_all("a1", |name: &str| {
+            let upper = name == "w";
+            move |c: &Captures| {
+                if upper {
+                    c[0].to_uppercase()
+                } else {
+                    format!("#{}", &c[0])
+                }
+            }
+        });
+        assert_eq!(out, "A#1");
+    }
+
+    #[test]
+    fn replace_reports_limits() {
+        let limited = RegexBuilder::new("a+").max_haystack_len(3).build().unwrap();
+        let rules = Rules {
+            names: vec!["a".into()],
+            regexes: vec![limited],
+        };
+        assert!(matches!(
+            rules.try_replace_all("aaaa", |_: &str| "-"),
+            Err(MatchError::HaystackTooLong { len: 4, limit: 3 })
+        ));
+    }
+
+    #[test]
+    fn failed_reload_keeps_old_rules() {
+        let set = RuleSet::new([("a", "a+")]).unwrap();
+        let Err(errs) = set.reload([("ok", "b"), ("bad", "(c")]) else {
+            panic!("reload should fail");
+        };
+        assert_eq!(errs.len(), 1);
+        assert_eq!((errs[0].0, errs[0].1.kind), (1, ErrorKind::UnbalancedParen));
+        assert_eq!(set.load().route("aa"), Some("a"));
+    }
+
+    #[test]
+    fn held_snapshot_survives_swap() {
+        let set = Arc::new(RuleSet::new([("a", "a")]).unwrap());
+        let old = set.load();
+        let t = {
+            let set = Arc::clone(&set);
+            std::thread::spawn(move || set.reload([("b", "b")]).unwrap())
+        };
+        let prev = t.join().unwrap();
+        assert!(Arc::ptr_eq(&prev, &old));
+        assert_eq!(old.route("a"), Some("a"));
+        assert_eq!(set.load().route("a"), None);
+        assert_eq!(set.load().names().collect::<Vec<_>>(), vec!["b"]);
+    }
+}
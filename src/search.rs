@@ -0,0 +1,611 @@
+// search.rs
+//! 1回の探索ごとに変えられる設定（`SearchOptions`）と、実行器へ渡す内部パラメータ。
+use std::iter::FusedIterator;
+
+use crate::error::MatchError;
+use crate::span::Span;
+use crate::{Captures, GroupSlot, Match, Regex, Thread, audit_span, search_failed, thread_start};
+
+/// `Regex::search` に渡す探索ごとの設定。コンパイル済みの `Regex` はそのまま使い回せる。
+///
+/// ```
+/// use my_regex::{Regex, SearchOptions};
+/// let re = Regex::new(r"\d+").unwrap();
+/// let opts = SearchOptions { anchored: false, ..Default::default() };
+/// assert_eq!(re.search("id=42;", &opts).unwrap().as_str(), "42");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// true なら一致は探索範囲の先頭から始まるものに限る（既定）。
+    /// false なら範囲内で最も左から始まる一致を探す。
+    pub anchored: bool,
+    /// true なら一致が確定した最初の位置で止める（終端が最短になる）。
+    /// 成否だけ知りたいときに余計な読み進みを省ける。
+    pub earliest: bool,
+    /// 探索するバイト範囲。終端より後ろは入力に無いものとして扱う。`None` なら全体。
+    pub span: Option<Span>,
+    /// 読み進めるバイト数の上限。`anchored` が false でも入力は左から1回だけ読むので、
+    /// 範囲の長さあれば足りる。超えたら `MatchError::BudgetExceeded`。
+    pub budget: Option<usize>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            anchored: true,
+            earliest: false,
+            span: None,
+            budget: None,
+        }
+    }
+}
+
+/// 実行器1回分の設定
+pub(crate) struct Run {
+    pub track_caps: bool,
+    /// 入力末尾まで読んだ一致だけを候補にする（完全一致用）
+    pub need_end: bool,
+    pub earliest: bool,
+    /// 一致が見つかるまで、各位置で新しい開始スレッドを足す（unanchored 探索）。
+    /// スレッドの開始位置はスロット 0 の開始側に入れて持ち回る
+    pub unanchored: bool,
+    // (残り, 元の予算)
+    fuel: Option<(usize, usize)>,
+    /// 実行器の作業領域。同じ `Run` で続けて照合すれば確保し直さずに済む
+    pub scratch: Scratch,
+}
+
+/// NFA 実行器がバイトごとに入れ替えるスレッド列と訪問済みの印。
+/// 中身は実行ごとに空にしてから使うので、前の実行の残りは結果に影響しない
+#[derive(Default)]
+pub(crate) struct Scratch {
+    pub curr: Vec<Thread>,
+    pub next: Vec<Thread>,
+    pub seen: Vec<bool>,
+}
+
+impl Run {
+    pub fn new(track_caps: bool, need_end: bool) -> Self {
+        Self {
+            track_caps,
+            need_end,
+            earliest: false,
+            unanchored: false,
+            fuel: None,
+            scratch: Scratch::default(),
+        }
+    }
+
+    /// 1バイト読む前に呼ぶ。予算を使い切っていたら失敗。
+    pub fn step(&mut self) -> Result<(), MatchError> {
+        match &mut self.fuel {
+            Some((0, budget)) => Err(MatchError::BudgetExceeded { budget: *budget }),
+            Some((left, _)) => {
+                *left -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// `n` バイトをまとめて読んだ（読み飛ばした）分を払う。足りなければ失敗。
+    pub fn step_n(&mut self, n: usize) -> Result<(), MatchError> {
+        match &mut self.fuel {
+            Some((left, budget)) if *left < n => {
+                Err(MatchError::BudgetExceeded { budget: *budget })
+            }
+            Some((left, _)) => {
+                *left -= n;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// 重ならない一致を左から順に探していくときの、次に探し始める位置。
+/// `find_iter` と、それと同じ一致を並べるもの（グループの列挙・置換・見積もり）はこれで進める。
+#[derive(Clone, Debug)]
+pub(crate) struct Cursor {
+    // 次に探し始める位置。`end` を超えたら終わり
+    at: usize,
+    // 探す範囲の終端
+    end: usize,
+}
+
+impl Cursor {
+    pub fn new(span: Span) -> Self {
+        Self {
+            at: span.start,
+            end: span.end,
+        }
+    }
+
+    /// 次の一致を `search` で探し、その後ろへ進める。`search` は渡した範囲の
+    /// unanchored 探索の結果を (一致の範囲, 結果) で返す。
+    /// 空一致で止まらないよう、空一致の次は1文字先から探す
+    pub fn try_next<T, E>(
+        &mut self,
+        hay: &str,
+        search: impl FnOnce(&SearchOptions) -> Result<Option<(Span, T)>, E>,
+    ) -> Result<Option<T>, E> {
+        if self.at > self.end {
+            return Ok(None);
+        }
+        let opts = SearchOptions {
+            anchored: false,
+            span: Some(Span::new(self.at, self.end)),
+            ..Default::default()
+        };
+        let Some((m, found)) = search(&opts)? else {
+            self.at = self.end + 1;
+            return Ok(None);
+        };
+        self.at = if m.is_empty() {
+            m.end + hay[m.end..].chars().next().map_or(1, char::len_utf8)
+        } else {
+            m.end
+        };
+        Ok(Some(found))
+    }
+
+    /// 残りから見つかりうる一致の数の上限。一致は重ならないので、
+    /// 残りのバイト数 + 1 個（空一致が各位置にある場合）を超えない
+    pub fn max_left(&self) -> usize {
+        (self.end + 1).saturating_sub(self.at)
+    }
+}
+
+/// `Regex::find_iter` が返すイテレータ。一致は重ならず、左から順に並ぶ。
+///
+/// どこで一致が切れるかは前から読まないと決まらないので、後ろからは取り出せない。
+/// `last()` も前から全部探すので、入力の長さに比例する時間がかかる。
+#[derive(Debug)]
+pub struct FindMatches<'r, 'h> {
+    re: &'r Regex,
+    hay: &'h str,
+    cursor: Cursor,
+}
+
+impl<'h> Iterator for FindMatches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        let (re, hay) = (self.re, self.hay);
+        self.cursor
+            .try_next(hay, |opts| {
+                Ok(re.try_search(hay, opts)?.map(|m| (m.span(), m)))
+            })
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cursor.max_left()))
+    }
+}
+
+// 入力を読み切った後は位置が範囲を超えたままなので、None を返し続ける
+impl FusedIterator for FindMatches<'_, '_> {}
+
+impl Regex {
+    /// 入力中の一致を左から順に、重ならないように返す。
+    /// 空一致の直後は1文字進めてから探し直すので、無限に同じ位置を返すことはない。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[0-9]+").unwrap();
+    /// let nums: Vec<&str> = re.find_iter("a1b22c333").map(|m| m.as_str()).collect();
+    /// assert_eq!(nums, vec!["1", "22", "333"]);
+    /// ```
+    ///
+    /// # Panics
+    /// 探索が `MatchError` で失敗した場合（`search` と同じ）。
+    pub fn find_iter<'r, 'h>(&'r self, hay: &'h str) -> FindMatches<'r, 'h> {
+        FindMatches {
+            re: self,
+            hay,
+            cursor: Cursor::new(Span::new(0, hay.len())),
+        }
+    }
+
+    /// `SearchOptions` に従って一致を探す。入力全体を消費しなくてもよい。
+    ///
+    /// # Panics
+    /// `try_search` が `MatchError` を返す場合（上限超過や不正な `span`）。
+    pub fn search<'h>(&self, hay: &'h str, opts: &SearchOptions) -> Option<Match<'h>> {
+        self.try_search(hay, opts)
+            .unwrap_or_else(|e| search_failed(e))
+    }
+
+    /// `search` の失敗を返す版。`span` が入力の範囲外か文字境界でなければ
+    /// `MatchError::InvalidSpan`。
+    pub fn try_search<'h>(
+        &self,
+        hay: &'h str,
+        opts: &SearchOptions,
+    ) -> Result<Option<Match<'h>>, MatchError> {
+        Ok(self
+            .search_slots(hay, opts, false)?
+            .map(|(start, end, _)| Match::new(hay, start, end)))
+    }
+
+    /// `try_search` と同じ探し方で、一致のキャプチャも返す
+    pub(crate) fn try_search_captures<'h>(
+        &self,
+        hay: &'h str,
+        opts: &SearchOptions,
+    ) -> Result<Option<Captures<'h>>, MatchError> {
+        match self.search_slots(hay, opts, true)? {
+            Some((start, end, caps)) => self.captures_from_slots(hay, start, end, &caps).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // (開始, 終端, スロット表)。`track_caps` が false ならスロット表は空
+    fn search_slots(
+        &self,
+        hay: &str,
+        opts: &SearchOptions,
+        track_caps: bool,
+    ) -> Result<Option<(usize, usize, Vec<GroupSlot>)>, MatchError> {
+        let res = self.search_slots_unobserved(hay, opts, track_caps);
+        self.observe(res, Option::is_some)
+    }
+
+    fn search_slots_unobserved(
+        &self,
+        hay: &str,
+        opts: &SearchOptions,
+        track_caps: bool,
+    ) -> Result<Option<(usize, usize, Vec<GroupSlot>)>, MatchError> {
+        self.check_haystack_len(hay)?;
+        let span = opts.span.unwrap_or(Span::new(0, hay.len()));
+        if span.start > span.end
+            || !hay.is_char_boundary(span.start)
+            || !hay.is_char_boundary(span.end)
+        {
+            return Err(MatchError::InvalidSpan { span });
+        }
+        let bytes = &hay.as_bytes()[..span.end];
+        let mut run = Run {
+            earliest: opts.earliest,
+            unanchored: !opts.anchored,
+            fuel: opts.budget.map(|b| (b, b)),
+            ..Run::new(track_caps, false)
+        };
+        // 開始位置ごとにやり直さず、1回の走査で各位置から始まるスレッドを足していく
+        let Some((end, caps)) = self.try_run(bytes, span.start, &mut run)? else {
+            return Ok(None);
+        };
+        let start = thread_start(&caps).unwrap_or(span.start);
+        audit_span(hay, start, end)?;
+        Ok(Some((start, end, caps)))
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use crate::{MatchError, MatchKind, Regex, RegexBuilder, SearchOptions, Span};
+
+    fn opts() -> SearchOptions {
+        SearchOptions::default()
+    }
+
+    #[test]
+    fn default_is_anchored_prefix() {
+        let re = Regex::new("ab+").unwrap();
+        let m = re.try_search("abbbc", &opts()).unwrap().unwrap();
+        assert_eq!((m.start(), m.end()), (0, 4));
+        assert_eq!(re.try_search("xabb", &opts()).unwrap(), None);
+    }
+
+    #[test]
+    fn unanchored_finds_leftmost() {
+        let re = Regex::new("ab+").unwrap();
+        let o = SearchOptions {
+            anchored: false,
+            ..opts()
+        };
+        let m = re.try_search("xxabbab", &o).unwrap().unwrap();
+        assert_eq!((m.start(), m.end(), m.as_str()), (2, 5, "abb"));
+        assert_eq!(re.try_search("xxx", &o).unwrap(), None);
+
+        // 固定文字列の戦略でも開始位置をずらせる
+        let lit = RegexBuilder::new("b.").literal(true).build().unwrap();
+        assert_eq!(lit.try_search("bxab.", &o).unwrap().unwrap().start(), 3);
+    }
+
+    #[test]
+    fn earliest_stops_at_first_accept() {
+        let re = Regex::new("a+").unwrap();
+        let o = SearchOptions {
+            earliest: true,
+            ..opts()
+        };
+        assert_eq!(re.try_search("aaaa", &o).unwrap().unwrap().as_str(), "a");
+
+        let re = RegexBuilder::new("a*")
+            .match_kind(MatchKind::LeftmostFirst)
+            .build()
+            .unwrap();
+        assert_eq!(re.try_search("aaaa", &o).unwrap().unwrap().as_str(), "");
+    }
+
+    #[test]
+    fn span_limits_both_ends() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let o = SearchOptions {
+            span: Some(Span::new(2, 5)),
+            ..opts()
+        };
+        let m = re.try_search("ab1234", &o).unwrap().unwrap();
+        assert_eq!(m.span(), Span::new(2, 5));
+        assert_eq!(m.as_str(), "123");
+
+        let o = SearchOptions {
+            span: Some(Span::new(0, 2)),
+            anchored: false,
+            ..opts()
+        };
+        assert_eq!(re.try_search("ab1234", &o).unwrap(), None);
+    }
+
+    #[test]
+    fn span_keeps_look_context() {
+        let re = RegexBuilder::new("cat").whole_word(true).build().unwrap();
+        let o = SearchOptions {
+            span: Some(Span::new(1, 4)),
+            ..opts()
+        };
+        // 範囲の直前が単語文字なので境界にならない
+        assert_eq!(re.try_search("xcat", &o).unwrap(), None);
+        assert!(re.try_search(" cat", &o).unwrap().is_some());
+    }
+
+    #[test]
+    fn text_anchors_pin_unanchored_search() {
+        let o = SearchOptions {
+            anchored: false,
+            ..opts()
+        };
+        let start = Regex::new(r"\A[0-9]+").unwrap();
+        assert_eq!(
+            start.try_search("12ab34", &o).unwrap().unwrap().as_str(),
+            "12"
+        );
+        assert_eq!(start.try_search("ab34", &o).unwrap(), None);
+        let end = Regex::new(r"[0-9]+\z").unwrap();
+        assert_eq!(
+            end.try_search("12ab34", &o).unwrap().unwrap().as_str(),
+            "34"
+        );
+        assert_eq!(
+            end.find_iter("1a2b3")
+                .map(|m| m.start())
+                .collect::<Vec<_>>(),
+            vec![4]
+        );
+
+        // \A は範囲の先頭ではなく入力の先頭。\z は範囲の終端（その先は無いものとして扱う）
+        let o = SearchOptions {
+            span: Some(Span::new(2, 4)),
+            ..o
+        };
+        assert_eq!(start.try_search("1234", &o).unwrap(), None);
+        assert_eq!(
+            end.try_search("1234", &o).unwrap().unwrap().span(),
+            Span::new(2, 4)
+        );
+    }
+
+    #[test]
+    fn budget_counts_each_byte_once() {
+        let re = Regex::new("a*b").unwrap();
+        let hay = "aaaaaaaaaa";
+        let o = SearchOptions {
+            anchored: false,
+            budget: Some(9),
+            ..opts()
+        };
+        assert_eq!(
+            re.try_search(hay, &o),
+            Err(MatchError::BudgetExceeded { budget: 9 })
+        );
+
+        // 開始位置をずらしても読み直さないので、入力の長さで足りる
+        let o = SearchOptions {
+            budget: Some(hay.len()),
+            ..o
+        };
+        assert_eq!(re.try_search(hay, &o), Ok(None));
+        assert_eq!(re.try_search("aab", &o).unwrap().unwrap().as_str(), "aab");
+    }
+
+    #[test]
+    fn budget_is_charged_for_skipped_and_scanned_bytes() {
+        let hay = format!("ab{}", "x".repeat(1000));
+        let o = |budget| SearchOptions {
+            anchored: false,
+            budget: Some(budget),
+            ..opts()
+        };
+        // 末尾の `.*` はまとめて読み飛ばすが、飛ばした分も数える（NFA と PikeVM の両方）
+        for kind in [MatchKind::LeftmostLongest, MatchKind::LeftmostFirst] {
+            let re = RegexBuilder::new("ab.*").match_kind(kind).build().unwrap();
+            assert_eq!(
+                re.try_search(&hay, &o(100)),
+                Err(MatchError::BudgetExceeded { budget: 100 })
+            );
+            assert_eq!(
+                re.try_search(&hay, &o(hay.len())).unwrap().unwrap().end(),
+                hay.len()
+            );
+        }
+        // リテラルは memchr で探しても、探したバイト数を払う
+        let hay = format!("{}needle", "x".repeat(1000));
+        for re in [
+            Regex::new("needle").unwrap(),
+            Regex::new("(?i)needle").unwrap(),
+        ] {
+            assert_eq!(
+                re.try_search(&hay, &o(100)),
+                Err(MatchError::BudgetExceeded { budget: 100 })
+            );
+            assert_eq!(
+                re.try_search(&hay, &o(hay.len())).unwrap().unwrap().start(),
+                1000
+            );
+        }
+    }
+
+    #[test]
+    fn unanchored_search_is_linear() {
+        // 開始位置ごとにやり直すと、どの位置からも末尾まで読んでしまう入力
+        let hay = "a".repeat(20_000);
+        let o = SearchOptions {
+            anchored: false,
+            budget: Some(hay.len()),
+            ..opts()
+        };
+        for kind in [MatchKind::LeftmostLongest, MatchKind::LeftmostFirst] {
+            let re = RegexBuilder::new("a*b").match_kind(kind).build().unwrap();
+            assert_eq!(re.try_search(&hay, &o), Ok(None));
+            assert_eq!(re.find_iter(&hay).count(), 0);
+            let re = RegexBuilder::new("(a|aa)*c")
+                .match_kind(kind)
+                .build()
+                .unwrap();
+            assert_eq!(re.try_search_captures(&hay, &o), Ok(None));
+        }
+    }
+
+    #[test]
+    fn unanchored_agrees_with_trying_each_start() {
+        let pats = [
+            "a*b",
+            "ab|b",
+            "b|ab",
+            "(a|ab)(c|bcd)",
+            "x*",
+            r"\Ac|t\z",
+            "a+$",
+            "(a*)(b?)",
+            "é|b",
+            "abcd|c",
+        ];
+        let hays = [
+            "",
+            "aab",
+            "xabcd",
+            "abab",
+            "cat concat",
+            "baaa",
+            "aéb",
+            "éé",
+            "abcd",
+        ];
+        for kind in [MatchKind::LeftmostLongest, MatchKind::LeftmostFirst] {
+            for pat in pats {
+                let re = RegexBuilder::new(pat).match_kind(kind).build().unwrap();
+                for hay in hays {
+                    for earliest in [false, true] {
+                        let o = |anchored, start| SearchOptions {
+                            anchored,
+                            earliest,
+                            span: Some(Span::new(start, hay.len())),
+                            ..opts()
+                        };
+                        let each = (0..=hay.len())
+                            .filter(|&k| hay.is_char_boundary(k))
+                            .find_map(|k| re.try_search_captures(hay, &o(true, k)).unwrap());
+                        let once = re.try_search_captures(hay, &o(false, 0)).unwrap();
+                        let groups = |c: Option<crate::Captures>| {
+                            c.map(|c| {
+                                (0..c.len())
+                                    .map(|g| c.get(g).map(|m| m.span()))
+                                    .collect::<Vec<_>>()
+                            })
+                        };
+                        assert_eq!(
+                            groups(once),
+                            groups(each),
+                            "{pat} {hay:?} {kind:?} {earliest}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_iter_skips_past_each_match() {
+        let re = Regex::new("a*").unwrap();
+        let got: Vec<Span> = re.find_iter("baaé").map(|m| m.span()).collect();
+        assert_eq!(
+            got,
+            vec![
+                Span::new(0, 0),
+                Span::new(1, 3),
+                Span::new(3, 3),
+                Span::new(5, 5)
+            ]
+        );
+
+        let re = Regex::new("ab|b").unwrap();
+        let got: Vec<&str> = re.find_iter("abbab").map(|m| m.as_str()).collect();
+        assert_eq!(got, vec!["ab", "b", "ab"]);
+        assert_eq!(re.find_iter("xyz").count(), 0);
+        assert_eq!(re.find_iter("").count(), 0);
+    }
+
+    #[test]
+    fn find_iter_last_scans_forward() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let mut it = re.find_iter("1 22 333 4444");
+        assert_eq!(it.next().unwrap().as_str(), "1");
+        assert_eq!(it.last().unwrap().as_str(), "4444");
+        assert_eq!(re.find_iter("abc").last(), None);
+    }
+
+    #[test]
+    fn find_iter_size_hint_bounds_the_count() {
+        let re = Regex::new("x*").unwrap();
+        let mut it = re.find_iter("abc");
+        assert_eq!(it.size_hint(), (0, Some(4)));
+        it.next();
+        assert_eq!(it.size_hint(), (0, Some(3)));
+        assert_eq!(it.by_ref().count(), 3);
+        assert_eq!(it.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn invalid_span_is_an_error() {
+        let re = Regex::new("a").unwrap();
+        let reversed = Span { start: 2, end: 1 };
+        for span in [Span::new(0, 9), Span::new(1, 2), reversed] {
+            let o = SearchOptions {
+                span: Some(span),
+                ..opts()
+            };
+            assert_eq!(
+                re.try_search("aé", &o),
+                Err(MatchError::InvalidSpan { span })
+            );
+        }
+        let e = MatchError::InvalidSpan {
+            span: Span::new(0, 9),
+        };
+        assert!(e.to_string().contains("0..9"));
+    }
+
+    #[test]
+    #[should_panic(expected = "InvalidSpan")]
+    fn search_panics_on_invalid_span() {
+        let re = Regex::new("a").unwrap();
+        let o = SearchOptions {
+            span: Some(Span::new(0, 9)),
+            ..opts()
+        };
+        let _ = re.search("a", &o);
+    }
+}
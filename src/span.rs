@@ -0,0 +1,154 @@
+// span.rs
+//! 入力やパターン中のバイト範囲。一致・グループ・トークン・エラーで共通に使う。
+use std::fmt;
+use std::ops::Range;
+
+/// 半開区間 `start..end`（バイトオフセット）
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// # Panics
+    /// `start > end` の場合。
+    pub fn new(start: usize, end: usize) -> Self {
+        assert!(start <= end, "invalid span {start}..{end}");
+        Self { start, end }
+    }
+
+    /// 位置 `pos` の空区間
+    pub fn at(pos: usize) -> Self {
+        Self::new(pos, pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// 位置 `pos` が区間内か（`end` は含まない）
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// `other` がまるごと区間内か。空区間は端に置いてあっても含むとみなす。
+    pub fn contains_span(&self, other: Span) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    /// 絶対位置 `mid` で前後に分ける
+    ///
+    /// # Panics
+    /// `mid` が `start..=end` に無い場合。
+    pub fn split_at(&self, mid: usize) -> (Span, Span) {
+        assert!(
+            self.start <= mid && mid <= self.end,
+            "split point {mid} outside {self:?}"
+        );
+        (Span::new(self.start, mid), Span::new(mid, self.end))
+    }
+
+    /// `hay` の該当部分
+    ///
+    /// # Panics
+    /// 範囲外か、文字境界でない場合。
+    pub fn slice<'h>(&self, hay: &'h str) -> &'h str {
+        &hay[self.range()]
+    }
+
+    /// バイト位置を文字（char）の位置に直す。エディタの列番号などに使う。
+    ///
+    /// # Panics
+    /// 範囲外か、文字境界でない場合。
+    pub fn to_chars(&self, hay: &str) -> Span {
+        let start = hay[..self.start].chars().count();
+        Span::new(start, start + self.slice(hay).chars().count())
+    }
+
+    /// 文字位置の区間をバイト位置に直す（`to_chars` の逆）。
+    /// 文字数を超えた位置は `hay.len()` に丸める。
+    pub fn from_chars(hay: &str, chars: Span) -> Span {
+        let byte_at = |n: usize| hay.char_indices().nth(n).map_or(hay.len(), |(b, _)| b);
+        Span::new(byte_at(chars.start), byte_at(chars.end))
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(r: Range<usize>) -> Self {
+        Span::new(r.start, r.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(s: Span) -> Self {
+        s.range()
+    }
+}
+
+#[cfg(test)]
+mod span_tests {
+    use super::Span;
+
+    #[test]
+    fn basics() {
+        let s = Span::new(2, 5);
+        assert_eq!(s.len(), 3);
+        assert!(!s.is_empty());
+        assert!(Span::at(4).is_empty());
+        assert_eq!(s.range(), 2..5);
+        assert_eq!(Span::from(2..5), s);
+        assert_eq!(format!("{s:?}"), "2..5");
+    }
+
+    #[test]
+    fn containment() {
+        let s = Span::new(2, 5);
+        assert!(s.contains(2) && s.contains(4));
+        assert!(!s.contains(5) && !s.contains(1));
+        assert!(s.contains_span(Span::new(3, 5)));
+        assert!(s.contains_span(Span::at(5)));
+        assert!(!s.contains_span(Span::new(1, 3)));
+    }
+
+    #[test]
+    fn split() {
+        let (a, b) = Span::new(2, 6).split_at(4);
+        assert_eq!((a, b), (Span::new(2, 4), Span::new(4, 6)));
+        let (a, b) = Span::new(2, 6).split_at(2);
+        assert!(a.is_empty());
+        assert_eq!(b, Span::new(2, 6));
+    }
+
+    #[test]
+    #[should_panic(expected = "split point")]
+    fn split_outside_panics() {
+        Span::new(2, 6).split_at(7);
+    }
+
+    #[test]
+    fn char_conversion_round_trip() {
+        let hay = "aé日x";
+        // 'é' は2バイト、'日' は3バイト
+        let s = Span::new(1, 6);
+        assert_eq!(s.slice(hay), "é日");
+        let c = s.to_chars(hay);
+        assert_eq!(c, Span::new(1, 3));
+        assert_eq!(Span::from_chars(hay, c), s);
+        assert_eq!(Span::from_chars(hay, Span::new(3, 9)), Span::new(6, 7));
+    }
+}
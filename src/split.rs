@@ -0,0 +1,262 @@
+// split.rs
+//! 一致を区切りとして入力を切り分ける。区切りは `find_iter` と同じ一致（重ならず左から）。
+use std::iter::FusedIterator;
+
+use crate::Regex;
+use crate::search::FindMatches;
+use crate::span::Span;
+
+/// `Regex::split` が返すイテレータ
+#[derive(Debug)]
+pub struct Split<'r, 'h> {
+    hay: &'h str,
+    finder: FindMatches<'r, 'h>,
+    // 次の断片の開始位置
+    last: usize,
+    // 最後の断片（最後の区切りから末尾まで）を返した
+    done: bool,
+}
+
+impl<'h> Iterator for Split<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        if self.done {
+            return None;
+        }
+        match self.finder.next() {
+            Some(m) => {
+                let piece = &self.hay[self.last..m.start()];
+                self.last = m.end();
+                Some(piece)
+            }
+            None => {
+                self.done = true;
+                Some(&self.hay[self.last..])
+            }
+        }
+    }
+}
+
+impl FusedIterator for Split<'_, '_> {}
+
+/// `Regex::splitn` が返すイテレータ
+#[derive(Debug)]
+pub struct SplitN<'r, 'h> {
+    splits: Split<'r, 'h>,
+    // まだ返してよい断片の数
+    left: usize,
+}
+
+impl<'h> Iterator for SplitN<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        match self.left {
+            0 => None,
+            1 => {
+                // 最後の断片は残り全部（以降の区切りは探さない）
+                self.left = 0;
+                let s = &mut self.splits;
+                if s.done {
+                    return None;
+                }
+                s.done = true;
+                Some(&s.hay[s.last..])
+            }
+            _ => {
+                self.left -= 1;
+                self.splits.next()
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.left))
+    }
+}
+
+impl FusedIterator for SplitN<'_, '_> {}
+
+/// `Regex::splitn_str` が返す断片1つ。`field` と区切りを順に繋げると元の入力に戻る
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// 区切りの間の文字列
+    pub field: String,
+    /// この断片の直後の区切りの、入力中の範囲。最後の断片は None
+    pub delimiter_span: Option<Span>,
+}
+
+impl Regex {
+    /// 一致するたびに入力を区切り、区切りの間の部分を左から順に返す。
+    ///
+    /// 断片は常に「一致の数 + 1」個で、`str::split` と同じく先頭・末尾の区切りや
+    /// 連続した区切りは空の断片になる（空の入力なら空の断片1つ）。
+    /// 空一致も区切りなので、どこにでも空一致するパターンは1文字ずつに切り、両端に空の断片が付く。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new("[ ,]+").unwrap();
+    /// let parts: Vec<&str> = re.split("a, b  c,").collect();
+    /// assert_eq!(parts, vec!["a", "b", "c", ""]);
+    /// let parts: Vec<&str> = Regex::new("x*").unwrap().split("ab").collect();
+    /// assert_eq!(parts, vec!["", "a", "b", ""]);
+    /// ```
+    ///
+    /// # Panics
+    /// `find_iter` と同じく、探索が `MatchError` で失敗した場合。
+    pub fn split<'r, 'h>(&'r self, hay: &'h str) -> Split<'r, 'h> {
+        Split {
+            hay,
+            finder: self.find_iter(hay),
+            last: 0,
+            done: false,
+        }
+    }
+
+    /// `split` と同じだが、断片を最大 `limit` 個まで返す（区切るのは `limit - 1` 回まで）。
+    /// 最後の断片は残りの入力そのままで、その中の一致では区切らない。`str::splitn` と同じく
+    /// `limit` が 0 なら何も返さない。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(" *= *").unwrap();
+    /// let parts: Vec<&str> = re.splitn("key = a = b", 2).collect();
+    /// assert_eq!(parts, vec!["key", "a = b"]);
+    /// ```
+    ///
+    /// # Panics
+    /// `find_iter` と同じく、探索が `MatchError` で失敗した場合。
+    pub fn splitn<'r, 'h>(&'r self, hay: &'h str, limit: usize) -> SplitN<'r, 'h> {
+        SplitN {
+            splits: self.split(hay),
+            left: limit,
+        }
+    }
+
+    /// `splitn` と同じ断片を、所有した文字列と直後の区切りの範囲の組にして返す。
+    /// 区切りの文字列も範囲から取り出せるので、元の入力をそのまま組み立て直せる。
+    ///
+    /// ```
+    /// use my_regex::{Regex, Span};
+    /// let re = Regex::new(" *[,;] *").unwrap();
+    /// let hay = "a, b;c";
+    /// let segs = re.splitn_str(hay, 0);
+    /// assert!(segs.is_empty());
+    /// let segs = re.splitn_str(hay, 2);
+    /// assert_eq!(segs[0].field, "a");
+    /// assert_eq!(segs[0].delimiter_span, Some(Span::new(1, 3)));
+    /// assert_eq!((segs[1].field.as_str(), segs[1].delimiter_span), ("b;c", None));
+    /// ```
+    ///
+    /// # Panics
+    /// `find_iter` と同じく、探索が `MatchError` で失敗した場合。
+    pub fn splitn_str(&self, hay: &str, limit: usize) -> Vec<Segment> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        let mut out = Vec::new();
+        let mut last = 0;
+        for m in self.find_iter(hay).take(limit - 1) {
+            out.push(Segment {
+                field: hay[last..m.start()].to_string(),
+                delimiter_span: Some(m.span()),
+            });
+            last = m.end();
+        }
+        out.push(Segment {
+            field: hay[last..].to_string(),
+            delimiter_span: None,
+        });
+        out
+    }
+}
+
+#[cfg(test)]
+mod split_tests {
+    use crate::Regex;
+
+    fn split<'h>(pat: &str, hay: &'h str) -> Vec<&'h str> {
+        Regex::new(pat).unwrap().split(hay).collect()
+    }
+
+    #[test]
+    fn pieces_between_matches() {
+        assert_eq!(split(",", "a,b,c"), vec!["a", "b", "c"]);
+        assert_eq!(split("[0-9]+", "a1b22c"), vec!["a", "b", "c"]);
+        assert_eq!(split(",", "abc"), vec!["abc"]);
+    }
+
+    #[test]
+    fn leading_trailing_and_adjacent_delimiters() {
+        assert_eq!(split(",", ",a,,b,"), vec!["", "a", "", "b", ""]);
+        assert_eq!(split(",", ","), vec!["", ""]);
+        assert_eq!(split(",", ""), vec![""]);
+    }
+
+    #[test]
+    fn empty_matches_split_between_chars() {
+        assert_eq!(split("x*", "aé"), vec!["", "a", "é", ""]);
+        assert_eq!(split("x*", ""), vec!["", ""]);
+        // 空でない一致の直後の空一致も find_iter と同じく区切りになる
+        assert_eq!(split("b*", "abba"), vec!["", "a", "", "a", ""]);
+    }
+
+    #[test]
+    fn splitn_keeps_the_rest_untouched() {
+        let re = Regex::new(",").unwrap();
+        let splitn = |hay, n| re.splitn(hay, n).collect::<Vec<_>>();
+        assert_eq!(splitn("a,b,c", 0), Vec::<&str>::new());
+        assert_eq!(splitn("a,b,c", 1), vec!["a,b,c"]);
+        assert_eq!(splitn("a,b,c", 2), vec!["a", "b,c"]);
+        assert_eq!(splitn("a,b,c", 3), vec!["a", "b", "c"]);
+        assert_eq!(splitn("a,b,c", 9), vec!["a", "b", "c"]);
+        assert_eq!(splitn(",a,", 2), vec!["", "a,"]);
+        assert_eq!(splitn("", 2), vec![""]);
+        // str::splitn と同じ
+        for n in 0..5 {
+            assert_eq!(
+                splitn("a,,b,", n),
+                "a,,b,".splitn(n, ',').collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn segments_rebuild_the_input() {
+        let re = Regex::new(r"\s*[,;]\s*|x*").unwrap();
+        for hay in ["a, b ;c", "", ",,", "éa ;", "abc"] {
+            for limit in 1..6 {
+                let segs = re.splitn_str(hay, limit);
+                let fields: Vec<&str> = segs.iter().map(|s| s.field.as_str()).collect();
+                assert_eq!(fields, re.splitn(hay, limit).collect::<Vec<_>>(), "{hay}");
+                let mut rebuilt = String::new();
+                for seg in &segs {
+                    rebuilt.push_str(&seg.field);
+                    if let Some(d) = seg.delimiter_span {
+                        rebuilt.push_str(d.slice(hay));
+                    }
+                }
+                assert_eq!(rebuilt, hay, "{limit}");
+                assert!(segs.last().unwrap().delimiter_span.is_none());
+                assert!(
+                    segs.iter()
+                        .rev()
+                        .skip(1)
+                        .all(|s| s.delimiter_span.is_some())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn is_fused() {
+        let re = Regex::new(",").unwrap();
+        let mut it = re.split("a,b");
+        assert_eq!(it.by_ref().count(), 2);
+        assert_eq!(it.next(), None);
+        let mut it = re.splitn("a,b", 5);
+        assert_eq!(it.by_ref().count(), 2);
+        assert_eq!(it.next(), None);
+    }
+}
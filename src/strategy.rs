@@ -0,0 +1,411 @@
+// strategy.rs
+//! 照合エンジンの選び方。
+//!
+//! コンパイル時にパターンを調べて `Strategy` を決め（どのエンジンが使えるか・先頭が `\A` か）、
+//! 実行時は `Regex::try_run` が呼び出しごとに（キャプチャが要るか・anchored か・
+//! 完全一致か）で振り分ける。順に:
+//!
+//! 1. 固定文字列のパターンはバイト比較（`Engine::Literal` / `Engine::FoldedLiteral`）
+//! 2. `\A` で始まるパターンの unanchored 探索は、位置 0 からの anchored 探索にする
+//! 3. キャプチャが要らず、終端の選び方が最長か完全一致なら DFA（feature `dfa`、初回に作る）
+//! 4. それ以外は NFA シミュレーション（`MatchKind` で最長一致か優先順位つきかを選ぶ）
+#[cfg(feature = "dfa")]
+use std::sync::{Arc, OnceLock};
+
+use crate::builder::{Config, MatchKind};
+use crate::class::{ByteSet, ClassSet, Equivalences};
+#[cfg(feature = "dfa")]
+use crate::dfa::Dfa;
+use crate::error::MatchError;
+use crate::nfa::{Label, Nfa};
+use crate::search::Run;
+use crate::token::{Look, Token};
+use crate::{GroupSlot, Regex, is_char_start, start_caps};
+
+/// パターンから決まる照合エンジン
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Engine {
+    /// 量指定子もグループも無い固定文字列: バイト比較で済ませる
+    Literal(Vec<u8>),
+    /// 各位置が1バイトの集合の固定長列（大文字・小文字や `equivalent_bytes` の同一視で
+    /// 文字がクラスに畳み込まれたリテラル）: 位置ごとの所属判定で済ませる
+    FoldedLiteral(Vec<ByteSet>),
+    /// それ以外: NFA シミュレーション
+    Nfa,
+}
+
+/// コンパイル時に決めた、呼び出しごとの振り分けの材料
+#[derive(Clone, Debug)]
+pub(crate) struct Strategy {
+    pub engine: Engine,
+    /// どの一致も入力の先頭（`\A`）からしか始まらない
+    pub text_anchored: bool,
+    /// キャプチャの要らない照合に使う DFA。最初に要ったときに作り、作れなければ None。
+    /// clone した `Regex` とも共有する
+    #[cfg(feature = "dfa")]
+    pub dfa: Arc<OnceLock<Option<Dfa>>>,
+}
+
+impl Strategy {
+    /// 照合用の DFA の状態数の上限（1状態 256 本の遷移を持つので小さめにする）
+    #[cfg(feature = "dfa")]
+    const DFA_STATES: usize = 256;
+
+    /// Concat 挿入済みのトークン列と、それから作った NFA から選ぶ
+    pub(crate) fn choose(tokens: &[Token], nfa: &Nfa, config: &Config) -> Self {
+        Self {
+            engine: choose_engine(tokens, config),
+            text_anchored: starts_at_text_start(nfa),
+            #[cfg(feature = "dfa")]
+            dfa: Arc::default(),
+        }
+    }
+}
+
+fn choose_engine(tokens: &[Token], config: &Config) -> Engine {
+    // 境界判定は NFA 側でしか扱えない
+    if config.whole_word {
+        return Engine::Nfa;
+    }
+    let Some(sets) = folded_bytes(tokens, &config.folded_equivalences()) else {
+        return Engine::Nfa;
+    };
+    // どの位置も1バイトに決まるなら、ただのバイト比較
+    match sets
+        .iter()
+        .map(|s| (s.count() == 1).then(|| s.nth(0)).flatten())
+        .collect()
+    {
+        Some(lit) => Engine::Literal(lit),
+        None => Engine::FoldedLiteral(sets),
+    }
+}
+
+// 文字・同一視で1文字から広がったクラス・Concat だけから成るなら、各位置のバイト集合。
+// `\d` のような本来のクラスは対象外（NFA の方が読み飛ばし最適化が効く）
+fn folded_bytes(tokens: &[Token], eq: &Equivalences) -> Option<Vec<ByteSet>> {
+    let is_fold = |set: &ByteSet| {
+        set.nth(0).is_some_and(|b| {
+            let one = ClassSet::from_ranges([(b as u32, b as u32)]);
+            ByteSet::from(&eq.close(&one)) == *set
+        })
+    };
+    let mut sets = Vec::with_capacity(tokens.len());
+    for t in tokens {
+        match t {
+            Token::Char(b) => sets.push(ByteSet::singleton(*b)),
+            Token::Class { ranges, neg: false }
+                if is_fold(&ByteSet::from_ranges(ranges, false)) =>
+            {
+                sets.push(ByteSet::from_ranges(ranges, false))
+            }
+            Token::Concat => {}
+            _ => return None,
+        }
+    }
+    (!sets.is_empty()).then_some(sets)
+}
+
+// 開始状態から文字を読む遷移・受理に着くまでに、どの道も `\A` を通るか
+fn starts_at_text_start(nfa: &Nfa) -> bool {
+    let mut seen = vec![false; nfa.states.len()];
+    let mut stack = vec![nfa.start];
+    while let Some(s) = stack.pop() {
+        if std::mem::replace(&mut seen[s], true) {
+            continue;
+        }
+        if s == nfa.accept {
+            return false;
+        }
+        for (lbl, to) in &nfa.states[s].edges {
+            match lbl {
+                Label::Look(Look::TextStart) => {}
+                Label::Eps | Label::CapBegin(_) | Label::CapEnd(_) | Label::Look(_) => {
+                    stack.push(*to)
+                }
+                Label::Byte(_) | Label::Any | Label::Class(_) => return false,
+            }
+        }
+    }
+    true
+}
+
+// `first` のどれかが最初に現れる位置。3 種類までなら memchr で飛ばす
+// （大文字・小文字の同一視なら 2 種類）
+fn find_first(first: &[u8], hay: &[u8]) -> Option<usize> {
+    match *first {
+        [a] => memchr::memchr(a, hay),
+        [a, b] => memchr::memchr2(a, b, hay),
+        [a, b, c] => memchr::memchr3(a, b, c, hay),
+        _ => hay.iter().position(|b| first.contains(b)),
+    }
+}
+
+// リテラルを探して読んだバイト数（`at` から、一致の終わりか諦めた位置まで）。
+// 予算はこの分を払う
+fn scanned(len: usize, at: usize, start: Option<usize>, lit_len: usize, unanchored: bool) -> usize {
+    let end = match start {
+        Some(k) => k + lit_len,
+        None if unanchored => len,
+        None => at + lit_len,
+    };
+    end.min(len) - at
+}
+
+impl Regex {
+    /// 位置 `at` から読める一致の終端と、そのときのキャプチャを返す（どれを採るかは MatchKind）。
+    /// `run.track_caps` が false なら Cap 遷移を記録せず、スレッドは状態だけで重複除去される。
+    /// どのエンジンで読むかはモジュールの説明の順に決める。
+    pub(crate) fn try_run(
+        &self,
+        bytes: &[u8],
+        at: usize,
+        run: &mut Run,
+    ) -> Result<Option<(usize, Vec<GroupSlot>)>, MatchError> {
+        // unanchored では一致の開始位置をスロット 0 で持ち回るので、キャプチャ不要でも1つ要る
+        let slots = if run.track_caps {
+            self.group_info.len()
+        } else {
+            usize::from(run.unanchored)
+        };
+
+        match &self.strategy.engine {
+            Engine::Literal(lit) => {
+                let start = if run.unanchored {
+                    memchr::memmem::find_iter(&bytes[at..], lit)
+                        .map(|k| at + k)
+                        .find(|&k| is_char_start(bytes, k))
+                } else {
+                    bytes[at..].starts_with(lit).then_some(at)
+                };
+                run.step_n(scanned(bytes.len(), at, start, lit.len(), run.unanchored))?;
+                return Ok(start.map(|k| (k + lit.len(), start_caps(k, slots))));
+            }
+            Engine::FoldedLiteral(sets) => {
+                let hit = |k: usize| {
+                    bytes.len() - k >= sets.len()
+                        && sets
+                            .iter()
+                            .zip(&bytes[k..])
+                            .all(|(set, &b)| set.contains(b))
+                };
+                let start = if run.unanchored {
+                    // 先頭の位置に来うるバイトを memchr で探し、そこからだけ残りを確かめる
+                    let first: Vec<u8> = (0..=255).filter(|&b| sets[0].contains(b)).collect();
+                    let mut from = at;
+                    std::iter::from_fn(|| {
+                        let k = from + find_first(&first, &bytes[from..])?;
+                        from = k + 1;
+                        Some(k)
+                    })
+                    .find(|&k| is_char_start(bytes, k) && hit(k))
+                } else {
+                    hit(at).then_some(at)
+                };
+                run.step_n(scanned(bytes.len(), at, start, sets.len(), run.unanchored))?;
+                return Ok(start.map(|k| (k + sets.len(), start_caps(k, slots))));
+            }
+            Engine::Nfa => {}
+        }
+
+        if run.unanchored && self.strategy.text_anchored {
+            // 位置 0 より後から始まる一致は無いので、開始スレッドを足し続けなくてよい
+            if at > 0 {
+                return Ok(None);
+            }
+            run.unanchored = false;
+            let res = self.try_run(bytes, at, run);
+            run.unanchored = true;
+            return res;
+        }
+
+        #[cfg(feature = "dfa")]
+        if !run.track_caps
+            && !run.unanchored
+            && self.thread_limit.is_none()
+            && (run.need_end || self.match_kind != MatchKind::LeftmostFirst)
+            && let Some(dfa) = self.matching_dfa()
+        {
+            return Ok(dfa.try_run(bytes, at, run)?.map(|end| (end, Vec::new())));
+        }
+
+        match self.match_kind {
+            MatchKind::LeftmostFirst => self.try_run_first(bytes, at, slots, run),
+            MatchKind::LeftmostLongest => self.try_run_longest(bytes, at, slots, run),
+        }
+    }
+
+    // 照合用の DFA（初回に作る）。Look を含むか状態が多すぎれば None
+    #[cfg(feature = "dfa")]
+    pub(crate) fn matching_dfa(&self) -> Option<&Dfa> {
+        self.strategy
+            .dfa
+            .get_or_init(|| Dfa::with_limit(self, Strategy::DFA_STATES).ok())
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod strategy_tests {
+    use super::*;
+    use crate::nfa::build_nfa;
+    use crate::parse::{insert_concat, to_postfix};
+    use crate::token::tokenize;
+    use crate::{RegexBuilder, SearchOptions};
+
+    fn choose(pat: &str) -> Engine {
+        let t = insert_concat(&tokenize(pat).unwrap());
+        choose_engine(&t, &Config::default())
+    }
+
+    fn text_anchored(pat: &str) -> bool {
+        let t = insert_concat(&tokenize(pat).unwrap());
+        starts_at_text_start(&build_nfa(&to_postfix(&t).unwrap(), &[]).unwrap())
+    }
+
+    #[test]
+    fn plain_text_is_literal() {
+        assert_eq!(choose("abc"), Engine::Literal(b"abc".to_vec()));
+        // エスケープ済みのメタ文字もリテラル
+        assert_eq!(choose(r"a\.b\*"), Engine::Literal(b"a.b*".to_vec()));
+    }
+
+    #[test]
+    fn case_folding_keeps_prefilter() {
+        use crate::token::apply_equivalences;
+        let config = Config {
+            case_insensitive: true,
+            ..Config::default()
+        };
+        let tokens = apply_equivalences(tokenize("Ab-1").unwrap(), &config.folded_equivalences());
+        let Engine::FoldedLiteral(sets) = choose_engine(&insert_concat(&tokens), &config) else {
+            panic!("folded literal should not fall back to the NFA");
+        };
+        assert_eq!(sets.len(), 4);
+        assert!(sets[0].contains(b'a') && sets[0].contains(b'A'));
+        assert_eq!(sets[2], ByteSet::singleton(b'-'));
+
+        // 1バイトに決まるクラスは固定文字列のまま。同一視由来でないクラスは NFA
+        assert_eq!(choose("[a]b"), Engine::Literal(b"ab".to_vec()));
+        assert_eq!(choose("[ab]c"), Engine::Nfa);
+        assert_eq!(choose("[^a]"), Engine::Nfa);
+    }
+
+    #[test]
+    fn folded_literal_scan_verifies_each_candidate() {
+        assert_eq!(find_first(b"aA", b"xxAx"), Some(2));
+        assert_eq!(find_first(b"0Oo", b"xyz"), None);
+        assert_eq!(find_first(b"0Ooq", b"xxqO"), Some(2));
+
+        // 先頭のバイトだけ合う位置は確かめて捨てる
+        let re = Regex::new("(?i)abc").unwrap();
+        assert!(matches!(re.strategy.engine, Engine::FoldedLiteral(_)));
+        let m: Vec<usize> = re.find_iter("aBaAbXaBCabC").map(|m| m.start()).collect();
+        assert_eq!(m, vec![6, 9]);
+        // 4 バイト以上の同一視は memchr を使わずに探す
+        let re = RegexBuilder::new("o:")
+            .equivalent_bytes(b"oO0q")
+            .build()
+            .unwrap();
+        assert!(matches!(re.strategy.engine, Engine::FoldedLiteral(_)));
+        assert_eq!(re.find("o q0:").unwrap().start(), 3);
+    }
+
+    #[test]
+    fn operators_need_nfa() {
+        assert_eq!(choose("ab*"), Engine::Nfa);
+        assert_eq!(choose("a|b"), Engine::Nfa);
+        assert_eq!(choose("(ab)"), Engine::Nfa);
+        assert_eq!(choose("a."), Engine::Nfa);
+        assert_eq!(choose(r"\d"), Engine::Nfa);
+    }
+
+    #[test]
+    fn whole_word_needs_nfa() {
+        let t = insert_concat(&tokenize("abc").unwrap());
+        let config = Config {
+            whole_word: true,
+            ..Config::default()
+        };
+        assert_eq!(choose_engine(&t, &config), Engine::Nfa);
+    }
+
+    #[test]
+    fn text_anchor_is_detected_on_every_path() {
+        assert!(text_anchored(r"\Aab*"));
+        assert!(text_anchored(r"(\Aa|\Ab)c"));
+        assert!(!text_anchored(r"ab"));
+        assert!(!text_anchored(r"\Aa|b"));
+        assert!(!text_anchored(r"(\A)?a"));
+        assert!(!text_anchored(r"a*\A"));
+    }
+
+    #[test]
+    fn text_anchored_search_only_tries_the_start() {
+        let re = Regex::new(r"\A(a|b)+").unwrap();
+        assert!(re.strategy.text_anchored);
+        let o = SearchOptions {
+            anchored: false,
+            budget: Some(3),
+            ..Default::default()
+        };
+        // 先頭で一致しなければ、残りの入力は読まない
+        let hay = format!("x{}", "ab".repeat(100));
+        assert_eq!(re.try_search(&hay, &o), Ok(None));
+        assert_eq!(re.try_search("abx", &o).unwrap().unwrap().as_str(), "ab");
+        assert_eq!(re.find_iter("abab").count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "dfa")]
+    fn only_capture_free_calls_build_the_dfa() {
+        let re = Regex::new("(a|b)*c").unwrap();
+        assert!(re.captures("abc").is_some());
+        assert!(re.strategy.dfa.get().is_none());
+        assert!(re.is_match("abc"));
+        assert!(re.strategy.dfa.get().is_some_and(Option::is_some));
+        // Look を含むパターンは DFA にできないので NFA のまま
+        let re = RegexBuilder::new("a").whole_word(true).build().unwrap();
+        assert!(re.is_match("a"));
+        assert!(re.strategy.dfa.get().is_some_and(Option::is_none));
+    }
+
+    #[test]
+    fn every_path_gives_the_same_answer() {
+        // 同じパターンをキャプチャあり・なし、上限あり（DFA を使わない）で比べる
+        for pat in [
+            r"(a|ab)(c|bcd)(d*)",
+            r"[^0-9]+\d?",
+            r"\A(x|y)*z?",
+            "GeT",
+            r"a+\z",
+        ] {
+            for kind in [MatchKind::LeftmostLongest, MatchKind::LeftmostFirst] {
+                let fast = RegexBuilder::new(pat).match_kind(kind).build().unwrap();
+                let slow = RegexBuilder::new(pat)
+                    .match_kind(kind)
+                    .thread_limit(1000)
+                    .build()
+                    .unwrap();
+                for hay in ["", "abcd", "abcddd", "xyz", "q1", "get", "xaa", "aa"] {
+                    assert_eq!(fast.is_match(hay), slow.is_match(hay), "{pat} {hay}");
+                    assert_eq!(
+                        fast.match_prefix(hay).map(|m| m.end()),
+                        slow.match_prefix(hay).map(|m| m.end()),
+                        "{pat} {hay}"
+                    );
+                    let unanchored = SearchOptions {
+                        anchored: false,
+                        ..Default::default()
+                    };
+                    let with_caps = fast.try_search_captures(hay, &unanchored).unwrap();
+                    assert_eq!(
+                        fast.find(hay).map(|m| m.span()),
+                        with_caps.and_then(|c| c.get(0)).map(|m| m.span()),
+                        "{pat} {hay}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+// stream.rs
+//! 書き込まれたバイト列をその場で照合する `std::io::Write`（ネットワークの流れを tee するなど）。
+//!
+//! 入力全体は持たず、まだ一致が確定していない候補の分だけを手元に残す。
+//! 一致は `find_iter` と同じく重ならず左から順で、終端が確定した時点でコールバックに渡す。
+//! 1バイトずつ進める部分（`Stepper`）は少しずつ探す `SearchState` と、
+//! 届いたバイトを溜める部分（`Scanner`）は非同期版（feature `tokio`）と共有する。
+use std::io;
+
+use crate::error::MatchError;
+use crate::span::Span;
+use crate::{Regex, Thread};
+
+/// `Regex::match_sink` が返す書き込み先
+///
+/// ```
+/// use std::io::Write;
+/// use my_regex::Regex;
+/// let re = Regex::new("[0-9]+").unwrap();
+/// let mut found = Vec::new();
+/// let mut sink = re.match_sink(|span, bytes: &[u8]| found.push((span.range(), bytes.to_vec())));
+/// sink.write_all(b"id=4").unwrap();
+/// sink.write_all(b"2; n=7").unwrap();
+/// sink.finish().unwrap();
+/// assert_eq!(found, vec![(3..5, b"42".to_vec()), (9..10, b"7".to_vec())]);
+/// ```
+pub struct MatchSink<'r, F> {
+    re: &'r Regex,
+    on_match: F,
+    scan: Scanner,
+}
+
+impl<'r, F: FnMut(Span, &[u8])> MatchSink<'r, F> {
+    /// 入力の終わりを伝え、残っている一致をすべて報告する。
+    /// 呼ばずに落とした場合、最後の確定していない一致は報告されない。
+    pub fn finish(mut self) -> io::Result<()> {
+        self.advance(true).map_err(io::Error::other)
+    }
+
+    fn advance(&mut self, last: bool) -> Result<(), MatchError> {
+        for m in self.scan.advance(self.re, last)? {
+            (self.on_match)(m, self.scan.bytes(m));
+        }
+        self.scan.trim();
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Span, &[u8])> io::Write for MatchSink<'_, F> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.scan.push(data);
+        self.advance(false).map_err(io::Error::other)?;
+        Ok(data.len())
+    }
+
+    /// 何もしない。一致の終端は後続のバイトを見るまで決まらないので、`finish` で締める
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 少しずつ届くバイト列と、それを照合する `Stepper`。
+/// 一致しかけている部分より前は `trim` で捨てる
+#[derive(Clone, Default)]
+pub(crate) struct Scanner {
+    // 手元に残しているバイト列。buf[0] は入力全体での位置 buf_start
+    buf: Vec<u8>,
+    buf_start: usize,
+    // buf[0] の直前のバイト（単語境界の判定用）
+    buf_prev: Option<u8>,
+    stepper: Stepper,
+}
+
+impl Scanner {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// 手元のバイトを読めるところまで進め、確定した一致を返す。
+    /// `last` なら入力の末尾も1つの位置として調べる
+    pub fn advance(&mut self, re: &Regex, last: bool) -> Result<Vec<Span>, MatchError> {
+        let mut found = Vec::new();
+        loop {
+            let at = self.stepper.at;
+            let end = self.end();
+            if at > end || (at == end && !last) {
+                break;
+            }
+            let prev = if at == self.buf_start {
+                self.buf_prev
+            } else {
+                Some(self.buf[at - 1 - self.buf_start])
+            };
+            let next = self.buf.get(at - self.buf_start).copied();
+            found.extend(self.stepper.step(re, prev, next)?);
+        }
+        Ok(found)
+    }
+
+    /// 入力全体での区間 `span` のバイト列（`settled` より後ろか、まだ `trim` していない部分）
+    pub fn bytes(&self, span: Span) -> &[u8] {
+        &self.buf[span.start - self.buf_start..span.end - self.buf_start]
+    }
+
+    /// これまでに届いたバイト数
+    pub fn end(&self) -> usize {
+        self.buf_start + self.buf.len()
+    }
+
+    /// これより前から始まる一致はもう無い
+    pub fn settled(&self) -> usize {
+        self.stepper.oldest_needed()
+    }
+
+    /// `settled` より前を捨てる
+    pub fn trim(&mut self) {
+        let k = self
+            .settled()
+            .saturating_sub(self.buf_start)
+            .min(self.buf.len());
+        if k > 0 {
+            self.buf_prev = Some(self.buf[k - 1]);
+            self.buf.drain(..k);
+            self.buf_start += k;
+        }
+    }
+}
+
+impl Regex {
+    /// 書き込まれたバイト列を順に照合し、一致が確定するたびに `on_match(位置, 一致したバイト列)` を呼ぶ
+    /// `std::io::Write` を返す。位置は書き込みの通算オフセット。
+    ///
+    /// 一致の選び方は `find_iter` と同じで、`match_kind` によらず最も左で最長のもの。
+    /// 手元に残すのは一致しかけている部分だけだが、`a.*b` のように終わりの来ない候補があると
+    /// その分は溜まり続ける。スレッド数が `thread_limit` を超えたら書き込みが失敗する。
+    pub fn match_sink<F: FnMut(Span, &[u8])>(&self, on_match: F) -> MatchSink<'_, F> {
+        MatchSink {
+            re: self,
+            on_match,
+            scan: Scanner::default(),
+        }
+    }
+}
+
+/// 最も左で最長の一致を、位置を1つずつ進めながら探す状態。
+/// 入力は持たず、呼び出し側が各位置の前後のバイトを渡す
+#[derive(Clone, Default)]
+pub(crate) struct Stepper {
+    // 次に調べる位置（入力全体でのオフセット）。一致を報告すると、その終端に戻る
+    pub at: usize,
+    // 位置 at での（ε閉包を取る前の）スレッド。caps[0].0 にそのスレッドの開始位置を入れる
+    threads: Vec<Thread>,
+    // これまでに見つかった最も左で最長の一致
+    best: Option<Span>,
+    // これより前からは新しい一致を始めない
+    search_from: usize,
+    // 空一致の直後。UTF-8 の継続バイトの位置からは始めない（`find_iter` の1文字送りと同じ）
+    skip_continuation: bool,
+}
+
+impl Stepper {
+    /// 位置 `at` を調べ、`next`（位置 `at` のバイト）があればそれを読んで1つ進む。
+    /// `prev` は位置 `at` の直前のバイト、`next` が None なら `at` は入力の末尾。
+    /// 一致が確定したらそれを返す（このとき `at` は一致の終端に戻っている）
+    pub fn step(
+        &mut self,
+        re: &Regex,
+        prev: Option<u8>,
+        next: Option<u8>,
+    ) -> Result<Option<Span>, MatchError> {
+        let p = self.at;
+        if self.best.is_none() && p >= self.search_from {
+            if self.skip_continuation && next.is_some_and(|b| (0x80..0xC0).contains(&b)) {
+                // 空一致と同じ文字の途中
+            } else {
+                self.skip_continuation = false;
+                self.threads.push(Thread {
+                    s: re.start,
+                    caps: vec![(Some(p), None)],
+                });
+            }
+        }
+
+        // 前後1バイトずつの窓で ε 閉包を取る（Look は前後1バイトしか見ない）
+        let mut window = [0u8; 2];
+        let mut n = 0;
+        if let Some(b) = prev {
+            window[0] = b;
+            n = 1;
+        }
+        let pos = n;
+        if let Some(b) = next {
+            window[n] = b;
+            n += 1;
+        }
+        let mut set = std::mem::take(&mut self.threads);
+        re.eps_closure(&mut set, &window[..n], pos);
+
+        // 同じ状態なら開始が左のものだけ残す（後から始めたものが勝つことはない）
+        set.sort_by_key(|t| t.caps[0].0);
+        let mut seen = vec![false; re.states.len()];
+        set.retain(|t| !std::mem::replace(&mut seen[t.s], true));
+
+        for t in &set {
+            if t.s == re.accept {
+                let start = t.caps[0].0.unwrap_or(p);
+                let better = match self.best {
+                    None => true,
+                    Some(b) => start < b.start || (start == b.start && p > b.end),
+                };
+                if better {
+                    self.best = Some(Span::new(start, p));
+                }
+            }
+        }
+        if let Some(b) = self.best {
+            set.retain(|t| t.caps[0].0.is_some_and(|s| s <= b.start));
+        }
+
+        self.threads = match next {
+            Some(b) => {
+                let next_set = re.step_byte(&set, b);
+                re.check_thread_limit(&next_set)?;
+                next_set
+            }
+            None => Vec::new(),
+        };
+        self.at += 1;
+
+        if !self.threads.is_empty() {
+            return Ok(None);
+        }
+        let Some(m) = self.best.take() else {
+            return Ok(None);
+        };
+        // 一致の終端から探し直す。空一致なら1文字先から
+        self.at = m.end;
+        self.search_from = if m.is_empty() { m.end + 1 } else { m.end };
+        self.skip_continuation = m.is_empty();
+        Ok(Some(m))
+    }
+
+    /// これより前の入力はもう参照しない
+    pub fn oldest_needed(&self) -> usize {
+        let mut keep = self.at;
+        if let Some(b) = self.best {
+            keep = keep.min(b.start);
+        }
+        for t in &self.threads {
+            if let Some(s) = t.caps[0].0 {
+                keep = keep.min(s);
+            }
+        }
+        keep
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use std::io::Write;
+
+    use crate::{Regex, RegexBuilder, Span};
+
+    // hay を chunk バイトずつ書き込んだときの一致
+    fn streamed(re: &Regex, hay: &str, chunk: usize) -> Vec<(Span, Vec<u8>)> {
+        let mut out = Vec::new();
+        let mut sink = re.match_sink(|s, b: &[u8]| out.push((s, b.to_vec())));
+        for part in hay.as_bytes().chunks(chunk.max(1)) {
+            sink.write_all(part).unwrap();
+        }
+        sink.finish().unwrap();
+        out
+    }
+
+    #[test]
+    fn agrees_with_find_iter_for_any_chunking() {
+        let cases = [
+            ("[0-9]+", "a1b22c333"),
+            ("x*", "axxb"),
+            ("a|ab|abc", "abcab"),
+            ("(ab)+c", "ababab ababc"),
+            ("é*", "aéb"),
+            ("a.*b", "a--b--b a"),
+            (r"\Aa|b\z", "aab ab"),
+            (r"\A\z", ""),
+        ];
+        for (pat, hay) in cases {
+            let re = Regex::new(pat).unwrap();
+            let want: Vec<(Span, Vec<u8>)> = re
+                .find_iter(hay)
+                .map(|m| (m.span(), m.as_str().as_bytes().to_vec()))
+                .collect();
+            for chunk in [1, 2, 3, hay.len()] {
+                assert_eq!(
+                    streamed(&re, hay, chunk),
+                    want,
+                    "{pat} on {hay:?} by {chunk}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn word_boundaries_see_across_writes() {
+        let re = RegexBuilder::new("foo").whole_word(true).build().unwrap();
+        let hay = "foo food xfoo foo";
+        for chunk in [1, 2, hay.len()] {
+            let got: Vec<Span> = streamed(&re, hay, chunk)
+                .into_iter()
+                .map(|(s, _)| s)
+                .collect();
+            assert_eq!(got, vec![Span::new(0, 3), Span::new(14, 17)], "by {chunk}");
+        }
+    }
+
+    #[test]
+    fn reports_matches_as_soon_as_they_end() {
+        let re = Regex::new("ab+").unwrap();
+        let mut seen = Vec::new();
+        let mut sink = re.match_sink(|s, _: &[u8]| seen.push(s));
+        sink.write_all(b"xabb").unwrap();
+        // まだ b が続くかもしれない
+        assert_eq!(sink.scan.buf, b"abb");
+        sink.write_all(b"x").unwrap();
+        assert!(sink.scan.buf.is_empty());
+        drop(sink);
+        assert_eq!(seen, vec![Span::new(1, 4)]);
+    }
+
+    #[test]
+    fn thread_limit_fails_the_write() {
+        let re = RegexBuilder::new("(a|aa)*b")
+            .thread_limit(1)
+            .build()
+            .unwrap();
+        let mut sink = re.match_sink(|_, _: &[u8]| {});
+        let err = sink.write_all(b"aaaa").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+}
@@ -0,0 +1,174 @@
+// suggest.rs
+//! 一致しない入力に対し、少ない編集で一致する文字列を探す（「もしかして」用）。
+//!
+//! (入力位置, NFA状態) を頂点とし、一致する1バイトの読み取りとε系の遷移をコスト0、
+//! 挿入・削除・置換をコスト1とした 0-1 BFS で最小編集数の経路を求める。
+use std::collections::VecDeque;
+
+use crate::Regex;
+use crate::class::ByteSet;
+use crate::nfa::Label;
+
+// 経路復元用: 直前の頂点と、そこから進むときに出力したバイト
+#[derive(Clone, Copy)]
+struct Pred {
+    from: usize,
+    emit: Option<u8>,
+}
+
+impl Regex {
+    /// `hay` をバイト単位で最大 `max_edits` 回（挿入・削除・置換）編集して、
+    /// 完全一致する文字列を返す。候補が複数あれば編集の少ないものの1つ。
+    /// すでに一致していれば `hay` そのもの。
+    ///
+    /// 挿入・置換するバイトは ASCII から選ぶ（英数字を優先）。単語境界は元の入力の
+    /// 位置で近似するので、最後に `is_match` で確かめ、通らなければ `None`。
+    /// 編集の結果が UTF-8 として壊れる場合も `None`。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"\d\d\d-\d\d\d\d").unwrap();
+    /// assert_eq!(re.closest_valid("123-45x7", 1).as_deref(), Some("123-4507"));
+    /// assert_eq!(re.closest_valid("1234567", 1).as_deref(), Some("123-4567"));
+    /// assert_eq!(re.closest_valid("12", 3), None);
+    /// ```
+    pub fn closest_valid(&self, hay: &str, max_edits: usize) -> Option<String> {
+        let bytes = hay.as_bytes();
+        let n = bytes.len();
+        let ns = self.states.len();
+        let id = |pos: usize, s: usize| pos * ns + s;
+
+        let mut dist = vec![usize::MAX; (n + 1) * ns];
+        let mut pred: Vec<Option<Pred>> = vec![None; (n + 1) * ns];
+        let mut q = VecDeque::new();
+        dist[id(0, self.start)] = 0;
+        q.push_back((0usize, self.start));
+
+        let goal = id(n, self.accept);
+        while let Some((pos, s)) = q.pop_front() {
+            let d = dist[id(pos, s)];
+            if id(pos, s) == goal {
+                break;
+            }
+            let mut relax = |pos2: usize, s2: usize, cost: usize, emit: Option<u8>| {
+                let nd = d + cost;
+                let v = id(pos2, s2);
+                if nd <= max_edits && nd < dist[v] {
+                    dist[v] = nd;
+                    pred[v] = Some(Pred {
+                        from: id(pos, s),
+                        emit,
+                    });
+                    if cost == 0 {
+                        q.push_front((pos2, s2));
+                    } else {
+                        q.push_back((pos2, s2));
+                    }
+                }
+            };
+
+            // 削除: 入力を1バイト捨てる
+            if pos < n {
+                relax(pos + 1, s, 1, None);
+            }
+            for (lbl, tgt) in &self.states[s].edges {
+                match lbl {
+                    Label::Eps | Label::CapBegin(_) | Label::CapEnd(_) => relax(pos, *tgt, 0, None),
+                    Label::Look(look) => {
+                        if look.matches(bytes, pos) {
+                            relax(pos, *tgt, 0, None);
+                        }
+                    }
+                    Label::Byte(_) | Label::Any | Label::Class(_) => {
+                        let set = lbl.byte_set().unwrap_or_else(ByteSet::empty);
+                        if pos < n && set.contains(bytes[pos]) {
+                            relax(pos + 1, *tgt, 0, Some(bytes[pos]));
+                        }
+                        if let Some(b) = representative(&set) {
+                            relax(pos, *tgt, 1, Some(b)); // 挿入
+                            if pos < n {
+                                relax(pos + 1, *tgt, 1, Some(b)); // 置換
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if dist[goal] == usize::MAX {
+            return None;
+        }
+        let mut out = Vec::new();
+        let mut v = goal;
+        while let Some(p) = pred[v] {
+            out.extend(p.emit);
+            v = p.from;
+        }
+        out.reverse();
+        let s = String::from_utf8(out).ok()?;
+        self.is_match(&s).then_some(s)
+    }
+}
+
+// 挿入・置換に使うバイト: 英数字 → 表示可能な ASCII → その他の ASCII の順に最初のもの。
+// 非 ASCII を1バイトだけ足すと UTF-8 が壊れるので選ばない
+fn representative(set: &ByteSet) -> Option<u8> {
+    let alnum = (b'0'..=b'9').chain(b'a'..=b'z').chain(b'A'..=b'Z');
+    alnum
+        .chain(0x20..0x7f)
+        .chain(0..0x80)
+        .find(|&b| set.contains(b))
+}
+
+#[cfg(test)]
+mod suggest_tests {
+    use crate::{Regex, RegexBuilder};
+
+    fn fix(p: &str, hay: &str, k: usize) -> Option<String> {
+        Regex::new(p).unwrap().closest_valid(hay, k)
+    }
+
+    #[test]
+    fn already_matching_is_returned_as_is() {
+        assert_eq!(fix("a+b", "aaab", 0).as_deref(), Some("aaab"));
+    }
+
+    #[test]
+    fn single_edits() {
+        // 置換・挿入・削除
+        assert_eq!(fix("colou?r", "colur", 1).as_deref(), Some("colour"));
+        assert_eq!(fix("abc", "axc", 1).as_deref(), Some("abc"));
+        assert_eq!(fix("abc", "ac", 1).as_deref(), Some("abc"));
+        assert_eq!(fix("abc", "abxc", 1).as_deref(), Some("abc"));
+        assert_eq!(fix("[A-Z][a-z]+", "john", 1).as_deref(), Some("Aohn"));
+    }
+
+    #[test]
+    fn respects_edit_budget() {
+        assert_eq!(fix("abcd", "xxcd", 1), None);
+        assert_eq!(fix("abcd", "xxcd", 2).as_deref(), Some("abcd"));
+        // 0 回なら一致しているときだけ
+        assert_eq!(fix("abcd", "abce", 0), None);
+    }
+
+    #[test]
+    fn negated_class_picks_allowed_byte() {
+        assert_eq!(fix("[^0-9]", "5", 1).as_deref(), Some("a"));
+        assert_eq!(fix("k=[^a-z0-9]", "k=", 1).as_deref(), Some("k=A"));
+    }
+
+    #[test]
+    fn keeps_non_ascii_text() {
+        // 量指定子はバイト単位なので、複数バイトの文字は括弧で囲む
+        assert_eq!(fix("(é)+!", "éé", 1).as_deref(), Some("éé!"));
+    }
+
+    #[test]
+    fn result_is_verified_for_word_boundaries() {
+        let re = RegexBuilder::new("cat").whole_word(true).build().unwrap();
+        assert_eq!(re.closest_valid("cot", 1).as_deref(), Some("cat"));
+        for s in [re.closest_valid("cats", 1), re.closest_valid("xcat", 2)] {
+            assert!(s.as_deref().is_none_or(|s| re.is_match(s)));
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// template.rs
+//! 名前付きの差し込み口 `{{name}}` を持つパターン。
+//!
+//! 差し込む値は必ず `escape` してから埋めるので、利用者の入力がメタ文字を含んでいても
+//! パターンの構造は変わらない（`format!` で組み立てると起きる取り違えを防ぐ）。
+use crate::Regex;
+use crate::error::{Error, ErrorKind, err};
+use crate::span::Span;
+use crate::token::{escape, is_counted_repetition};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    // 雛形のこの範囲をそのまま使う
+    Raw(Span),
+    // 差し込み口。範囲は `{{name}}` 全体
+    Param { name: String, span: Span },
+}
+
+/// `{{name}}` を含むパターンの雛形。
+///
+/// ```
+/// use my_regex::PatternTemplate;
+/// let t = PatternTemplate::new(r"key=\w+;{{user}}").unwrap();
+/// let re = t.instantiate(&[("user", "a.b*")]).unwrap();
+/// assert!(re.is_match("key=x;a.b*"));
+/// assert!(!re.is_match("key=x;aXbbb"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct PatternTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+impl PatternTemplate {
+    /// 雛形を解析し、差し込み口を仮の値で埋めて構文を検査する。
+    /// エラー位置は雛形中の位置。差し込み口の直後に量指定子は置けない
+    /// （値が複数文字のとき意味が曖昧になるので、`({{w}})+` のように括弧で囲む）。
+    pub fn new(template: &str) -> Result<Self, Error> {
+        let parts = split_placeholders(template);
+        for w in parts.windows(2) {
+            if let [Part::Param { .. }, Part::Raw(next)] = w
+                && let rest = &template.as_bytes()[next.start..]
+                && (matches!(rest[0], b'*' | b'+' | b'?') || is_counted_repetition(rest))
+            {
+                return err(ErrorKind::QuantifiedPlaceholder, next.start);
+            }
+        }
+        let t = Self {
+            template: template.to_string(),
+            parts,
+        };
+        // 値の中身は構文に影響しないので、1文字で代表させて検査する
+        let probe: Vec<(&str, &str)> = t.placeholders().map(|name| (name, "a")).collect();
+        t.instantiate(&probe)?;
+        Ok(t)
+    }
+
+    /// 差し込み口の名前（出現順、重複あり）
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|p| match p {
+            Part::Param { name, .. } => Some(name.as_str()),
+            Part::Raw(_) => None,
+        })
+    }
+
+    /// 値をエスケープして埋めたパターン文字列と、展開後の各部分が雛形のどこから来たか
+    fn expand_with_map(
+        &self,
+        values: &[(&str, &str)],
+    ) -> Result<(String, Vec<(usize, Span)>), Error> {
+        let mut out = String::new();
+        let mut map = Vec::new(); // (展開後の開始位置, 雛形中の範囲)
+        for part in &self.parts {
+            map.push((out.len(), *part.span()));
+            match part {
+                Part::Raw(span) => out.push_str(span.slice(&self.template)),
+                Part::Param { name, span } => {
+                    let Some((_, value)) = values.iter().find(|(k, _)| k == name) else {
+                        return err(ErrorKind::UnboundPlaceholder, span.start);
+                    };
+                    out.push_str(&escape(value));
+                }
+            }
+        }
+        Ok((out, map))
+    }
+
+    /// 値をエスケープして埋めたパターン文字列
+    pub fn expand(&self, values: &[(&str, &str)]) -> Result<String, Error> {
+        Ok(self.expand_with_map(values)?.0)
+    }
+
+    /// 値を埋めてコンパイルする。値が足りなければ `ErrorKind::UnboundPlaceholder`。
+    /// コンパイルエラーの位置は雛形中の位置に引き直す（値の中なら差し込み口の先頭）。
+    pub fn instantiate(&self, values: &[(&str, &str)]) -> Result<Regex, Error> {
+        let (pattern, map) = self.expand_with_map(values)?;
+        Regex::new(&pattern).map_err(|e| {
+            let pos = match map.iter().rposition(|(at, _)| *at <= e.pos) {
+                Some(k) => {
+                    let (at, span) = map[k];
+                    match self.parts[k] {
+                        Part::Raw(_) => span.start + (e.pos - at),
+                        Part::Param { .. } => span.start,
+                    }
+                }
+                None => self.template.len(),
+            };
+            Error { pos, ..e }
+        })
+    }
+}
+
+impl Part {
+    fn span(&self) -> &Span {
+        match self {
+            Part::Raw(span) | Part::Param { span, .. } => span,
+        }
+    }
+}
+
+// `{{` 名前 `}}` を差し込み口として切り出す。名前は英数字と `_`。
+// 形になっていない `{{` は通常の文字として残す。
+fn split_placeholders(template: &str) -> Vec<Part> {
+    let bytes = template.as_bytes();
+    let mut parts = Vec::new();
+    let mut raw_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"{{") {
+            let name_len = bytes[i + 2..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                .count();
+            let close = i + 2 + name_len;
+            if name_len > 0 && bytes[close..].starts_with(b"}}") {
+                if raw_start < i {
+                    parts.push(Part::Raw(Span::new(raw_start, i)));
+                }
+                parts.push(Part::Param {
+                    name: template[i + 2..close].to_string(),
+                    span: Span::new(i, close + 2),
+                });
+                i = close + 2;
+                raw_start = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if raw_start < bytes.len() {
+        parts.push(Part::Raw(Span::new(raw_start, bytes.len())));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::PatternTemplate;
+    use crate::ErrorKind;
+
+    #[test]
+    fn values_are_escaped() {
+        let t = PatternTemplate::new("({{a}}|{{b}})-[0-9]+").unwrap();
+        let names: Vec<&str> = t.placeholders().collect();
+        assert_eq!(names, vec!["a", "b"]);
+
+        let values = [("a", "x|y"), ("b", "(z)")];
+        assert_eq!(t.expand(&values).unwrap(), r"(x\|y|\(z\))-[0-9]+");
+        let re = t.instantiate(&values).unwrap();
+        assert!(re.is_match("x|y-1"));
+        assert!(re.is_match("(z)-22"));
+        assert!(!re.is_match("x-1"));
+        assert!(!re.is_match("z-1"));
+        // 値がグループを足すことはない
+        assert_eq!(re.captures("(z)-2").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn repeated_placeholder_and_literal_braces() {
+        let t = PatternTemplate::new("{{w}}:{{w}}{{ x}}{{}}").unwrap();
+        assert_eq!(t.placeholders().count(), 2);
+        let re = t.instantiate(&[("w", "ab")]).unwrap();
+        assert!(re.is_match("ab:ab{{ x}}{{}}"));
+    }
+
+    #[test]
+    fn missing_value_points_at_placeholder() {
+        let t = PatternTemplate::new("a{{x}}b{{y}}").unwrap();
+        let e = t.instantiate(&[("x", "1")]).unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::UnboundPlaceholder, 7));
+    }
+
+    #[test]
+    fn syntax_errors_use_template_positions() {
+        let e = PatternTemplate::new("{{long_name}}(ab").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::UnbalancedParen, 13));
+
+        let e = PatternTemplate::new("x{{w}}+").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::QuantifiedPlaceholder, 6));
+        assert!(PatternTemplate::new("x({{w}})+").is_ok());
+        let e = PatternTemplate::new("{{w}}{2}").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::QuantifiedPlaceholder, 5));
+    }
+
+    #[test]
+    fn empty_value_error_maps_to_placeholder() {
+        // 空の値で `|` の片側が空になる。エンジンは閉じ括弧で気付くので、雛形でも `)` を指す
+        let t = PatternTemplate::new("(a|{{w}})").unwrap();
+        let e = t.instantiate(&[("w", "")]).unwrap_err();
+        assert_eq!(e.pos, 8);
+    }
+}
@@ -1,4 +1,19 @@
-use crate::error::{Error, ErrorKind, err};
+//! パターンの字句解析。
+//!
+//! [`tokenize_spanned`] はエンジン本体と同じ字句解析器を公開したもので、
+//! 各トークンにパターン中のバイト範囲が付く。リンタやハイライタ向け。
+//! 外に見せるのは [`TokenKind`] だけで、構文解析が内部で使うトークンは含まない。
+//!
+//! ```
+//! use my_regex::token::{TokenKind, tokenize_spanned};
+//! let ts = tokenize_spanned(r"a\d+").unwrap();
+//! assert_eq!(ts[0].kind, TokenKind::Char(b'a'));
+//! assert_eq!(ts[1].span.range(), 1..3); // \d
+//! assert_eq!(ts[2].kind, TokenKind::Plus);
+//! ```
+use crate::class::{ClassSet, Equivalences};
+use crate::error::{Error, ErrorKind, Feature, err};
+use crate::span::Span;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) enum Token {
@@ -6,15 +21,176 @@ pub(crate) enum Token {
     Dot,      // .
     LParen,   // (
     RParen,   // )
-    Alt,      // |
-    Star,     // *
-    Plus,     // +
-    Qmark,    // ?
-    Class { ranges: Vec<(u8, u8)>, neg: bool },
-    Concat, // implicit concatenation
+    // (?: / (?i-s: など、キャプチャしないグループの開き。フラグはトークンの区間から `group_flags` で読む
+    NonCapturing,
+    Alt,   // |
+    Star,  // *
+    Plus,  // +
+    Qmark, // ?
+    Class {
+        ranges: Vec<(u8, u8)>,
+        neg: bool,
+    },
+    Look(Look), // ゼロ幅アサーション
+    NamedClass {
+        name: String,
+        neg: bool,
+    }, // \p{name} / \P{name}（RegexBuilder::define_class で定義した集合を参照）
+    Repeat {
+        min: usize,
+        max: Option<usize>, // None は上限なし（{m,}）
+    }, // {m} / {m,} / {m,n}
 
+    // 以下は構文解析の途中で作られる内部用トークン（字句解析では出ない）
+    Concat, // implicit concatenation
     CapStart(usize),
     CapEnd(usize),
+    // 名前を解決した後の文字集合（1文字 = UTF-8 の1〜4バイト）
+    CodepointClass(ClassSet),
+}
+
+/// 内部用のトークンと、それが由来するパターン中のバイト範囲
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Lexeme {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// [`tokenize_spanned`] が返すトークンの種類。
+/// 構文を増やしたときに種類も増えるので `#[non_exhaustive]`。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TokenKind {
+    /// パターン先頭のフラグ `(?i)` / `(?ims)` など。立っているフラグは区間の文字から読む
+    Flags,
+    /// リテラルの1バイト（`\.` や `\n` などのエスケープも含む）
+    Char(u8),
+    /// `.`
+    Dot,
+    /// キャプチャするグループの開き。`(` / `(?P<name>` / `(?<name>`
+    LParen,
+    /// `)`
+    RParen,
+    /// キャプチャしないグループの開き。`(?:` / `(?i-s:` など
+    NonCapturing,
+    /// `|`
+    Alt,
+    /// `*`
+    Star,
+    /// `+`
+    Plus,
+    /// `?`
+    Qmark,
+    /// `[...]` や `\d` などのバイトクラス。`neg` は否定
+    Class { ranges: Vec<(u8, u8)>, neg: bool },
+    /// ゼロ幅アサーション（`^` / `$` / `\A` / `\z`）
+    Look(Look),
+    /// `\p{name}` / `\P{name}`
+    NamedClass { name: String, neg: bool },
+    /// `{m}` / `{m,}` / `{m,n}`。`max` が `None` なら上限なし
+    Repeat { min: usize, max: Option<usize> },
+}
+
+impl TokenKind {
+    fn from_token(token: Token) -> Self {
+        match token {
+            Token::Char(b) => TokenKind::Char(b),
+            Token::Dot => TokenKind::Dot,
+            Token::LParen => TokenKind::LParen,
+            Token::RParen => TokenKind::RParen,
+            Token::NonCapturing => TokenKind::NonCapturing,
+            Token::Alt => TokenKind::Alt,
+            Token::Star => TokenKind::Star,
+            Token::Plus => TokenKind::Plus,
+            Token::Qmark => TokenKind::Qmark,
+            Token::Class { ranges, neg } => TokenKind::Class { ranges, neg },
+            Token::Look(look) => TokenKind::Look(look),
+            Token::NamedClass { name, neg } => TokenKind::NamedClass { name, neg },
+            Token::Repeat { min, max } => TokenKind::Repeat { min, max },
+            Token::Concat | Token::CapStart(_) | Token::CapEnd(_) | Token::CodepointClass(_) => {
+                unreachable!("字句解析では出ないトークン")
+            }
+        }
+    }
+}
+
+/// トークンの種類と、それが由来するパターン中のバイト範囲
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpannedToken {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// ゼロ幅の位置アサーション（入力を消費しない）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Look {
+    WordBoundary, // \b 相当: 前後の「単語バイトかどうか」が異なる位置
+    LineStart,    // (?m) の ^: 入力の先頭か、\n の直後
+    LineEnd,      // (?m) の $: 入力の末尾か、\n の直前
+    TextStart,    // \A: 入力の先頭（探索範囲の先頭ではない）
+    TextEnd,      // \z: 入力の末尾（探索範囲の終端より後ろは無いものとして扱う）
+}
+
+// \w に入るバイトの表（[A-Za-z0-9_]）。境界判定はスレッドごとに呼ばれるので表引きにする
+static WORD_BYTE: [bool; 256] = {
+    let mut t = [false; 256];
+    let mut b = 0;
+    while b < 256 {
+        let c = b as u8;
+        t[b] = c.is_ascii_alphanumeric() || c == b'_';
+        b += 1;
+    }
+    t
+};
+
+/// \w と同じ判定（[A-Za-z0-9_]）
+pub(crate) fn is_word_byte(b: u8) -> bool {
+    WORD_BYTE[b as usize]
+}
+
+impl Look {
+    /// `pos` の位置（bytes[pos-1] と bytes[pos] の間）でアサーションが成り立つか
+    pub(crate) fn matches(self, bytes: &[u8], pos: usize) -> bool {
+        match self {
+            Look::WordBoundary => {
+                let before = pos > 0 && is_word_byte(bytes[pos - 1]);
+                let after = pos < bytes.len() && is_word_byte(bytes[pos]);
+                before != after
+            }
+            Look::LineStart => pos == 0 || bytes[pos - 1] == b'\n',
+            Look::LineEnd => pos == bytes.len() || bytes[pos] == b'\n',
+            Look::TextStart => pos == 0,
+            Look::TextEnd => pos == bytes.len(),
+        }
+    }
+}
+
+/// 1つの入力位置での Look の判定結果を覚えておく。
+/// ε閉包ではスレッドごとに同じ位置の同じアサーションを何度も問うので、最初の1回だけ計算する。
+#[derive(Debug, Default)]
+pub(crate) struct LookCache {
+    pos: usize,
+    word_boundary: Option<bool>,
+}
+
+impl LookCache {
+    pub(crate) fn matches(&mut self, look: Look, bytes: &[u8], pos: usize) -> bool {
+        if pos != self.pos {
+            *self = Self {
+                pos,
+                ..Self::default()
+            };
+        }
+        let slot = match look {
+            Look::WordBoundary => &mut self.word_boundary,
+            // 1バイト見るだけなので覚えるまでもない
+            Look::LineStart | Look::LineEnd | Look::TextStart | Look::TextEnd => {
+                return look.matches(bytes, pos);
+            }
+        };
+        *slot.get_or_insert_with(|| look.matches(bytes, pos))
+    }
 }
 
 // ===== Lexer =====
@@ -65,13 +241,43 @@ fn predefined_class(esc: u8) -> Option<(Vec<(u8, u8)>, bool)> {
 }
 
 // ===== Lexer =====
+#[cfg(test)] // 位置の要らないテスト用
 pub(crate) fn tokenize(pattern: &str) -> Result<Vec<Token>, Error> {
+    Ok(lex(pattern)?.into_iter().map(|t| t.token).collect())
+}
+
+/// パターンをトークン列に分解し、各トークンの元の位置を添えて返す。
+pub fn tokenize_spanned(pattern: &str) -> Result<Vec<SpannedToken>, Error> {
+    let tokens = lex(pattern)?;
+    let flags = match leading_flags(pattern).0 {
+        0 => None,
+        len => Some(SpannedToken {
+            kind: TokenKind::Flags,
+            span: Span::new(0, len),
+        }),
+    };
+    Ok(flags
+        .into_iter()
+        .chain(tokens.into_iter().map(|t| SpannedToken {
+            kind: TokenKind::from_token(t.token),
+            span: t.span,
+        }))
+        .collect())
+}
+
+// エンジン本体が使う字句解析。`tokenize_spanned` と同じ列を内部用のトークンで返す
+pub(crate) fn lex(pattern: &str) -> Result<Vec<Lexeme>, Error> {
     let bytes = pattern.as_bytes();
-    let mut i = 0;
+    // 先頭のフラグ (?im) は内部用のトークンにしない（コンパイル側で設定として読む）
+    let mut i = leading_flags(pattern).0;
     let n = bytes.len();
-    let mut out: Vec<Token> = Vec::new();
+    let mut spanned: Vec<Lexeme> = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
 
     while i < n {
+        let start = i;
+        // 各分岐は out にちょうど1トークン積む（位置はまとめて後で付ける）
+        let mut out: Vec<Token> = Vec::with_capacity(1);
         let c = bytes[i] as char;
         match c {
             '\\' => {
@@ -81,21 +287,39 @@ pub(crate) fn tokenize(pattern: &str) -> Result<Vec<Token>, Error> {
                 }
                 let esc = bytes[i];
 
-                // 追加: プリセットクラス
-                if let Some((ranges, neg)) = predefined_class(esc) {
-                    out.push(Token::Class { ranges, neg });
-                    i += 1;
+                if matches!(esc, b'p' | b'P')
+                    && let Some((name, close)) = class_name(bytes, i + 1)
+                {
+                    out.push(Token::NamedClass {
+                        name: name.to_string(),
+                        neg: esc == b'P',
+                    });
+                    i = close + 1;
+                    spanned.extend(out.into_iter().map(|token| Lexeme {
+                        token,
+                        span: Span::new(start, i),
+                    }));
                     continue;
                 }
+                if let Some(f) = unsupported_escape(esc) {
+                    return err(ErrorKind::UnsupportedFeature(f), start);
+                }
 
-                // 制御系のショートエスケープ
-                match esc {
-                    b't' => out.push(Token::Char(b'\t')),
-                    b'n' => out.push(Token::Char(b'\n')),
-                    b'r' => out.push(Token::Char(b'\r')),
-                    // ここで \. \* \+ \? \| \( \) \[ \] \\ などは
-                    // 「その文字をリテラルとして扱う」= Char でOK
-                    other => out.push(Token::Char(other)),
+                // 追加: プリセットクラス
+                if let Some((ranges, neg)) = predefined_class(esc) {
+                    out.push(Token::Class { ranges, neg });
+                } else {
+                    // 制御系のショートエスケープ
+                    match esc {
+                        b't' => out.push(Token::Char(b'\t')),
+                        b'n' => out.push(Token::Char(b'\n')),
+                        b'r' => out.push(Token::Char(b'\r')),
+                        b'A' => out.push(Token::Look(Look::TextStart)),
+                        b'z' => out.push(Token::Look(Look::TextEnd)),
+                        // ここで \. \* \+ \? \| \( \) \[ \] \\ などは
+                        // 「その文字をリテラルとして扱う」= Char でOK
+                        other => out.push(Token::Char(other)),
+                    }
                 }
                 i += 1;
             }
@@ -103,10 +327,36 @@ pub(crate) fn tokenize(pattern: &str) -> Result<Vec<Token>, Error> {
                 out.push(Token::Dot);
                 i += 1;
             }
-            '(' => {
-                out.push(Token::LParen);
+            // 複数行モードでなければ、コンパイル時に \A / \z と同じものに直す
+            '^' => {
+                out.push(Token::Look(Look::LineStart));
                 i += 1;
             }
+            '$' => {
+                out.push(Token::Look(Look::LineEnd));
+                i += 1;
+            }
+            '(' => {
+                // 名前付きグループも LParen 1つ。名前はトークンの区間から `group_name` で読む
+                if let Some(open) = flag_group_opener(&bytes[i + 1..]) {
+                    out.push(Token::NonCapturing);
+                    i += open;
+                } else if let Some(open) = named_group_opener(pattern, i)? {
+                    let name = group_name(&pattern[i..i + open]).unwrap_or_default();
+                    if names.contains(&name) {
+                        return err(ErrorKind::DuplicateGroupName, start);
+                    }
+                    names.push(name);
+                    out.push(Token::LParen);
+                    i += open;
+                } else {
+                    if let Some(f) = group_extension(&bytes[i + 1..]) {
+                        return err(ErrorKind::UnsupportedFeature(f), start);
+                    }
+                    out.push(Token::LParen);
+                    i += 1;
+                }
+            }
             ')' => {
                 out.push(Token::RParen);
                 i += 1;
@@ -127,6 +377,15 @@ pub(crate) fn tokenize(pattern: &str) -> Result<Vec<Token>, Error> {
                 out.push(Token::Qmark);
                 i += 1;
             }
+            '{' if is_counted_repetition(&bytes[i..]) => {
+                let close = i + bytes[i..].iter().position(|&b| b == b'}').unwrap_or(0);
+                let (min, max) = parse_repetition(&bytes[i + 1..close]);
+                if max.is_some_and(|max| max < min) {
+                    return err(ErrorKind::BadRepetition, start);
+                }
+                out.push(Token::Repeat { min, max });
+                i = close + 1;
+            }
             '[' => {
                 let (token, j) = parse_class(bytes, i + 1)?; // 既存
                 out.push(token);
@@ -137,8 +396,302 @@ pub(crate) fn tokenize(pattern: &str) -> Result<Vec<Token>, Error> {
                 i += 1;
             }
         }
+        spanned.extend(out.into_iter().map(|token| Lexeme {
+            token,
+            span: Span::new(start, i),
+        }));
+    }
+    Ok(spanned)
+}
+
+/// パターン中で特別な意味を持つ（または将来持ちうる）ASCII 文字
+fn is_meta(b: u8) -> bool {
+    matches!(
+        b,
+        b'\\'
+            | b'.'
+            | b'('
+            | b')'
+            | b'|'
+            | b'*'
+            | b'+'
+            | b'?'
+            | b'['
+            | b']'
+            | b'{'
+            | b'}'
+            | b'^'
+            | b'$'
+    )
+}
+
+/// 文字列をそのままの意味で一致するパターンに直す（メタ文字の前に `\` を付ける）。
+///
+/// ```
+/// use my_regex::{Regex, escape};
+/// assert_eq!(escape("1+1=2?"), r"1\+1=2\?");
+/// assert!(Regex::new(&escape("a.b(c)")).unwrap().is_match("a.b(c)"));
+/// ```
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_ascii() && is_meta(ch as u8) {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// `\p` の直後（位置 `i`）が `{name}` なら、名前と `}` の位置。名前は英数字と `_`。
+fn class_name(bytes: &[u8], i: usize) -> Option<(&str, usize)> {
+    if bytes.get(i) != Some(&b'{') {
+        return None;
+    }
+    let len = bytes[i + 1..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+        .count();
+    let close = i + 1 + len;
+    if len == 0 || bytes.get(close) != Some(&b'}') {
+        return None;
+    }
+    // ASCII だけなので UTF-8 として必ず正しい
+    let name = std::str::from_utf8(&bytes[i + 1..close]).ok()?;
+    Some((name, close))
+}
+
+/// `{` と `}` の間（`m` / `m,` / `m,n`）を (min, max) にする。
+/// 桁あふれは usize::MAX に丸める（上限の検査で弾かれる）。
+fn parse_repetition(body: &[u8]) -> (usize, Option<usize>) {
+    let num = |ds: &[u8]| {
+        ds.iter().fold(0usize, |n, d| {
+            n.saturating_mul(10).saturating_add((d - b'0') as usize)
+        })
+    };
+    let mut parts = body.splitn(2, |&b| b == b',');
+    let min = num(parts.next().unwrap_or_default());
+    let max = match parts.next() {
+        None => Some(min),
+        Some([]) => None,
+        Some(ds) => Some(num(ds)),
+    };
+    (min, max)
+}
+
+/// 他のエンジンでは意味を持つが、ここでは未対応のエスケープ
+fn unsupported_escape(esc: u8) -> Option<Feature> {
+    match esc {
+        b'1'..=b'9' => Some(Feature::Backreference),
+        b'b' | b'B' => Some(Feature::WordBoundary),
+        b'p' | b'P' => Some(Feature::UnicodeClass),
+        _ => None,
+    }
+}
+
+/// インラインフラグ。パターン先頭のものは全体に、`(?i:...)` のものはグループの中だけに効く
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct InlineFlags {
+    /// `i`: `RegexBuilder::case_insensitive(true)` と同じ
+    pub case_insensitive: bool,
+    /// `m`: `RegexBuilder::multi_line(true)` と同じ
+    pub multi_line: bool,
+    /// `s`: `RegexBuilder::dot_matches_new_line(true)` と同じ
+    pub dot_matches_new_line: bool,
+}
+
+impl InlineFlags {
+    // フラグ文字1つを yes にする。知らない文字なら false
+    fn set(&mut self, letter: u8, yes: bool) -> bool {
+        match letter {
+            b'i' => self.case_insensitive = yes,
+            b'm' => self.multi_line = yes,
+            b's' => self.dot_matches_new_line = yes,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// パターン先頭の `(?i)` / `(?m)` / `(?s)` / `(?ims)` などの長さと、その中身（無ければ 0 と既定値）。
+/// 先頭以外の位置や、それ以外のフラグは `Feature::InlineFlags` として拒否される。
+pub(crate) fn leading_flags(pattern: &str) -> (usize, InlineFlags) {
+    let mut flags = InlineFlags::default();
+    let Some(rest) = pattern.strip_prefix("(?") else {
+        return (0, flags);
+    };
+    for (k, b) in rest.bytes().enumerate() {
+        match b {
+            b')' if k > 0 => return (k + 3, flags),
+            _ if flags.set(b, true) => {}
+            _ => break,
+        }
+    }
+    (0, InlineFlags::default())
+}
+
+// `(?` と `:` の間（`im-s` の形）を、グループの外のフラグ `outer` に当てた結果にする
+fn apply_group_flags(spec: &[u8], outer: InlineFlags) -> Option<InlineFlags> {
+    let (on, off) = match spec.iter().position(|&b| b == b'-') {
+        Some(k) if k + 1 < spec.len() => (&spec[..k], &spec[k + 1..]),
+        Some(_) => return None,
+        None => (spec, &[][..]),
+    };
+    let mut flags = outer;
+    for &b in on {
+        flags.set(b, true).then_some(())?;
+    }
+    for &b in off {
+        flags.set(b, false).then_some(())?;
+    }
+    Some(flags)
+}
+
+/// `(` の直後（`rest`）が `?:` / `?i:` / `?-i:` / `?im-s:` の形なら、`(` を含めた開き部分のバイト長
+fn flag_group_opener(rest: &[u8]) -> Option<usize> {
+    let body = rest.strip_prefix(b"?")?;
+    let colon = body.iter().position(|&b| b == b':')?;
+    apply_group_flags(&body[..colon], InlineFlags::default())?;
+    Some(colon + 3)
+}
+
+/// `NonCapturing` トークンの区間の文字列（`(?i-s:` など）を、グループの外のフラグに当てた結果
+pub(crate) fn group_flags(open: &str, outer: InlineFlags) -> InlineFlags {
+    let spec = open
+        .strip_prefix("(?")
+        .and_then(|r| r.strip_suffix(':'))
+        .unwrap_or_default();
+    apply_group_flags(spec.as_bytes(), outer).unwrap_or(outer)
+}
+
+/// 各トークンの位置で効いているフラグ。`base` から始め、`(?i-s:...)` の中だけ書き換える。
+/// 括弧の対応はまだ検査されていないので、余った `)` では `base` に戻す
+pub(crate) fn scoped_flags(
+    tokens: &[Lexeme],
+    pattern: &str,
+    base: InlineFlags,
+) -> Vec<InlineFlags> {
+    let mut outer: Vec<InlineFlags> = Vec::new();
+    let mut cur = base;
+    tokens
+        .iter()
+        .map(|t| {
+            match t.token {
+                Token::LParen => outer.push(cur),
+                Token::NonCapturing => {
+                    outer.push(cur);
+                    cur = group_flags(t.span.slice(pattern), cur);
+                }
+                Token::RParen => cur = outer.pop().unwrap_or(base),
+                _ => {}
+            }
+            cur
+        })
+        .collect()
+}
+
+/// `(` の直後（`rest`）が `(?...)` 形式の未対応の拡張なら、その種類
+fn group_extension(rest: &[u8]) -> Option<Feature> {
+    if rest.first() != Some(&b'?') {
+        return None;
+    }
+    Some(match &rest[1..] {
+        [b'=' | b'!', ..] | [b'<', b'=' | b'!', ..] => Feature::Lookaround,
+        [b'P', b'=', ..] => Feature::Backreference,
+        [b':', ..] => Feature::NonCapturingGroup,
+        _ => Feature::InlineFlags,
+    })
+}
+
+/// `pattern[i]` の `(` が `(?P<name>` / `(?<name>` なら、その開き部分のバイト長。
+/// 名前は英字か `_` で始まり、英数字と `_` が続く。形が崩れていれば `ErrorKind::BadGroupName`
+fn named_group_opener(pattern: &str, i: usize) -> Result<Option<usize>, Error> {
+    let rest = &pattern.as_bytes()[i + 1..];
+    let prefix = match rest {
+        [b'?', b'P', b'<', ..] => 3,
+        [b'?', b'<', b'=' | b'!', ..] => return Ok(None),
+        [b'?', b'<', ..] => 2,
+        _ => return Ok(None),
+    };
+    let name_start = i + 1 + prefix;
+    let name = &pattern.as_bytes()[name_start..];
+    let len = name
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+        .count();
+    if len == 0 || name[0].is_ascii_digit() || name.get(len) != Some(&b'>') {
+        return err(ErrorKind::BadGroupName, name_start);
+    }
+    Ok(Some(1 + prefix + len + 1))
+}
+
+/// `LParen` トークンの区間の文字列（`(` や `(?<name>`）から、グループの名前
+pub(crate) fn group_name(open: &str) -> Option<&str> {
+    let rest = open
+        .strip_prefix("(?P<")
+        .or_else(|| open.strip_prefix("(?<"))?;
+    rest.strip_suffix('>')
+}
+
+/// `{m}` / `{m,}` / `{m,n}` の形をしているか（それ以外の `{` はリテラル）
+pub(crate) fn is_counted_repetition(rest: &[u8]) -> bool {
+    let Some(close) = rest.iter().position(|&b| b == b'}') else {
+        return false;
+    };
+    let body = &rest[1..close];
+    let mut parts = body.splitn(2, |&b| b == b',');
+    let min = parts.next().unwrap_or_default();
+    let max = parts.next();
+    !min.is_empty()
+        && min.iter().all(u8::is_ascii_digit)
+        && max.is_none_or(|m| m.iter().all(u8::is_ascii_digit))
+}
+
+/// 同一視テーブルをリテラルとクラスに適用する。
+/// 同一視される文字を持つ Char はクラスに、クラスは同一視で閉じてから否定を掛ける。
+#[cfg(test)] // 固定文字列判定のテスト用
+pub(crate) fn apply_equivalences(tokens: Vec<Token>, eq: &Equivalences) -> Vec<Token> {
+    tokens
+        .into_iter()
+        .map(|t| apply_equivalence(t, eq))
+        .collect()
+}
+
+/// `apply_equivalences` のトークン1つ分（フラグでトークンごとに表を選ぶ用）
+pub(crate) fn apply_equivalence(token: Token, eq: &Equivalences) -> Token {
+    if eq.is_empty() {
+        return token;
+    }
+    let to_ranges = |set: &ClassSet| -> Vec<(u8, u8)> {
+        set.ranges()
+            .iter()
+            .filter(|&&(lo, _)| lo <= 0xFF)
+            .map(|&(lo, hi)| (lo as u8, hi.min(0xFF) as u8))
+            .collect()
+    };
+    match token {
+        Token::Char(b) => {
+            let one = ClassSet::from_ranges([(b as u32, b as u32)]);
+            let closed = eq.close(&one);
+            if closed == one {
+                Token::Char(b)
+            } else {
+                Token::Class {
+                    ranges: to_ranges(&closed),
+                    neg: false,
+                }
+            }
+        }
+        Token::Class { ranges, neg } => {
+            let set = ClassSet::from_ranges(ranges.iter().map(|&(lo, hi)| (lo as u32, hi as u32)));
+            Token::Class {
+                ranges: to_ranges(&eq.close(&set)),
+                neg,
+            }
+        }
+        Token::CodepointClass(set) => Token::CodepointClass(eq.close(&set)),
+        other => other,
     }
-    Ok(out)
 }
 
 fn parse_class(bytes: &[u8], mut i: usize) -> Result<(Token, usize), Error> {
@@ -349,6 +902,242 @@ mod tests {
         );
     }
 
+    #[test]
+    fn word_boundary_look() {
+        let b = b"ab cd";
+        assert!(Look::WordBoundary.matches(b, 0));
+        assert!(!Look::WordBoundary.matches(b, 1));
+        assert!(Look::WordBoundary.matches(b, 2));
+        assert!(Look::WordBoundary.matches(b, 3));
+        assert!(Look::WordBoundary.matches(b, 5));
+        assert!(!Look::WordBoundary.matches(b"", 0));
+    }
+
+    #[test]
+    fn word_table_agrees_with_definition() {
+        for b in 0..=255u8 {
+            assert_eq!(
+                is_word_byte(b),
+                b.is_ascii_alphanumeric() || b == b'_',
+                "{b}"
+            );
+        }
+    }
+
+    #[test]
+    fn look_cache_follows_position() {
+        let bytes = b"ab cd";
+        let mut cache = LookCache::default();
+        for pos in [0, 0, 1, 2, 2, 3, 5, 1] {
+            assert_eq!(
+                cache.matches(Look::WordBoundary, bytes, pos),
+                Look::WordBoundary.matches(bytes, pos),
+                "{pos}"
+            );
+        }
+    }
+
+    #[test]
+    fn spanned_tokens_cover_pattern_text() {
+        let cases: [(&str, &[&str]); 4] = [
+            (
+                r"(a\d)+[^x-z]|\.",
+                &["(", "a", r"\d", ")", "+", "[^x-z]", "|", r"\."],
+            ),
+            (r"(?i)abc", &["(?i)", "a", "b", "c"]),
+            (r"(?ms)^.$", &["(?ms)", "^", ".", "$"]),
+            (r"(?i)", &["(?i)"]),
+        ];
+        for (pat, want) in cases {
+            let got = tokenize_spanned(pat).unwrap();
+            let spans: Vec<_> = got.iter().map(|t| t.span.slice(pat)).collect();
+            assert_eq!(spans, want, "{pat}");
+            // 区間は先頭から隙間なく連続する
+            assert_eq!(got[0].span.start, 0, "{pat}");
+            for w in got.windows(2) {
+                assert_eq!(w[0].span.end, w[1].span.start, "{pat}");
+            }
+            assert_eq!(got.last().unwrap().span.end, pat.len(), "{pat}");
+        }
+        let got = tokenize_spanned(r"(?i)abc").unwrap();
+        assert_eq!(got[0].kind, TokenKind::Flags);
+        assert_eq!(got[0].span.range(), 0..4);
+        assert_eq!(got[1].kind, TokenKind::Char(b'a'));
+    }
+
+    #[test]
+    fn named_groups_are_plain_parens() {
+        let pat = r"(?P<year>\d+)-(?<m_1>\d)(x)";
+        let got = tokenize_spanned(pat).unwrap();
+        let opens: Vec<&str> = got
+            .iter()
+            .filter(|t| t.kind == TokenKind::LParen)
+            .map(|t| t.span.slice(pat))
+            .collect();
+        assert_eq!(opens, vec!["(?P<year>", "(?<m_1>", "("]);
+        let names: Vec<Option<&str>> = opens.iter().map(|o| group_name(o)).collect();
+        assert_eq!(names, vec![Some("year"), Some("m_1"), None]);
+
+        let cases = [
+            (r"(?<>a)", ErrorKind::BadGroupName, 3),
+            (r"(?P<1a>a)", ErrorKind::BadGroupName, 4),
+            (r"x(?<a-b>a)", ErrorKind::BadGroupName, 4),
+            (r"(?<abc", ErrorKind::BadGroupName, 3),
+            (r"(?<a>x)(?P<a>y)", ErrorKind::DuplicateGroupName, 7),
+        ];
+        for (pat, kind, pos) in cases {
+            let e = tokenize(pat).unwrap_err();
+            assert_eq!((e.kind, e.pos), (kind, pos), "{pat}");
+        }
+    }
+
+    #[test]
+    fn unsupported_syntax_is_reported_with_feature() {
+        let cases = [
+            (r"a(?=b)", Feature::Lookaround, 1),
+            (r"(?<!x)y", Feature::Lookaround, 0),
+            (r"(?P=n)", Feature::Backreference, 0),
+            (r"(?x:ab)", Feature::InlineFlags, 0),
+            (r"a(?i-:b)", Feature::InlineFlags, 1),
+            (r"(?x)ab", Feature::InlineFlags, 0),
+            (r"(?)ab", Feature::InlineFlags, 0),
+            (r"a(?i)b", Feature::InlineFlags, 1),
+            (r"(?i)(?i)b", Feature::InlineFlags, 4),
+            (r"(a)\1", Feature::Backreference, 3),
+            (r"\bword", Feature::WordBoundary, 0),
+            (r"\pL", Feature::UnicodeClass, 0),
+            (r"\p{}", Feature::UnicodeClass, 0),
+        ];
+        for (pat, feature, pos) in cases {
+            let e = tokenize(pat).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::UnsupportedFeature(feature), "{pat}");
+            assert_eq!(e.pos, pos, "{pat}");
+        }
+    }
+
+    #[test]
+    fn text_anchor_tokens() {
+        assert_eq!(
+            tokenize(r"\Aa\z").unwrap(),
+            vec![
+                Token::Look(Look::TextStart),
+                Token::Char(b'a'),
+                Token::Look(Look::TextEnd),
+            ]
+        );
+        // \Z（末尾の改行を許す版）は無い。他の英字と同じく文字そのもの
+        assert_eq!(tokenize(r"\Z").unwrap(), vec![Token::Char(b'Z')]);
+        // ^ / $ は行の先頭・末尾。クラスの中やエスケープしたものは文字
+        assert_eq!(
+            tokenize(r"^[$^]\^$").unwrap(),
+            vec![
+                Token::Look(Look::LineStart),
+                Token::Class {
+                    ranges: vec![(b'$', b'$'), (b'^', b'^')],
+                    neg: false
+                },
+                Token::Char(b'^'),
+                Token::Look(Look::LineEnd),
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_inline_flags() {
+        let flags = |i, m, s| InlineFlags {
+            case_insensitive: i,
+            multi_line: m,
+            dot_matches_new_line: s,
+        };
+        assert_eq!(leading_flags("(?i)a"), (4, flags(true, false, false)));
+        assert_eq!(leading_flags("(?mi)a"), (5, flags(true, true, false)));
+        assert_eq!(leading_flags("(?m)"), (4, flags(false, true, false)));
+        assert_eq!(leading_flags("(?s)."), (4, flags(false, false, true)));
+        for pat in ["a(?i)", "(?)a", "(?x)a", "(?i", "(a)"] {
+            assert_eq!(leading_flags(pat), (0, InlineFlags::default()), "{pat}");
+        }
+        assert_eq!(tokenize("(?im)a").unwrap(), vec![Token::Char(b'a')]);
+    }
+
+    #[test]
+    fn scoped_flag_groups() {
+        let pat = "a(?i:b(?-i:c)(d))(?s-m:)e";
+        let ts = lex(pat).unwrap();
+        assert_eq!(ts[1].token, Token::NonCapturing);
+        assert_eq!(ts[1].span.slice(pat), "(?i:");
+        assert_eq!(ts[3].span.slice(pat), "(?-i:");
+        assert_eq!(ts[10].span.slice(pat), "(?s-m:");
+
+        let base = InlineFlags {
+            multi_line: true,
+            ..InlineFlags::default()
+        };
+        let ci: Vec<bool> = scoped_flags(&ts, pat, base)
+            .iter()
+            .map(|f| f.case_insensitive)
+            .collect();
+        // a ( b ( c ) ( d ) ) ( ) e
+        assert_eq!(
+            ci,
+            [
+                false, true, true, false, false, true, true, true, true, false, false, false, false
+            ]
+        );
+        let inner = scoped_flags(&ts, pat, base)[10];
+        assert!(inner.dot_matches_new_line && !inner.multi_line);
+        assert_eq!(group_flags("(?:", base), base);
+    }
+
+    #[test]
+    fn named_class_tokens() {
+        let got = tokenize_spanned(r"a\p{kana_1}\P{x}").unwrap();
+        assert_eq!(
+            got[1].kind,
+            TokenKind::NamedClass {
+                name: "kana_1".into(),
+                neg: false
+            }
+        );
+        assert_eq!(got[1].span, Span::new(1, 11));
+        assert_eq!(
+            got[2].kind,
+            TokenKind::NamedClass {
+                name: "x".into(),
+                neg: true
+            }
+        );
+    }
+
+    #[test]
+    fn counted_repetition_tokens() {
+        let rep = |min, max| Token::Repeat { min, max };
+        assert_eq!(
+            tokenize("a{2}b{2,}c{0,3}").unwrap(),
+            vec![
+                Token::Char(b'a'),
+                rep(2, Some(2)),
+                Token::Char(b'b'),
+                rep(2, None),
+                Token::Char(b'c'),
+                rep(0, Some(3)),
+            ]
+        );
+        assert_eq!(
+            tokenize("a{99999999999999999999999}").unwrap()[1],
+            rep(usize::MAX, Some(usize::MAX))
+        );
+        let e = tokenize("xa{3,2}").unwrap_err();
+        assert_eq!((e.kind, e.pos), (ErrorKind::BadRepetition, 2));
+    }
+
+    #[test]
+    fn braces_that_are_not_repetition_stay_literal() {
+        for pat in ["{", "a{", "a{}", "a{x}", "a{,3}", "{a,b}"] {
+            let got = tokenize(pat).unwrap();
+            assert!(got.iter().all(|t| matches!(t, Token::Char(_))), "{pat}");
+        }
+    }
+
     #[test]
     fn trailing_backslash_is_error() {
         let err = tokenize("\\").unwrap_err();
@@ -0,0 +1,220 @@
+// validate.rs
+//! 入力全体がパターンに合うか調べ、合わなければどこで外れたか・そこで何が来るべきだったかを返す
+//! （フォームの入力検査でそのままメッセージにできるように）。
+use std::fmt;
+
+use crate::class::ByteSet;
+use crate::{Captures, Regex, Thread};
+
+/// `Regex::validate` の失敗。外れた位置と、その位置で読めたはずのバイトのクラス
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    position: usize,
+    expected: Vec<Vec<(u8, u8)>>,
+    expects_end: bool,
+    found: Option<u8>,
+}
+
+impl ValidationError {
+    /// パターンに沿って読めた最長の先頭部分の長さ（= 最初に外れたバイト位置）。
+    /// 入力が途中で終わったなら入力の長さ
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// `position` で生きていた NFA 状態が読めたバイトのクラス。
+    /// 1つのクラスはバイト範囲 (lo, hi) の列で、重複を除いて小さい順に並ぶ
+    pub fn expected(&self) -> &[Vec<(u8, u8)>] {
+        &self.expected
+    }
+
+    /// `position` で入力が終わっていれば一致していたか（余計な入力が続いている場合）
+    pub fn expects_end(&self) -> bool {
+        self.expects_end
+    }
+
+    /// `expected` の和集合。重なりや隣接はまとめて、小さい順に並ぶ
+    pub fn expected_bytes(&self) -> Vec<(u8, u8)> {
+        let mut set = ByteSet::empty();
+        for &(lo, hi) in self.expected.iter().flatten() {
+            set.insert_range(lo, hi);
+        }
+        set.ranges()
+    }
+
+    /// `position` にあったバイト。入力が途中で終わったなら None
+    pub fn found(&self) -> Option<u8> {
+        self.found
+    }
+}
+
+/// `expected digit or '-', found 'x' at byte 2` の形
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut items: Vec<String> = self
+            .expected_bytes()
+            .into_iter()
+            .map(|(lo, hi)| describe_range(lo, hi))
+            .collect();
+        if self.expects_end {
+            items.push("end of input".to_string());
+        }
+        match items.split_last() {
+            None => f.write_str("unexpected ")?,
+            Some((last, [])) => write!(f, "expected {last}, found ")?,
+            Some((last, rest)) => write!(f, "expected {} or {last}, found ", rest.join(", "))?,
+        }
+        match self.found {
+            Some(b) => write!(f, "{}", describe_byte(b))?,
+            None => f.write_str("end of input")?,
+        }
+        write!(f, " at byte {}", self.position)
+    }
+}
+
+// 名前のあるよく使う範囲は名前で、それ以外は文字（範囲）で書く
+fn describe_range(lo: u8, hi: u8) -> String {
+    match (lo, hi) {
+        (b'0', b'9') => "digit".to_string(),
+        (b'a', b'z') => "lowercase letter".to_string(),
+        (b'A', b'Z') => "uppercase letter".to_string(),
+        _ if lo == hi => describe_byte(lo),
+        _ => format!("{}..={}", describe_byte(lo), describe_byte(hi)),
+    }
+}
+
+// 表示できる ASCII は 'x'、それ以外は 0xNN
+fn describe_byte(b: u8) -> String {
+    if b.is_ascii_graphic() || b == b' ' {
+        format!("{:?}", b as char)
+    } else {
+        format!("0x{b:02X}")
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Regex {
+    /// 入力全体が一致すればキャプチャを、しなければ外れた位置と期待されたクラスを返す。
+    ///
+    /// ```
+    /// use my_regex::Regex;
+    /// let re = Regex::new(r"(\d+)-(\d+)").unwrap();
+    /// assert_eq!(&re.validate("12-34").unwrap()[2], "34");
+    /// let err = re.validate("12x34").unwrap_err();
+    /// assert_eq!(err.position(), 2);
+    /// assert_eq!(err.expected(), [vec![(b'-', b'-')], vec![(b'0', b'9')]]);
+    /// ```
+    ///
+    /// # Panics
+    /// `captures` と同じく、`RegexBuilder::thread_limit` などの上限を超えた場合。
+    pub fn validate<'h>(&self, hay: &'h str) -> Result<Captures<'h>, ValidationError> {
+        if let Some(caps) = self.captures(hay) {
+            return Ok(caps);
+        }
+        let bytes = hay.as_bytes();
+        let (position, live) = self.failure_point(bytes);
+        let mut sets: Vec<ByteSet> = Vec::new();
+        for t in &live {
+            for (lbl, _) in &self.states[t.s].edges {
+                if let Some(bs) = lbl.byte_set()
+                    && !sets.contains(&bs)
+                {
+                    sets.push(bs);
+                }
+            }
+        }
+        let mut expected: Vec<Vec<(u8, u8)>> = sets.iter().map(ByteSet::ranges).collect();
+        expected.sort();
+        Err(ValidationError {
+            position,
+            expected,
+            expects_end: live.iter().any(|t| t.s == self.accept),
+            found: bytes.get(position).copied(),
+        })
+    }
+
+    // 先頭から読めるところまで読み、(止まった位置, そこでの ε 閉包済みスレッド) を返す
+    fn failure_point(&self, bytes: &[u8]) -> (usize, Vec<Thread>) {
+        let mut curr = vec![Thread {
+            s: self.start,
+            caps: Vec::new(),
+        }];
+        for (i, &b) in bytes.iter().enumerate() {
+            self.eps_closure(&mut curr, bytes, i);
+            let next = self.step_byte(&curr, b);
+            if next.is_empty() {
+                return (i, curr);
+            }
+            curr = next;
+        }
+        self.eps_closure(&mut curr, bytes, bytes.len());
+        (bytes.len(), curr)
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use crate::Regex;
+
+    #[test]
+    fn reports_first_failing_byte() {
+        let re = Regex::new(r"[a-z]+@[a-z]+\.com").unwrap();
+        assert!(re.validate("me@host.com").is_ok());
+
+        let err = re.validate("me@host.org").unwrap_err();
+        assert_eq!(err.position(), 8);
+        assert_eq!(err.expected(), [vec![(b'c', b'c')]]);
+        assert!(!err.expects_end());
+        assert_eq!(err.found(), Some(b'o'));
+    }
+
+    #[test]
+    fn short_and_long_inputs() {
+        let re = Regex::new(r"\d\d").unwrap();
+        // 途中で終わった
+        let err = re.validate("1").unwrap_err();
+        assert_eq!((err.position(), err.expects_end()), (1, false));
+        assert_eq!(err.expected(), [vec![(b'0', b'9')]]);
+        // 余計な入力が続いた
+        let err = re.validate("123").unwrap_err();
+        assert_eq!((err.position(), err.expects_end()), (2, true));
+        assert!(err.expected().is_empty());
+    }
+
+    #[test]
+    fn expected_bytes_are_merged_across_classes() {
+        let re = Regex::new(r"-?([0-9a-c]+|[c-f_]x)").unwrap();
+        let err = re.validate("-g").unwrap_err();
+        assert_eq!(err.expected().len(), 2);
+        assert_eq!(
+            err.expected_bytes(),
+            vec![(b'0', b'9'), (b'_', b'_'), (b'a', b'f')]
+        );
+        assert_eq!(err.found(), Some(b'g'));
+    }
+
+    #[test]
+    fn messages() {
+        let msg = |p: &str, hay: &str| {
+            Regex::new(p)
+                .unwrap()
+                .validate(hay)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            msg(r"\d+(-\d+)?", "12x"),
+            "expected '-', digit or end of input, found 'x' at byte 2"
+        );
+        assert_eq!(
+            msg(r"[a-z][A-Z]", "a"),
+            "expected uppercase letter, found end of input at byte 1"
+        );
+        assert_eq!(msg(r"[a-f]é", "aë"), "expected 0xA9, found 0xAB at byte 2");
+        assert_eq!(
+            msg("ab", "abc"),
+            "expected end of input, found 'c' at byte 2"
+        );
+    }
+}